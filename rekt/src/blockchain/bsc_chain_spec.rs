@@ -1,11 +1,15 @@
+use ethers::types::Address;
 use hex_literal::hex;
 use once_cell::sync::Lazy;
 use std::collections::BTreeMap;
+use std::str::FromStr;
 
+use crate::constants::BOOTSTRAP_NODES;
+use crate::contracts::caesar_bot::CAESAR_BOT_ADDRESS;
+use crate::public_nodes::nodes::PUBLIC_NODE_URLS;
 use crate::types::hash::H256;
 
-use super::chain_spec::ChainSpec;
-use super::fork::{ForkFilter, ForkId};
+use super::chain_spec::{ChainSpec, Routers};
 use super::fork_condition::ForkCondition;
 use super::hard_fork::Hardfork;
 
@@ -59,10 +63,18 @@ pub static BSC_MAINNET: Lazy<ChainSpec> = Lazy::new(|| {
             (Hardfork::Cancun, ForkCondition::Timestamp(1718863500)),
             (Hardfork::Haber, ForkCondition::Timestamp(1718863500)),
         ]),
+        bootnodes: BOOTSTRAP_NODES.iter().map(ToString::to_string).collect(),
+        enrtrees: vec![],
+        routers: Routers {
+            v2_router: address("0x10ED43C718714eb63d5aA57B78B54704E256024E"),
+            v3_position_manager: address("0x46A15B0b27311cedF172AB29E4f4766fbE7F4364"),
+            v3_factory: address("0x0BFbCF9fa4f9C56B0F40a671Ad40E0805A091865"),
+        },
+        caesar_bot: address(CAESAR_BOT_ADDRESS),
+        public_nodes: PUBLIC_NODE_URLS.iter().map(ToString::to_string).collect(),
     }
 });
 
-pub static BSC_MAINNET_FORK_ID: Lazy<ForkId> = Lazy::new(|| BSC_MAINNET.fork_id(&BSC_MAINNET.head));
-
-pub static BSC_MAINNET_FORK_FILTER: Lazy<ForkFilter> =
-    Lazy::new(|| BSC_MAINNET.fork_filter(BSC_MAINNET.head));
+pub(super) fn address(address: &str) -> Address {
+    Address::from_str(address).expect("Invalid contract address")
+}
@@ -1,15 +1,48 @@
 use std::collections::BTreeMap;
+use std::io;
+
+use ethers::types::Address;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
 
 use crate::types::hash::H256;
 
-use super::fork::{ForkFilter, ForkFilterKey, ForkHash, ForkId};
+use super::fork::{ForkFilter, ForkFilterKey, ForkId};
 use super::fork_condition::ForkCondition;
 use super::hard_fork::Hardfork;
 use super::head::Head;
+use super::{BSC_MAINNET, BSC_TESTNET};
+
+static CHAIN_SPEC: OnceCell<ChainSpec> = OnceCell::new();
+
+/// Selects the chain, must be called before anything reads the spec.
+/// Fails if the spec was already initialized, eg. read with [chain_spec] before this call
+pub fn init_chain_spec(spec: ChainSpec) -> Result<(), io::Error> {
+    CHAIN_SPEC.set(spec).map_err(|spec| {
+        io::Error::other(format!(
+            "Chain spec is already initialized, can't select chain {}",
+            spec.chain
+        ))
+    })?;
+
+    let spec = chain_spec();
+    println!(
+        "Chain spec initialized, chain id: {}, bootnodes: {}",
+        spec.chain,
+        spec.bootnodes.len()
+    );
+    Ok(())
+}
+
+/// Selected chain, BSC mainnet if none was selected
+pub fn chain_spec() -> &'static ChainSpec {
+    CHAIN_SPEC.get_or_init(|| BSC_MAINNET.clone())
+}
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChainSpec {
     /// The chain ID
-    pub chain: u8,
+    pub chain: u64,
 
     /// The hash of the genesis block.
     ///
@@ -25,9 +58,45 @@ pub struct ChainSpec {
 
     // Blockchain head
     pub head: Head,
+
+    /// Enodes used to bootstrap discovery and outbound connections
+    #[serde(default)]
+    pub bootnodes: Vec<String>,
+
+    /// EIP-1459 node lists (`enrtree://<key>@<domain>`) resolved at startup next to bootnodes
+    #[serde(default)]
+    pub enrtrees: Vec<String>,
+
+    #[serde(default)]
+    pub routers: Routers,
+
+    /// Our bot contract, zero address if it's not deployed on the chain
+    #[serde(default)]
+    pub caesar_bot: Address,
+
+    /// JSON-RPC endpoints used for nonces and balances
+    #[serde(default)]
+    pub public_nodes: Vec<String>,
+}
+
+/// DEX contracts used by liquidity triggers, zero address if not deployed on the chain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Routers {
+    pub v2_router: Address,
+    pub v3_position_manager: Address,
+    pub v3_factory: Address,
 }
 
 impl ChainSpec {
+    /// Known chain by its name
+    pub fn by_name(name: &str) -> Option<ChainSpec> {
+        match name {
+            "bsc" => Some(BSC_MAINNET.clone()),
+            "chapel" => Some(BSC_TESTNET.clone()),
+            _ => None,
+        }
+    }
+
     /// Returns the forks in this specification and their activation conditions.
     pub fn hardforks(&self) -> &BTreeMap<Hardfork, ForkCondition> {
         &self.hardforks
@@ -64,36 +133,68 @@ impl ChainSpec {
         ForkFilter::new(head, self.genesis_hash, forks)
     }
 
+    /// Forks are applied ordered by block number and then by timestamp (as in the fork filter),
+    /// not by hardfork order, since testnets activated some forks in a different order
     pub fn fork_id(&self, head: &Head) -> ForkId {
-        let mut curr_forkhash = ForkHash::from(self.genesis_hash);
-        let mut current_applied_value = 0;
+        self.fork_filter(*head).current()
+    }
+}
 
-        for (_, cond) in self.forks_iter() {
-            let value = match cond {
-                ForkCondition::Block(block) => block,
-                ForkCondition::Timestamp(time) => time,
-                ForkCondition::TTD {
-                    fork_block: Some(block),
-                    ..
-                } => block,
-                _ => continue,
-            };
-
-            if cond.active_at_head(head) {
-                if value != current_applied_value {
-                    curr_forkhash += value;
-                    current_applied_value = value;
-                }
-            } else {
-                return ForkId {
-                    hash: curr_forkhash,
-                    next: value,
-                };
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blockchain::fork::ForkHash;
+
+    #[test]
+    fn chain_spec_from_toml() {
+        let spec: ChainSpec = toml::from_str(
+            r#"
+            chain = 1
+            genesis_hash = "0xd4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+            td = 17179869184
+            bootnodes = ["enode://d860a01f9722d78051619d1e2351aba3f43f943f6f00718d1b9baa4101932a1f5011f16bb2b1bb35db20d6fe28fa0bf09636d26a87d31de9ec6203eeedb1f666@18.138.108.67:30303"]
+
+            [head]
+            number = 0
+            hash = "0xd4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+            difficulty = 17179869184
+            total_difficulty = 17179869184
+            timestamp = 0
+
+            [hardforks]
+            Frontier = { Block = 0 }
+            Homestead = { Block = 1150000 }
+            Paris = { TTD = { total_difficulty = 0 } }
+            Shanghai = { Timestamp = 1681338455 }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(spec.chain, 1);
+        assert_eq!(spec.bootnodes.len(), 1);
+        assert_eq!(spec.routers, Routers::default());
+        assert_eq!(
+            spec.fork(Hardfork::Homestead),
+            ForkCondition::Block(1150000)
+        );
+        assert_eq!(spec.fork(Hardfork::Cancun), ForkCondition::Never);
+        // mainnet genesis fork id from EIP-2124
+        assert_eq!(
+            spec.fork_id(&spec.head),
+            ForkId {
+                hash: ForkHash([0xfc, 0x64, 0xec, 0x04]),
+                next: 1150000
             }
-        }
-        ForkId {
-            hash: curr_forkhash,
-            next: 0,
-        }
+        );
+    }
+
+    #[test]
+    fn bsc_is_the_default_chain() {
+        assert_eq!(ChainSpec::by_name("bsc").as_ref(), Some(&*BSC_MAINNET));
+        assert_eq!(chain_spec().chain, 56);
+        assert_eq!(ChainSpec::by_name("unknown"), None);
+        // spec can't be switched once something has read it
+        assert!(init_chain_spec(BSC_TESTNET.clone()).is_err());
+        assert_eq!(chain_spec().chain, 56);
     }
 }
@@ -0,0 +1,195 @@
+use ethers::types::Address;
+use hex_literal::hex;
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+
+use crate::types::hash::H256;
+
+use super::bsc_chain_spec::address;
+use super::chain_spec::{ChainSpec, Routers};
+use super::fork_condition::ForkCondition;
+use super::hard_fork::Hardfork;
+
+/// BSC testnet, used for dry runs before mainnet launches.
+/// There are no default bootnodes, testnet peers should be set in config `nodes`,
+/// and Caesar bot deployed on testnet via config `caesar_bot`
+pub static BSC_TESTNET: Lazy<ChainSpec> = Lazy::new(|| {
+    let genesis_hash = H256(hex!(
+        "6d3c66c5357ec91d5c43af47e234a939b22557cbb552dc45bebbceeed90fbe34"
+    ));
+    ChainSpec {
+        chain: 97,
+        td: 1,
+        genesis_hash,
+        head: super::head::Head {
+            number: 0,
+            hash: genesis_hash,
+            difficulty: 1,
+            total_difficulty: 1,
+            timestamp: 1587390414,
+        },
+        hardforks: BTreeMap::from([
+            (Hardfork::Homestead, ForkCondition::Block(0)),
+            (Hardfork::Eip150, ForkCondition::Block(0)),
+            (Hardfork::Eip155, ForkCondition::Block(0)),
+            (Hardfork::Eip158, ForkCondition::Block(0)),
+            (Hardfork::Byzantium, ForkCondition::Block(0)),
+            (Hardfork::Constantinople, ForkCondition::Block(0)),
+            (Hardfork::Petersburg, ForkCondition::Block(0)),
+            (Hardfork::Istanbul, ForkCondition::Block(0)),
+            (Hardfork::MuirGlacier, ForkCondition::Block(0)),
+            (Hardfork::Ramanujan, ForkCondition::Block(1010000)),
+            (Hardfork::Niels, ForkCondition::Block(1014369)),
+            (Hardfork::MirrorSync, ForkCondition::Block(5582500)),
+            (Hardfork::Bruno, ForkCondition::Block(13837000)),
+            (Hardfork::Euler, ForkCondition::Block(19203503)),
+            (Hardfork::Gibbs, ForkCondition::Block(22800220)),
+            (Hardfork::Nano, ForkCondition::Block(23482428)),
+            (Hardfork::Moran, ForkCondition::Block(23603940)),
+            (Hardfork::Planck, ForkCondition::Block(28196022)),
+            (Hardfork::Luban, ForkCondition::Block(29295050)),
+            (Hardfork::Plato, ForkCondition::Block(29861024)),
+            // Same as on mainnet, Berlin and London are enabled together with Hertz
+            (Hardfork::Berlin, ForkCondition::Block(31103030)),
+            (Hardfork::London, ForkCondition::Block(31103030)),
+            (Hardfork::Hertz, ForkCondition::Block(31103030)),
+            (Hardfork::HertzFix, ForkCondition::Block(35682300)),
+            (Hardfork::Shanghai, ForkCondition::Timestamp(1702972800)),
+            (Hardfork::Keppler, ForkCondition::Timestamp(1702972800)),
+            (Hardfork::Feynman, ForkCondition::Timestamp(1710136800)),
+            (Hardfork::FeynmanFix, ForkCondition::Timestamp(1711342800)),
+            (Hardfork::Cancun, ForkCondition::Timestamp(1713330442)),
+            (Hardfork::Haber, ForkCondition::Timestamp(1716962820)),
+        ]),
+        bootnodes: vec![],
+        enrtrees: vec![],
+        routers: Routers {
+            v2_router: address("0xD99D1c33F9fC3444f8101754aBC46c52416550D1"),
+            v3_position_manager: address("0x427bF5b37357632377eCbEC9de3626C71A5396c1"),
+            v3_factory: address("0x0BFbCF9fa4f9C56B0F40a671Ad40E0805A091865"),
+        },
+        caesar_bot: Address::zero(),
+        public_nodes: vec![
+            "https://data-seed-prebsc-1-s1.binance.org:8545/".to_string(),
+            "https://data-seed-prebsc-2-s1.binance.org:8545/".to_string(),
+        ],
+    }
+});
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blockchain::fork::{ForkHash, ForkId, ValidationError};
+    use crate::blockchain::head::Head;
+
+    fn head(number: u64, timestamp: u64) -> Head {
+        Head {
+            number,
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    /// Fork ids on every transition of bsc `params.ChapelChainConfig`, each hash is CRC32 of the
+    /// genesis hash extended with big endian fork blocks and timestamps, as in EIP-2124
+    #[test]
+    fn chapel_fork_ids() {
+        let vectors = [
+            (head(0, 0), hex!("216c4fbd"), 1010000),
+            (head(1009999, 0), hex!("216c4fbd"), 1010000),
+            (head(1010000, 0), hex!("cd0d163d"), 1014369),
+            (head(1014368, 0), hex!("cd0d163d"), 1014369),
+            (head(1014369, 0), hex!("36c6880a"), 5582500),
+            (head(5582499, 0), hex!("36c6880a"), 5582500),
+            (head(5582500, 0), hex!("1600553d"), 13837000),
+            (head(13836999, 0), hex!("1600553d"), 13837000),
+            (head(13837000, 0), hex!("5865ba3c"), 19203503),
+            (head(19203502, 0), hex!("5865ba3c"), 19203503),
+            (head(19203503, 0), hex!("de3b3a04"), 22800220),
+            (head(22800219, 0), hex!("de3b3a04"), 22800220),
+            (head(22800220, 0), hex!("1eb4c319"), 23482428),
+            (head(23482427, 0), hex!("1eb4c319"), 23482428),
+            (head(23482428, 0), hex!("c49af9db"), 23603940),
+            (head(23603939, 0), hex!("c49af9db"), 23603940),
+            (head(23603940, 0), hex!("59afa6a3"), 28196022),
+            (head(28196021, 0), hex!("59afa6a3"), 28196022),
+            (head(28196022, 0), hex!("8279af9a"), 29295050),
+            (head(29295049, 0), hex!("8279af9a"), 29295050),
+            (head(29295050, 0), hex!("999eaf8b"), 29861024),
+            (head(29861023, 0), hex!("999eaf8b"), 29861024),
+            (head(29861024, 0), hex!("110bea95"), 31103030),
+            (head(31103029, 0), hex!("110bea95"), 31103030),
+            (head(31103030, 0), hex!("dc55905c"), 35682300),
+            (head(35682299, 0), hex!("dc55905c"), 35682300),
+            (head(35682300, 0), hex!("de149bad"), 1702972800),
+            (head(35682300, 1702972799), hex!("de149bad"), 1702972800),
+            (head(35682300, 1702972800), hex!("53474aa9"), 1710136800),
+            (head(35682300, 1710136799), hex!("53474aa9"), 1710136800),
+            (head(35682300, 1710136800), hex!("96d46a82"), 1711342800),
+            (head(35682300, 1711342799), hex!("96d46a82"), 1711342800),
+            (head(35682300, 1711342800), hex!("712317d4"), 1713330442),
+            (head(35682300, 1713330441), hex!("712317d4"), 1713330442),
+            (head(35682300, 1713330442), hex!("821df8b9"), 1716962820),
+            (head(35682300, 1716962819), hex!("821df8b9"), 1716962820),
+            (head(35682300, 1716962820), hex!("63d5dae0"), 0),
+            (head(36682300, 2000000000), hex!("63d5dae0"), 0),
+        ];
+
+        for (head, hash, next) in vectors {
+            assert_eq!(
+                BSC_TESTNET.fork_id(&head),
+                ForkId {
+                    hash: ForkHash(hash),
+                    next
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn chapel_compatibility_check() {
+        let mut filter = BSC_TESTNET.fork_filter(head(35682300, 1713330442));
+
+        // Local is at Cancun, remote announces the same and knows about Haber
+        assert_eq!(
+            filter.validate(ForkId {
+                hash: ForkHash(hex!("821df8b9")),
+                next: 1716962820
+            }),
+            Ok(())
+        );
+
+        // Local is at Cancun, remote is at FeynmanFix and knows about Cancun, it's out of sync
+        assert_eq!(
+            filter.validate(ForkId {
+                hash: ForkHash(hex!("712317d4")),
+                next: 1713330442
+            }),
+            Ok(())
+        );
+
+        // Local is at Haber, remote is at Cancun and doesn't know about Haber
+        filter.set_head(head(35682300, 1716962820));
+        assert_eq!(
+            filter.validate(ForkId {
+                hash: ForkHash(hex!("821df8b9")),
+                next: 0
+            }),
+            Err(ValidationError::RemoteStale {
+                local: ForkId {
+                    hash: ForkHash(hex!("63d5dae0")),
+                    next: 0
+                },
+                remote: ForkId {
+                    hash: ForkHash(hex!("821df8b9")),
+                    next: 0
+                }
+            })
+        );
+
+        // Mainnet peer is rejected
+        assert!(filter
+            .validate(crate::blockchain::BSC_MAINNET.fork_id(&crate::blockchain::BSC_MAINNET.head))
+            .is_err());
+    }
+}
@@ -75,6 +75,7 @@ pub enum Hardfork {
     FeynmanFix,
     Cancun,
     Haber,
+    Prague,
 }
 
 impl Hardfork {
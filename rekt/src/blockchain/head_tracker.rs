@@ -0,0 +1,609 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use tokio::sync::watch;
+
+use crate::types::hash::{H256, H512};
+
+use super::chain_spec::{chain_spec, ChainSpec};
+use super::fork::{ForkFilter, ForkId, ValidationError};
+use super::head::Head;
+
+/// Announced blocks too far ahead of the known head are ignored, so peers can't move the fork id
+/// (and disconnect us from everyone else). While at genesis only the clock bound applies.
+const MAX_BLOCKS_AHEAD: u64 = 10_000;
+/// Shortest block interval of supported chains, head can't move faster than that
+const MIN_BLOCK_INTERVAL: Duration = Duration::from_millis(450);
+/// Same as geth, blocks with timestamps further in the future are ignored
+const MAX_FUTURE_BLOCK_TIME: u64 = 15;
+/// Blocks move the head and statuses move total difficulty only once this many peers agree
+const MIN_ANNOUNCERS: usize = 2;
+/// Numbers of blocks announced by too few peers which are kept, the highest ones are dropped first
+const MAX_PENDING_BLOCKS: usize = 64;
+/// Peer statuses kept for total difficulty agreement, the lowest ones are dropped first
+const MAX_PEER_STATUSES: usize = 64;
+/// Heads kept so blocks can be checked against them after the head moves on
+const MAX_RECENT_HEADS: usize = 64;
+/// Block interval until we measure it from announced blocks
+const DEFAULT_BLOCK_INTERVAL: Duration = Duration::from_millis(3000);
+/// Head moving by more blocks than this (eg. after we were idle) says nothing about block time
+const MAX_BLOCKS_PER_INTERVAL_SAMPLE: u64 = 3;
+/// Waiting for blocks times out this long after they are expected
+const BLOCK_WAIT_SLACK: Duration = Duration::from_millis(500);
+
+static HEAD_TRACKER: Lazy<RwLock<HeadTracker>> =
+    Lazy::new(|| RwLock::new(HeadTracker::new(chain_spec())));
+
+/*
+* Chain head as announced by our peers, so we don't have to follow the chain ourselves.
+* Fork filter head is moved by `NewBlockMsg`, `NewBlockHashesMsg` and latest blocks of eth/69 peers
+* (status and `BlockRangeUpdateMsg`), so fork id we advertise (and expect from peers) changes by
+* itself once a new hardfork is activated.
+* Total difficulty and its block hash are advertised in our status message, they are taken from
+* new blocks and from status messages of peers.
+*
+* A block moves the head only once `MIN_ANNOUNCERS` peers announced the same hash, and only if it
+* could have been built by now (by number and by timestamp), so a single peer can't move it.
+* Total difficulty of status messages needs the same agreement.
+*
+* Times at which new heads are seen give us block interval, so instead of sleeping for a guessed
+* block duration we wait for the block to be announced (see `wait_for_blocks`).
+* */
+#[derive(Debug, Clone)]
+pub struct HeadTracker {
+    filter: ForkFilter,
+    head: Head,
+    td: u128,
+    td_hash: H256,
+    gas_limit: u64,
+    head_seen_at: Option<Instant>,
+    block_interval: Duration,
+    pending: BTreeMap<u64, Vec<PendingBlock>>,
+    peer_statuses: HashMap<H512, (u128, H256)>,
+    /// Head block number, for tasks waiting for new blocks
+    head_number: watch::Sender<u64>,
+    /// Number and hash of the latest heads, newest last
+    recent_heads: VecDeque<(u64, H256)>,
+}
+
+/// Peer which announced a block and when we got it
+#[derive(Debug, Clone, Copy)]
+pub struct Announcer {
+    pub peer: H512,
+    pub at: Instant,
+    /// Wall clock seconds, announced numbers and timestamps are bounded by it
+    pub unix_time: u64,
+}
+
+impl Announcer {
+    pub fn now(peer: H512) -> Self {
+        Self {
+            peer,
+            at: Instant::now(),
+            unix_time: unix_time(),
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Block announced by fewer than `MIN_ANNOUNCERS` peers so far
+#[derive(Debug, Clone)]
+struct PendingBlock {
+    hash: H256,
+    peers: Vec<H512>,
+    /// Head, total difficulty and gas limit, once the block is announced in full
+    full: Option<(Head, u128, u64)>,
+}
+
+impl HeadTracker {
+    pub fn new(spec: &ChainSpec) -> Self {
+        Self {
+            filter: spec.fork_filter(spec.head),
+            head: spec.head,
+            td: spec.td as u128,
+            td_hash: spec.genesis_hash,
+            gas_limit: 0,
+            head_seen_at: None,
+            block_interval: DEFAULT_BLOCK_INTERVAL,
+            pending: BTreeMap::new(),
+            peer_statuses: HashMap::new(),
+            head_number: watch::channel(spec.head.number).0,
+            recent_heads: VecDeque::with_capacity(MAX_RECENT_HEADS),
+        }
+    }
+
+    pub fn fork_id(&self) -> ForkId {
+        self.filter.current()
+    }
+
+    pub fn head(&self) -> Head {
+        self.head
+    }
+
+    /// Gas limit of the latest full block, 0 until one is seen
+    pub fn gas_limit(&self) -> u64 {
+        self.gas_limit
+    }
+
+    pub fn block_interval(&self) -> Duration {
+        self.block_interval
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.head_number.subscribe()
+    }
+
+    /// Total difficulty and block hash for our status message
+    pub fn status(&self) -> (u128, H256) {
+        (self.td, self.td_hash)
+    }
+
+    pub fn validate(&self, fork_id: ForkId) -> Result<(), ValidationError> {
+        self.filter.validate(fork_id)
+    }
+
+    pub fn is_new_block(&self, number: u64, td: u128) -> bool {
+        number > self.head.number || td > self.td
+    }
+
+    /// Whether the block was one of the recent heads, ie. peers agreed on it
+    pub fn is_agreed_block(&self, number: u64, hash: H256) -> bool {
+        self.recent_heads.contains(&(number, hash))
+    }
+
+    /// Newer block, or timestamp of the head which was moved by hashes only
+    pub fn is_new_full_block(&self, head: &Head) -> bool {
+        head.number > self.head.number
+            || (head.hash == self.head.hash && head.timestamp > self.head.timestamp)
+    }
+
+    /// Number can't be ahead of what the chain could have built since the latest block we know,
+    /// nor more than `MAX_BLOCKS_AHEAD` once we are past genesis
+    fn is_too_far_ahead(&self, number: u64, unix_time: u64) -> bool {
+        let since_head = unix_time.saturating_sub(self.head.timestamp) + MAX_FUTURE_BLOCK_TIME;
+        let mut max_ahead = (since_head as u128 * 1000 / MIN_BLOCK_INTERVAL.as_millis()) as u64;
+        if self.head.number > 0 {
+            max_ahead = max_ahead.min(MAX_BLOCKS_AHEAD);
+        }
+        number > self.head.number.saturating_add(max_ahead)
+    }
+
+    /// Timestamps only grow and can't be (much) ahead of our clock
+    fn is_valid_timestamp(&self, timestamp: u64, unix_time: u64) -> bool {
+        timestamp >= self.head.timestamp && timestamp <= unix_time + MAX_FUTURE_BLOCK_TIME
+    }
+
+    pub fn on_new_block(&mut self, head: Head, td: u128, gas_limit: u64, from: Announcer) {
+        if !self.is_valid_timestamp(head.timestamp, from.unix_time) {
+            return;
+        }
+
+        // head was moved by hashes only, timestamp comes with the full block
+        if head.number == self.head.number && head.hash == self.head.hash {
+            self.gas_limit = gas_limit;
+            self.head.timestamp = head.timestamp;
+            self.move_fork_filter();
+            return;
+        }
+
+        if head.number <= self.head.number || self.is_too_far_ahead(head.number, from.unix_time) {
+            return;
+        }
+
+        if let Some(block) =
+            self.announce(head.number, head.hash, Some((head, td, gas_limit)), &from)
+        {
+            let (head, td, gas_limit) = block.full.unwrap_or((head, td, gas_limit));
+            if td > self.td {
+                self.td = td;
+                self.td_hash = head.hash;
+            }
+            self.gas_limit = gas_limit;
+            self.set_head(head, from.at);
+        }
+    }
+
+    /// Only number and hash are announced, timestamp stays the same until the next full block
+    pub fn on_block_hash(&mut self, number: u64, hash: H256, from: Announcer) {
+        if number <= self.head.number || self.is_too_far_ahead(number, from.unix_time) {
+            return;
+        }
+
+        if let Some(block) = self.announce(number, hash, None, &from) {
+            let head = match block.full {
+                Some((head, _, gas_limit)) => {
+                    self.gas_limit = gas_limit;
+                    head
+                }
+                None => Head {
+                    number,
+                    hash,
+                    ..self.head
+                },
+            };
+            self.set_head(head, from.at);
+        }
+    }
+
+    /// Total difficulty of our status is the highest one reached by `MIN_ANNOUNCERS` peers
+    pub fn on_peer_status(&mut self, peer: H512, td: u128, hash: H256) {
+        self.peer_statuses.insert(peer, (td, hash));
+        if self.peer_statuses.len() > MAX_PEER_STATUSES {
+            if let Some(lowest) = self
+                .peer_statuses
+                .iter()
+                .min_by_key(|(_, (td, _))| *td)
+                .map(|(peer, _)| *peer)
+            {
+                self.peer_statuses.remove(&lowest);
+            }
+        }
+
+        let mut statuses = self.peer_statuses.values().copied().collect::<Vec<_>>();
+        statuses.sort_unstable_by_key(|(td, _)| Reverse(*td));
+        if let Some(&(td, hash)) = statuses.get(MIN_ANNOUNCERS - 1) {
+            if td > self.td {
+                self.td = td;
+                self.td_hash = hash;
+            }
+        }
+    }
+
+    /// Records the announcement, returns the block once enough peers announced it.
+    /// Every peer counts for a single hash of a number, the latest one it announced.
+    fn announce(
+        &mut self,
+        number: u64,
+        hash: H256,
+        full: Option<(Head, u128, u64)>,
+        from: &Announcer,
+    ) -> Option<PendingBlock> {
+        let blocks = self.pending.entry(number).or_default();
+        for block in blocks.iter_mut() {
+            block.peers.retain(|peer| peer != &from.peer);
+        }
+        blocks.retain(|block| !block.peers.is_empty() || block.hash == hash);
+
+        let index = match blocks.iter().position(|block| block.hash == hash) {
+            Some(index) => index,
+            None => {
+                blocks.push(PendingBlock {
+                    hash,
+                    peers: Vec::new(),
+                    full: None,
+                });
+                blocks.len() - 1
+            }
+        };
+        let block = &mut blocks[index];
+        block.peers.push(from.peer);
+        // announcers may disagree about total difficulty, the lowest one is kept
+        if let Some(announced) = full {
+            if block.full.is_none_or(|(_, td, _)| announced.1 < td) {
+                block.full = Some(announced);
+            }
+        }
+
+        if block.peers.len() < MIN_ANNOUNCERS {
+            while self.pending.len() > MAX_PENDING_BLOCKS {
+                self.pending.pop_last();
+            }
+            return None;
+        }
+
+        let block = blocks.swap_remove(index);
+        // announcements up to the new head are useless
+        self.pending = self.pending.split_off(&(number + 1));
+        Some(block)
+    }
+
+    /// Block interval is rolling average of time between heads we've seen.
+    /// Heads faster than the chain can build blocks are not taken as samples.
+    fn observe_block_time(&mut self, number: u64, now: Instant) {
+        let blocks = number - self.head.number;
+        if let Some(seen_at) = self.head_seen_at {
+            if blocks <= MAX_BLOCKS_PER_INTERVAL_SAMPLE {
+                let sample = now.saturating_duration_since(seen_at) / blocks as u32;
+                if sample >= MIN_BLOCK_INTERVAL {
+                    self.block_interval = (self.block_interval * 7 + sample) / 8;
+                }
+            }
+        }
+        self.head_seen_at = Some(now);
+    }
+
+    fn set_head(&mut self, head: Head, now: Instant) {
+        self.observe_block_time(head.number, now);
+        self.head = head;
+        if self.recent_heads.len() == MAX_RECENT_HEADS {
+            self.recent_heads.pop_front();
+        }
+        self.recent_heads.push_back((head.number, head.hash));
+        self.head_number.send_replace(head.number);
+        self.move_fork_filter();
+    }
+
+    fn move_fork_filter(&mut self) {
+        if let Some(transition) = self.filter.set_head(self.head) {
+            println!(
+                "Fork id changed at block {}: {:X?} -> {:X?}",
+                self.head.number, transition.past, transition.current
+            );
+        }
+    }
+}
+
+pub fn current_fork_id() -> ForkId {
+    HEAD_TRACKER.read().unwrap().fork_id()
+}
+
+pub fn current_head() -> Head {
+    HEAD_TRACKER.read().unwrap().head()
+}
+
+pub fn current_gas_limit() -> u64 {
+    HEAD_TRACKER.read().unwrap().gas_limit()
+}
+
+pub fn block_interval() -> Duration {
+    HEAD_TRACKER.read().unwrap().block_interval()
+}
+
+pub fn status_td_and_hash() -> (u128, H256) {
+    HEAD_TRACKER.read().unwrap().status()
+}
+
+pub fn is_agreed_block(number: u64, hash: H256) -> bool {
+    HEAD_TRACKER.read().unwrap().is_agreed_block(number, hash)
+}
+
+pub fn validate_fork_id(fork_id: ForkId) -> Result<(), ValidationError> {
+    HEAD_TRACKER.read().unwrap().validate(fork_id)
+}
+
+pub fn on_new_block(peer: H512, head: Head, td: u128, gas_limit: u64) {
+    // the same block is announced by many peers, so write lock is taken only for new ones
+    if !HEAD_TRACKER.read().unwrap().is_new_full_block(&head) {
+        return;
+    }
+    HEAD_TRACKER
+        .write()
+        .unwrap()
+        .on_new_block(head, td, gas_limit, Announcer::now(peer));
+}
+
+/// Block hash announced by a peer, by `NewBlockHashesMsg` or as the latest block of an eth/69 peer
+pub fn on_block_hash(peer: H512, number: u64, hash: H256) {
+    if !HEAD_TRACKER.read().unwrap().is_new_block(number, 0) {
+        return;
+    }
+    HEAD_TRACKER
+        .write()
+        .unwrap()
+        .on_block_hash(number, hash, Announcer::now(peer));
+}
+
+/// Waits until block `number` (or a later one) is announced.
+/// Returns false on timeout (eg. no peers announce blocks), callers carry on as if it was seen.
+pub async fn wait_for_block(number: u64, timeout: Duration) -> bool {
+    let head = HEAD_TRACKER.read().unwrap().subscribe();
+    wait_for_head(head, number, timeout).await
+}
+
+/// Waits for `count` blocks after the current head, times out shortly after they are expected
+pub async fn wait_for_blocks(count: u64) -> bool {
+    let target = current_head().number + count;
+    let timeout = block_interval() * count as u32 + BLOCK_WAIT_SLACK;
+    wait_for_block(target, timeout).await
+}
+
+async fn wait_for_head(mut head: watch::Receiver<u64>, number: u64, timeout: Duration) -> bool {
+    let seen = tokio::time::timeout(timeout, head.wait_for(|head| *head >= number)).await;
+    matches!(seen, Ok(Ok(_)))
+}
+
+pub fn on_peer_status(peer: H512, td: u128, hash: H256) {
+    if !HEAD_TRACKER.read().unwrap().is_new_block(0, td) {
+        return;
+    }
+    HEAD_TRACKER.write().unwrap().on_peer_status(peer, td, hash);
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::blockchain::fork::ForkHash;
+    use crate::blockchain::BSC_TESTNET;
+
+    /// Wall clock of tests, after all Chapel forks
+    const UNIX_TIME: u64 = 1_720_000_000;
+
+    fn from(peer: u8, at: Instant) -> Announcer {
+        Announcer {
+            peer: H512::repeat_byte(peer),
+            at,
+            unix_time: UNIX_TIME,
+        }
+    }
+
+    /// Block hash announced by two peers, enough to move the head
+    fn agreed_block_hash(tracker: &mut HeadTracker, number: u64, hash: H256, at: Instant) {
+        tracker.on_block_hash(number, hash, from(1, at));
+        tracker.on_block_hash(number, hash, from(2, at));
+    }
+
+    #[test]
+    fn fork_id_follows_new_blocks() {
+        let mut tracker = HeadTracker::new(&BSC_TESTNET);
+        assert_eq!(tracker.fork_id().hash, ForkHash(hex!("216c4fbd")));
+        assert_eq!(tracker.status(), (1, BSC_TESTNET.genesis_hash));
+
+        // number only, timestamp forks are not activated
+        let now = Instant::now();
+        agreed_block_hash(&mut tracker, 35_682_300, H256::repeat_byte(1), now);
+        assert_eq!(
+            tracker.fork_id(),
+            ForkId {
+                hash: ForkHash(hex!("de149bad")),
+                next: 1702972800
+            }
+        );
+        assert_eq!(tracker.status(), (1, BSC_TESTNET.genesis_hash));
+
+        let block = Head {
+            number: 35_690_000,
+            hash: H256::repeat_byte(2),
+            timestamp: 1716962820,
+            ..Default::default()
+        };
+        // a single peer doesn't move the head, however many times it announces the block
+        tracker.on_new_block(block, 80_000_000, 140_000_000, from(1, now));
+        tracker.on_new_block(block, 80_000_000, 140_000_000, from(1, now));
+        assert_eq!(tracker.head().number, 35_682_300);
+        assert!(!tracker.is_agreed_block(block.number, block.hash));
+
+        tracker.on_new_block(block, 80_000_001, 140_000_000, from(2, now));
+        assert!(tracker.is_agreed_block(block.number, block.hash));
+        assert_eq!(tracker.fork_id().hash, ForkHash(hex!("63d5dae0")));
+        assert_eq!(tracker.status(), (80_000_000, H256::repeat_byte(2)));
+        assert!(tracker
+            .validate(ForkId {
+                hash: ForkHash(hex!("63d5dae0")),
+                next: 0
+            })
+            .is_ok());
+
+        // older block doesn't move the head back, bogus one doesn't move it forward
+        agreed_block_hash(&mut tracker, 35_689_999, H256::repeat_byte(3), now);
+        assert_eq!(tracker.head(), block);
+        agreed_block_hash(&mut tracker, 36_000_000, H256::repeat_byte(3), now);
+        assert_eq!(tracker.head(), block);
+        assert_eq!(tracker.gas_limit(), 140_000_000);
+
+        // total difficulty of a single peer isn't trusted
+        tracker.on_peer_status(H512::repeat_byte(1), 80_000_005, H256::repeat_byte(4));
+        assert_eq!(tracker.status(), (80_000_000, H256::repeat_byte(2)));
+        tracker.on_peer_status(H512::repeat_byte(2), 80_000_002, H256::repeat_byte(5));
+        assert_eq!(tracker.status(), (80_000_002, H256::repeat_byte(5)));
+        tracker.on_peer_status(H512::repeat_byte(2), 80_000_009, H256::repeat_byte(6));
+        assert_eq!(tracker.status(), (80_000_005, H256::repeat_byte(4)));
+        assert_eq!(tracker.head(), block);
+    }
+
+    #[test]
+    fn announced_blocks_are_bounded_by_clock() {
+        let mut tracker = HeadTracker::new(&BSC_TESTNET);
+        let now = Instant::now();
+        let genesis = BSC_TESTNET.head.timestamp;
+        // an hour after genesis, about 8000 blocks could have been built
+        let unix_time = genesis + 3_600;
+        let block = |number: u64, timestamp| Head {
+            number,
+            hash: H256::from_low_u64_be(number),
+            timestamp,
+            ..Default::default()
+        };
+        let announce = |tracker: &mut HeadTracker, head: Head| {
+            for peer in 1..=2 {
+                tracker.on_new_block(
+                    head,
+                    1,
+                    0,
+                    Announcer {
+                        peer: H512::repeat_byte(peer),
+                        at: now,
+                        unix_time,
+                    },
+                );
+            }
+        };
+
+        announce(&mut tracker, block(100_000, genesis + 3_000));
+        assert_eq!(tracker.head(), BSC_TESTNET.head);
+        announce(&mut tracker, block(10, unix_time + 60));
+        assert_eq!(tracker.head(), BSC_TESTNET.head);
+
+        announce(&mut tracker, block(7_000, unix_time));
+        assert_eq!(tracker.head(), block(7_000, unix_time));
+        // timestamps don't go back
+        announce(&mut tracker, block(7_001, unix_time - 1));
+        assert_eq!(tracker.head().number, 7_000);
+    }
+
+    #[test]
+    fn block_interval_follows_heads() {
+        let mut tracker = HeadTracker::new(&BSC_TESTNET);
+        let start = Instant::now();
+        let hash = H256::repeat_byte(1);
+        assert_eq!(tracker.block_interval(), DEFAULT_BLOCK_INTERVAL);
+
+        // the first head only starts the clock
+        agreed_block_hash(&mut tracker, 100, hash, start);
+        assert_eq!(tracker.block_interval(), DEFAULT_BLOCK_INTERVAL);
+
+        for i in 1..=50 {
+            agreed_block_hash(
+                &mut tracker,
+                100 + i,
+                hash,
+                start + Duration::from_millis(i * 1_000),
+            );
+        }
+        let interval = tracker.block_interval();
+        assert!(interval > Duration::from_millis(990) && interval < Duration::from_millis(1_100));
+
+        // we weren't listening for a while
+        agreed_block_hash(
+            &mut tracker,
+            1_000,
+            hash,
+            start + Duration::from_secs(3_000),
+        );
+        assert_eq!(tracker.block_interval(), interval);
+    }
+
+    #[test]
+    fn block_hashes_need_several_peers() {
+        let mut tracker = HeadTracker::new(&BSC_TESTNET);
+        let now = Instant::now();
+
+        tracker.on_block_hash(100, H256::repeat_byte(1), from(1, now));
+        tracker.on_block_hash(100, H256::repeat_byte(1), from(1, now));
+        tracker.on_block_hash(100, H256::repeat_byte(2), from(2, now));
+        assert_eq!(tracker.head(), BSC_TESTNET.head);
+
+        // peer 2 changed its mind
+        tracker.on_block_hash(100, H256::repeat_byte(1), from(2, now));
+        assert_eq!(tracker.head().number, 100);
+        assert_eq!(tracker.head().hash, H256::repeat_byte(1));
+    }
+
+    #[tokio::test]
+    async fn wait_for_announced_block() {
+        let mut tracker = HeadTracker::new(&BSC_TESTNET);
+        let head = tracker.head().number;
+        assert!(wait_for_head(tracker.subscribe(), head, Duration::from_millis(10)).await);
+        assert!(!wait_for_head(tracker.subscribe(), head + 1, Duration::from_millis(10)).await);
+
+        let waiting = tokio::spawn(wait_for_head(
+            tracker.subscribe(),
+            head + 1,
+            Duration::from_secs(5),
+        ));
+        let now = Instant::now();
+        tracker.on_block_hash(head + 1, H256::repeat_byte(9), from(1, now));
+        tracker.on_block_hash(head + 1, H256::repeat_byte(9), from(2, now));
+        assert!(waiting.await.unwrap());
+    }
+}
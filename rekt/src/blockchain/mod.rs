@@ -1,8 +1,12 @@
 pub mod bsc_chain_spec;
 pub mod chain_spec;
+pub mod chapel_chain_spec;
 pub mod fork;
 pub mod fork_condition;
 pub mod hard_fork;
 pub mod head;
+pub mod head_tracker;
 
 pub use self::bsc_chain_spec::BSC_MAINNET;
+pub use self::chain_spec::{chain_spec, init_chain_spec, ChainSpec};
+pub use self::chapel_chain_spec::BSC_TESTNET;
@@ -0,0 +1,2 @@
+pub mod msg_handler;
+pub mod protocol;
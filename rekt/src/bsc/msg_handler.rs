@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use open_fastrlp::{Decodable, DecodeError, Header, HeaderInfo};
+
+use super::protocol::{BscMessage, BscProtocol, BSC_PROTOCOL_VERSION};
+
+/// Votes are of no use to us, they are only counted
+static VOTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+
+/*
+* `bsc/1` is negotiated next to `eth` so validators' sentries (which drop peers without it) keep
+* us connected. The only thing the protocol needs from us is the `BscCapMsg` handshake, which we
+* send when the session starts, so incoming messages are only checked and dropped.
+* */
+pub fn handle_bsc_message(msg: BscMessage) -> Result<(), DecodeError> {
+    match msg.id {
+        BscProtocol::CapMsg => check_cap_message(&mut &msg.data[..]),
+        BscProtocol::VotesMsg => {
+            VOTES_RECEIVED.fetch_add(count_votes(&mut &msg.data[..])?, Ordering::Relaxed);
+            Ok(())
+        }
+        BscProtocol::Unknown => Ok(()),
+    }
+}
+
+pub fn votes_received() -> u64 {
+    VOTES_RECEIVED.load(Ordering::Relaxed)
+}
+
+fn check_cap_message(buf: &mut &[u8]) -> Result<(), DecodeError> {
+    let h = Header::decode(buf)?;
+    if !h.list {
+        return Err(DecodeError::UnexpectedString);
+    }
+
+    if usize::decode(buf)? != BSC_PROTOCOL_VERSION {
+        return Err(DecodeError::Custom("Unsupported bsc protocol version"));
+    }
+    Ok(())
+}
+
+/// `VotesMsg`: [[vote envelope, ...]]
+fn count_votes(buf: &mut &[u8]) -> Result<u64, DecodeError> {
+    let h = Header::decode(buf)?;
+    if !h.list {
+        return Err(DecodeError::UnexpectedString);
+    }
+    let votes = Header::decode(buf)?;
+    if !votes.list {
+        return Err(DecodeError::UnexpectedString);
+    }
+
+    let payload_view = &mut &buf[..votes.payload_length];
+    let mut count = 0;
+    while !payload_view.is_empty() {
+        HeaderInfo::skip_next_item(payload_view)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::{Bytes, BytesMut};
+    use hex_literal::hex;
+    use open_fastrlp::Encodable;
+
+    use super::*;
+
+    #[test]
+    fn our_cap_is_accepted() {
+        let cap = BscMessage::cap();
+        // [1, 0x00]
+        assert_eq!(&cap.data[..], &hex!("c20100")[..]);
+        assert_eq!(handle_bsc_message(cap), Ok(()));
+
+        let newer = BscMessage::new(BscProtocol::CapMsg, Bytes::from_static(&hex!("c20200")));
+        assert!(handle_bsc_message(newer).is_err());
+    }
+
+    #[test]
+    fn counts_votes() {
+        let votes = vec![Bytes::from_static(&[1, 2]), Bytes::from_static(&[3])];
+        let mut inner = BytesMut::new();
+        votes.encode(&mut inner);
+        let mut data = BytesMut::new();
+        Header {
+            list: true,
+            payload_length: inner.len(),
+        }
+        .encode(&mut data);
+        data.extend_from_slice(&inner);
+
+        assert_eq!(count_votes(&mut &data[..]), Ok(2));
+        let before = votes_received();
+        handle_bsc_message(BscMessage::new(BscProtocol::VotesMsg, data.freeze())).unwrap();
+        assert!(votes_received() >= before + 2);
+    }
+}
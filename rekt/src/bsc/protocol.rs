@@ -0,0 +1,57 @@
+use bytes::{Bytes, BytesMut};
+use derive_more::Display;
+use open_fastrlp::{Encodable, RlpEncodable};
+
+pub const BSC_PROTOCOL: &str = "bsc";
+pub const BSC_PROTOCOL_VERSION: usize = 1;
+/// Number of message ids `bsc/1` takes in the shared message id space
+pub const BSC_PROTOCOL_LENGTH: u8 = 2;
+
+#[derive(Debug, Display, Copy, Clone, Eq, PartialEq)]
+pub enum BscProtocol {
+    /// Handshake of the `bsc` protocol, both sides send it right after the connection is set up
+    CapMsg = 0x00,
+    /// Fast finality votes
+    VotesMsg = 0x01,
+    Unknown = 0xff,
+}
+
+impl From<u8> for BscProtocol {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => Self::CapMsg,
+            0x01 => Self::VotesMsg,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BscMessage {
+    pub id: BscProtocol,
+    pub data: Bytes,
+}
+
+/// `BscCapMsg`: [protocol_version, extra], extra is raw rlp value which nobody uses yet
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable)]
+struct BscCapMessage {
+    protocol_version: usize,
+    extra: Bytes,
+}
+
+impl BscMessage {
+    pub fn new(id: BscProtocol, data: Bytes) -> Self {
+        Self { id, data }
+    }
+
+    /// Our `BscCapMsg`, extra is the same as in BSC client (single 0x00 byte)
+    pub fn cap() -> Self {
+        let mut rlp = BytesMut::new();
+        BscCapMessage {
+            protocol_version: BSC_PROTOCOL_VERSION,
+            extra: Bytes::from_static(&[0x00]),
+        }
+        .encode(&mut rlp);
+        Self::new(BscProtocol::CapMsg, rlp.freeze())
+    }
+}
@@ -2,6 +2,9 @@ use std::fmt::{Display, Formatter};
 
 use clap::Parser;
 
+use crate::eth::transactions::cache::DEFAULT_TXS_PER_GENERATION;
+use crate::server::peer_slots::{DEFAULT_MAX_INBOUND_PEERS, DEFAULT_MAX_OUTBOUND_PEERS};
+
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
@@ -44,6 +47,61 @@ pub struct Cli {
     #[arg(long = "city", default_value = "N/A", value_name = "Server country")]
     pub city: String,
 
+    #[arg(
+        long = "tx_cache_size",
+        default_value_t = DEFAULT_TXS_PER_GENERATION,
+        value_name = "How many txs are remembered per tx cache generation"
+    )]
+    pub tx_cache_size: usize,
+
+    #[arg(
+        long = "max_inbound_peers",
+        default_value_t = DEFAULT_MAX_INBOUND_PEERS,
+        value_name = "Max number of inbound peers"
+    )]
+    pub max_inbound_peers: usize,
+
+    #[arg(
+        long = "max_outbound_peers",
+        default_value_t = DEFAULT_MAX_OUTBOUND_PEERS,
+        value_name = "Max number of outbound peers"
+    )]
+    pub max_outbound_peers: usize,
+
+    #[arg(
+        long = "announce_txs_to",
+        value_delimiter = ',',
+        value_name = "Protocol versions (eg. 67,68) of peers which get our txs announced by hash"
+    )]
+    pub announce_txs_to: Vec<usize>,
+
+    #[arg(
+        long = "nodekey",
+        value_name = "Node key file, generated if missing (overrides config)"
+    )]
+    pub node_key_file: Option<String>,
+
+    #[arg(
+        long = "nodekey_hex",
+        value_name = "Node key as hex (overrides node key file)"
+    )]
+    pub node_key_hex: Option<String>,
+
+    #[arg(
+        long = "node_db",
+        value_name = "File with discovered nodes, kept across restarts (overrides config)"
+    )]
+    pub node_db_file: Option<String>,
+
+    #[arg(long = "discv5", value_name = "Run discovery v5 next to v4")]
+    pub discv5: bool,
+
+    #[arg(
+        long = "discv5_port",
+        value_name = "UDP port of discovery v5, shares the discovery v4 port if not set"
+    )]
+    pub discv5_port: Option<u16>,
+
     pub first_wallet: Option<ethers::types::Address>,
     pub last_wallet: Option<ethers::types::Address>,
 }
@@ -79,6 +137,15 @@ impl Default for Cli {
             name: "N/A".into(),
             country: "N/A".into(),
             city: "N/A".into(),
+            tx_cache_size: DEFAULT_TXS_PER_GENERATION,
+            max_inbound_peers: DEFAULT_MAX_INBOUND_PEERS,
+            max_outbound_peers: DEFAULT_MAX_OUTBOUND_PEERS,
+            announce_txs_to: Vec::new(),
+            node_key_file: None,
+            node_key_hex: None,
+            node_db_file: None,
+            discv5: false,
+            discv5_port: None,
             first_wallet: None,
             last_wallet: None,
         }
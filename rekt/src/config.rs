@@ -1,10 +1,78 @@
 use std::io;
+use std::net::SocketAddr;
 
+use ethers::types::Address;
 use serde::Deserialize;
 
+use crate::blockchain::ChainSpec;
+
 #[derive(Deserialize)]
 pub struct Config {
     pub nodes: Vec<String>,
+
+    /// Name of the known chain, eg. `bsc`, ignored if `chain_spec` is set
+    #[serde(default)]
+    pub chain: Option<String>,
+
+    /// Full spec for chains which are not known by name
+    #[serde(default)]
+    pub chain_spec: Option<ChainSpec>,
+
+    /// Caesar bot deployed on the selected chain, overrides the one from the chain spec
+    #[serde(default)]
+    pub caesar_bot: Option<Address>,
+
+    /// File with our node key, `nodekey` by default
+    #[serde(default)]
+    pub node_key_file: Option<String>,
+
+    /// File with discovered nodes, `nodes.json` by default
+    #[serde(default)]
+    pub node_db_file: Option<String>,
+
+    /// ENRs (`enr:...`) discovery v5 starts from
+    #[serde(default)]
+    pub discv5_bootnodes: Vec<String>,
+
+    /// EIP-1459 node lists, resolved next to the ones of the chain spec
+    #[serde(default)]
+    pub enrtrees: Vec<String>,
+
+    /// Name server node lists are resolved with, `1.1.1.1:53` by default
+    #[serde(default)]
+    pub dns_server: Option<SocketAddr>,
+}
+
+impl Config {
+    /// Chain selected in config, BSC mainnet by default
+    pub fn chain_spec(&self) -> Result<ChainSpec, io::Error> {
+        let mut spec = match &self.chain_spec {
+            Some(spec) => spec.clone(),
+            None => {
+                let name = self.chain.as_deref().unwrap_or("bsc");
+                ChainSpec::by_name(name)
+                    .ok_or_else(|| io::Error::other(format!("Unknown chain: {}", name)))?
+            }
+        };
+
+        if let Some(caesar_bot) = self.caesar_bot {
+            spec.caesar_bot = caesar_bot;
+        }
+        if spec.caesar_bot.is_zero() {
+            return Err(io::Error::other(format!(
+                "Caesar bot address is not set for chain {}",
+                spec.chain
+            )));
+        }
+        if spec.public_nodes.is_empty() {
+            return Err(io::Error::other(format!(
+                "Public nodes are not set for chain {}",
+                spec.chain
+            )));
+        }
+
+        Ok(spec)
+    }
 }
 
 pub fn get_config() -> Result<Config, io::Error> {
@@ -19,3 +87,50 @@ fn get_config_file() -> Result<String, io::Error> {
     let file_str = std::fs::read_to_string("config.toml")?;
     Ok(file_str)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CAESAR_BOT: &str = "0x47Aeb02ba0aa4b432E75f30293CB7C7BF70aafbf";
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn chapel_is_selected_by_name() {
+        let config = config(&format!(
+            "nodes = []\nchain = \"chapel\"\ncaesar_bot = \"{}\"",
+            CAESAR_BOT
+        ));
+        let spec = config.chain_spec().unwrap();
+
+        assert_eq!(spec.chain, 97);
+        assert_eq!(
+            spec.genesis_hash,
+            crate::blockchain::BSC_TESTNET.genesis_hash
+        );
+        assert_eq!(spec.caesar_bot, CAESAR_BOT.parse().unwrap());
+    }
+
+    #[test]
+    fn incomplete_chain_is_rejected() {
+        // testnet has no default Caesar bot
+        assert!(config("nodes = []\nchain = \"chapel\"")
+            .chain_spec()
+            .is_err());
+        assert!(config("nodes = []\nchain = \"unknown\"")
+            .chain_spec()
+            .is_err());
+
+        let mut spec = crate::blockchain::BSC_TESTNET.clone();
+        spec.caesar_bot = CAESAR_BOT.parse().unwrap();
+        spec.public_nodes.clear();
+        let config = Config {
+            chain_spec: Some(spec),
+            ..config("nodes = []")
+        };
+        assert!(config.chain_spec().is_err());
+    }
+}
@@ -1,21 +1,18 @@
-use std::str::FromStr;
-
 use ethers::{
     abi::Abi,
-    contract::Contract,
-    providers::{Http, Provider},
-    types::{Address, Bytes, U256},
+    contract::BaseContract,
+    types::{Bytes, U256},
 };
 use num_traits::Pow;
 use once_cell::sync::Lazy;
 
-use crate::public_nodes::nodes::PUBLIC_NODE_URLS;
 use crate::token::token::Token;
 
 pub const BUY_TX_METHOD: &str = "cure";
 pub const CAESAR_BOT_ADDRESS: &str = "0x47Aeb02ba0aa4b432E75f30293CB7C7BF70aafbf";
 
-static CAESAR_BOT: Lazy<Contract<Provider<Http>>> = Lazy::new(|| get_caesar_bot());
+/// Only used to encode calls, so it needs neither the address nor a node
+static CAESAR_BOT: Lazy<BaseContract> = Lazy::new(|| BaseContract::from(get_abi()));
 
 pub fn encode_buy_method() -> Bytes {
     let buy_tx = CAESAR_BOT
@@ -54,18 +51,6 @@ pub fn encode_prep_method(token: &Token) -> Bytes {
     prep_tx
 }
 
-fn get_caesar_bot() -> Contract<Provider<Http>> {
-    let bot_address = Address::from_str(CAESAR_BOT_ADDRESS).expect("Invalid bot address");
-    //NOTE: this looks like a shitty solution (since we are just using the first node from the list
-    //and we don't even know if the node works or not)
-    //but this is ok , because we are never using the node for anything
-    //we need here to make ethers crate happy
-    let client = Provider::<Http>::try_from(PUBLIC_NODE_URLS[0]).expect("Failed to create client");
-    let bot_contract = Contract::new(bot_address, get_abi(), client.into());
-
-    bot_contract
-}
-
 fn get_abi() -> Abi {
     let abi: Abi = serde_json::from_str(
         r#"[
@@ -88,3 +88,8 @@ pub fn decode_msg_and_create_response(
 pub fn packet_size_is_valid(size: usize) -> bool {
     size > HEADER_SIZE && size <= MAX_PACKET_SIZE
 }
+
+/// discv4 packets start with keccak of the rest of the packet, anything else on our port is discv5
+pub fn is_v4_packet(buf: &[u8]) -> bool {
+    packet_size_is_valid(buf.len()) && keccak256(&buf[HASH_SIZE..])[..] == buf[..HASH_SIZE]
+}
@@ -1,13 +1,17 @@
 use std::net::{IpAddr, Ipv4Addr};
 use std::time::Instant;
 
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::fork::ForkId;
 use crate::types::hash::H512;
 use crate::types::node_record::NodeRecord;
 
 use super::messages::find_node::NeighborNodeRecord;
 use super::messages::ping_pong_messages::PingMessage;
+use super::node_db::{unix_to_instant, StoredNode};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DiscoverNodeType {
     Unknown,
     Static,
@@ -15,7 +19,7 @@ pub enum DiscoverNodeType {
     TheyDiscoveredUs,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AuthStatus {
     NotAuthed,
     WeAuthedThem,
@@ -29,6 +33,8 @@ pub struct DiscoverNode {
     pub ip_v4_addr: Ipv4Addr,
     pub node_type: DiscoverNodeType,
     pub is_bsc_node: Option<bool>,
+    /// Fork id from node's ENR
+    pub fork_id: Option<ForkId>,
 
     pinged_on: Option<Instant>,
     ping_count: u8,
@@ -112,6 +118,11 @@ impl DiscoverNode {
         self.is_bsc_node = Some(is_bsc);
     }
 
+    /// When we last received pong and ping from this node
+    pub(super) fn auth_times(&self) -> (Option<Instant>, Option<Instant>) {
+        (self.pong_received_on, self.ping_received_on)
+    }
+
     pub(super) fn from_ping_msg(ping_msg: &PingMessage, id: H512) -> Result<Self, ()> {
         let node_record =
             NodeRecord::new_with_id(ping_msg.from.ip, ping_msg.from.tcp, ping_msg.from.udp, id)
@@ -127,6 +138,7 @@ impl DiscoverNode {
                 pinged_on: None,
                 pong_received_on: None,
                 is_bsc_node: None,
+                fork_id: None,
             });
         }
 
@@ -170,6 +182,7 @@ impl TryFrom<NodeRecord> for DiscoverNode {
             pong_received_on: None,
             ping_received_on: None,
             is_bsc_node: Some(true),
+            fork_id: None,
         })
     }
 }
@@ -191,9 +204,25 @@ impl TryFrom<NeighborNodeRecord> for DiscoverNode {
                 pong_received_on: None,
                 ping_received_on: None,
                 is_bsc_node: None,
+                fork_id: None,
             });
         }
 
         Err(())
     }
 }
+
+impl TryFrom<&StoredNode> for DiscoverNode {
+    type Error = ();
+
+    fn try_from(stored: &StoredNode) -> Result<Self, Self::Error> {
+        let mut node = DiscoverNode::try_from(stored.enode.parse::<NodeRecord>().map_err(|_| ())?)?;
+        node.node_type = stored.node_type.clone();
+        node.is_bsc_node = stored.is_bsc_node;
+        node.fork_id = stored.fork_id;
+        node.pong_received_on = stored.pong_received_at.and_then(unix_to_instant);
+        node.ping_received_on = stored.ping_received_at.and_then(unix_to_instant);
+
+        Ok(node)
+    }
+}
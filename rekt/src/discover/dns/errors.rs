@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DnsDiscoveryError {
+    #[error("invalid tree url: {0}")]
+    InvalidUrl(String),
+    #[error("invalid tree entry: {0}")]
+    InvalidEntry(String),
+    #[error("invalid root signature")]
+    InvalidSignature,
+    /// Entry content doesn't hash to the subdomain it was found at
+    #[error("hash mismatch of {0}")]
+    HashMismatch(String),
+    #[error("no tree entry at {0}")]
+    MissingEntry(String),
+    #[error("dns lookup of {0} failed: {1}")]
+    Lookup(String, String),
+}
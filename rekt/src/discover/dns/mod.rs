@@ -0,0 +1,382 @@
+pub mod errors;
+pub mod resolver;
+pub mod tree;
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use enr::Enr;
+use futures::{stream, StreamExt};
+use secp256k1::SecretKey;
+
+use crate::blockchain::head_tracker::validate_fork_id;
+use crate::discover::messages::enr::enr_fork_id;
+use crate::types::node_record::NodeRecord;
+
+use errors::DnsDiscoveryError;
+use resolver::Resolver;
+use tree::{entry_hash, Entry, Root, TreeUrl};
+
+/// Limit of entries read from one tree, real trees have few thousand
+const MAX_TREE_ENTRIES: usize = 20_000;
+
+/// Limit of trees read through links from one url
+const MAX_LINKED_TREES: usize = 16;
+
+/// Entries of a tree level looked up at the same time
+const MAX_CONCURRENT_LOOKUPS: usize = 8;
+
+/// All trees are resolved on startup, trees not resolved within this are skipped
+pub const RESOLVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct DnsClient<R> {
+    resolver: R,
+}
+
+impl<R: Resolver> DnsClient<R> {
+    pub fn new(resolver: R) -> Self {
+        Self { resolver }
+    }
+
+    /// ENRs of the tree at `url` and of trees it links to. Only failure to read or verify the root
+    /// of `url` is an error, broken entries and linked trees are skipped
+    pub async fn resolve_tree(&self, url: &str) -> Result<Vec<Enr<SecretKey>>, DnsDiscoveryError> {
+        let url = url.parse::<TreeUrl>()?;
+        let mut visited = HashSet::from([url.domain.to_lowercase()]);
+        let mut to_resolve = vec![url];
+        let mut enrs = Vec::new();
+        let mut is_first = true;
+
+        while let Some(url) = to_resolve.pop() {
+            let root = match self.resolve_root(&url).await {
+                Ok(root) => root,
+                Err(e) if is_first => return Err(e),
+                Err(e) => {
+                    println!("Skipping linked tree {}: {}", url.domain, e);
+                    continue;
+                }
+            };
+            is_first = false;
+
+            let (tree_enrs, _) = self.walk(&url.domain, &root.enr_root).await;
+            enrs.extend(tree_enrs);
+
+            let (_, links) = self.walk(&url.domain, &root.link_root).await;
+            for link in links {
+                if visited.len() < MAX_LINKED_TREES && visited.insert(link.domain.to_lowercase()) {
+                    to_resolve.push(link);
+                }
+            }
+        }
+
+        Ok(enrs)
+    }
+
+    async fn resolve_root(&self, url: &TreeUrl) -> Result<Root, DnsDiscoveryError> {
+        let txt = self
+            .resolver
+            .lookup_txt(&url.domain)
+            .await?
+            .ok_or_else(|| DnsDiscoveryError::MissingEntry(url.domain.clone()))?;
+
+        let Entry::Root(root) = txt.parse::<Entry>()? else {
+            return Err(DnsDiscoveryError::InvalidEntry(txt));
+        };
+        if !root.verify(&url.public_key) {
+            return Err(DnsDiscoveryError::InvalidSignature);
+        }
+
+        Ok(root)
+    }
+
+    /// Reads the subtree level by level, entries of a level are looked up a few at a time
+    async fn walk(&self, domain: &str, root_hash: &str) -> (Vec<Enr<SecretKey>>, Vec<TreeUrl>) {
+        let mut enrs = Vec::new();
+        let mut links = Vec::new();
+        let mut seen = HashSet::new();
+        let mut level = vec![root_hash.to_string()];
+
+        while !level.is_empty() {
+            let entries = stream::iter(&level)
+                .map(|hash| self.lookup_entry(domain, hash))
+                .buffer_unordered(MAX_CONCURRENT_LOOKUPS)
+                .collect::<Vec<_>>()
+                .await;
+
+            let mut next_level = Vec::new();
+            for entry in entries {
+                match entry {
+                    Ok(Entry::Branch(hashes)) => next_level.extend(
+                        hashes
+                            .into_iter()
+                            .filter(|h| seen.len() < MAX_TREE_ENTRIES && seen.insert(h.clone())),
+                    ),
+                    Ok(Entry::Enr(enr)) => enrs.push(*enr),
+                    Ok(Entry::Link(link)) => links.push(link),
+                    Ok(Entry::Root(_)) => println!("Unexpected root entry inside tree {}", domain),
+                    Err(e) => println!("Skipping entry of tree {}: {}", domain, e),
+                }
+            }
+            level = next_level;
+        }
+
+        (enrs, links)
+    }
+
+    async fn lookup_entry(&self, domain: &str, hash: &str) -> Result<Entry, DnsDiscoveryError> {
+        let name = format!("{}.{}", hash, domain);
+        let txt = self
+            .resolver
+            .lookup_txt(&name)
+            .await?
+            .ok_or_else(|| DnsDiscoveryError::MissingEntry(name.clone()))?;
+
+        if !entry_hash(&txt).eq_ignore_ascii_case(hash) {
+            return Err(DnsDiscoveryError::HashMismatch(name));
+        }
+
+        txt.parse()
+    }
+}
+
+/// Dialable node of an ENR: it must have an IPv4 address and a tcp port, and its `eth` fork id, if
+/// present, must match ours
+pub fn enr_to_node_record(enr: &Enr<SecretKey>) -> Option<NodeRecord> {
+    let (ip, tcp) = (enr.ip4()?, enr.tcp4()?);
+    if enr_fork_id(enr).is_some_and(|fork_id| validate_fork_id(fork_id).is_err()) {
+        return None;
+    }
+
+    Some(NodeRecord::new(
+        IpAddr::V4(ip),
+        tcp,
+        enr.udp4().unwrap_or(tcp),
+        enr.public_key(),
+    ))
+}
+
+/// Nodes of all `enrtree://` urls, trees which can't be resolved are logged and skipped.
+/// `timeout` bounds all trees together, nodes of trees resolved before it are kept
+pub async fn resolve_enrtrees<R: Resolver>(
+    resolver: R,
+    urls: &[String],
+    timeout: Duration,
+) -> Vec<NodeRecord> {
+    let client = DnsClient::new(resolver);
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut nodes = Vec::new();
+
+    for url in urls {
+        match tokio::time::timeout_at(deadline, client.resolve_tree(url)).await {
+            Ok(Ok(enrs)) => {
+                let before = nodes.len();
+                nodes.extend(enrs.iter().filter_map(enr_to_node_record));
+                println!(
+                    "Resolved {} ENRs, {} dialable nodes from {}",
+                    enrs.len(),
+                    nodes.len() - before,
+                    url
+                );
+            }
+            Ok(Err(e)) => println!("Failed to resolve {}: {}", url, e),
+            Err(_) => println!("Timed out resolving {}", url),
+        }
+    }
+
+    nodes
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use data_encoding::BASE32_NOPAD;
+    use enr::EnrBuilder;
+    use ethers::utils::keccak256;
+    use secp256k1::{PublicKey, SECP256K1};
+
+    use super::resolver::MemoryResolver;
+    use super::*;
+
+    /// Example tree of EIP-1459, its ENRs have no endpoints
+    fn eip_example_zone() -> MemoryResolver {
+        let mut zone = MemoryResolver::default();
+        zone.insert("nodes.example.org", "enrtree-root:v1 e=JWXYDBPXYWG6FX3GMDIBFA6CJ4 l=C7HRFPF3BLGF3YR4DY5KX3SMBE seq=1 sig=o908WmNp7LibOfPsr4btQwatZJ5URBr2ZAuxvK4UWHlsB9sUOTJQaGAlLPVAhM__XJesCHxLISo94z5Z2a463gA");
+        zone.insert(
+            "C7HRFPF3BLGF3YR4DY5KX3SMBE.nodes.example.org",
+            "enrtree://AM5FCQLWIZX2QFPNJAP7VUERCCRNGRHWZG3YYHIUV7BVDQ5FDPRT2@morenodes.example.org",
+        );
+        zone.insert("JWXYDBPXYWG6FX3GMDIBFA6CJ4.nodes.example.org", "enrtree-branch:2XS2367YHAXJFGLZHVAWLQD4ZY,H4FHT4B454P6UXFD7JCYQ5PWDY,MHTDO6TMUBRIA2XWG5LUDACK24");
+        zone.insert("2XS2367YHAXJFGLZHVAWLQD4ZY.nodes.example.org", "enr:-HW4QOFzoVLaFJnNhbgMoDXPnOvcdVuj7pDpqRvh6BRDO68aVi5ZcjB3vzQRZH2IcLBGHzo8uUN3snqmgTiE56CH3AMBgmlkgnY0iXNlY3AyNTZrMaECC2_24YYkYHEgdzxlSNKQEnHhuNAbNlMlWJxrJxbAFvA");
+        zone.insert("H4FHT4B454P6UXFD7JCYQ5PWDY.nodes.example.org", "enr:-HW4QAggRauloj2SDLtIHN1XBkvhFZ1vtf1raYQp9TBW2RD5EEawDzbtSmlXUfnaHcvwOizhVYLtr7e6vw7NAf6mTuoCgmlkgnY0iXNlY3AyNTZrMaECjrXI8TLNXU0f8cthpAMxEshUyQlK-AM0PW2wfrnacNI");
+        zone.insert("MHTDO6TMUBRIA2XWG5LUDACK24.nodes.example.org", "enr:-HW4QLAYqmrwllBEnzWWs7I5Ev2IAs7x_dZlbYdRdMUx5EyKHDXp7AV5CkuPGUPdvbv1_Ms1CPfhcGCvSElSosZmyoqAgmlkgnY0iXNlY3AyNTZrMaECriawHKWdDRk2xeZkrOXBQ0dfMFLHY4eENZwdufn1S1o");
+        zone
+    }
+
+    /// Publishes a signed tree under `domain`, one branch holds all `enrs` and another all `links`
+    fn publish_tree(
+        zone: &mut MemoryResolver,
+        key: &SecretKey,
+        domain: &str,
+        enrs: &[Enr<SecretKey>],
+        links: &[String],
+    ) -> String {
+        let mut branch = |leaves: Vec<String>| {
+            let hashes = leaves
+                .iter()
+                .map(|leaf| {
+                    let hash = entry_hash(leaf);
+                    zone.insert(&format!("{}.{}", hash, domain), leaf);
+                    hash
+                })
+                .collect::<Vec<_>>();
+            let branch = format!("enrtree-branch:{}", hashes.join(","));
+            let hash = entry_hash(&branch);
+            zone.insert(&format!("{}.{}", hash, domain), &branch);
+            hash
+        };
+
+        let mut root = Root {
+            enr_root: branch(enrs.iter().map(|enr| enr.to_base64()).collect()),
+            link_root: branch(links.to_vec()),
+            seq: 1,
+            signature: Vec::new(),
+        };
+        let msg = secp256k1::Message::from_slice(&keccak256(root.signed_text())).unwrap();
+        let (rec_id, sig) = SECP256K1
+            .sign_ecdsa_recoverable(&msg, key)
+            .serialize_compact();
+        root.signature = [&sig[..], &[rec_id.to_i32() as u8]].concat();
+        zone.insert(domain, &root.to_string());
+
+        let public_key = PublicKey::from_secret_key(SECP256K1, key).serialize();
+        format!("enrtree://{}@{}", BASE32_NOPAD.encode(&public_key), domain)
+    }
+
+    fn enr(port: u16) -> Enr<SecretKey> {
+        let key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        EnrBuilder::new("v4")
+            .ip4(Ipv4Addr::new(10, 0, 0, 1))
+            .tcp4(port)
+            .udp4(port)
+            .build(&key)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn resolve_eip_example_tree() {
+        let client = DnsClient::new(eip_example_zone());
+        let enrs = client
+            .resolve_tree(
+                "enrtree://AKPYQIUQIL7PSIACI32J7FGZW56E5FKHEFCCOFHILBIMW3M6LWXS2@nodes.example.org",
+            )
+            .await
+            .unwrap();
+
+        // linked morenodes.example.org is not in the zone and is skipped
+        assert_eq!(enrs.len(), 3);
+        assert!(enrs.iter().all(|enr| enr_to_node_record(enr).is_none()));
+
+        // root signed by another key
+        let other_key =
+            "enrtree://AM5FCQLWIZX2QFPNJAP7VUERCCRNGRHWZG3YYHIUV7BVDQ5FDPRT2@nodes.example.org";
+        assert!(matches!(
+            client.resolve_tree(other_key).await,
+            Err(DnsDiscoveryError::InvalidSignature)
+        ));
+    }
+
+    #[tokio::test]
+    async fn resolve_linked_trees() {
+        let mut zone = MemoryResolver::default();
+        let (a, b) = (enr(30311), enr(30312));
+
+        let linked_key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        let linked = publish_tree(
+            &mut zone,
+            &linked_key,
+            "linked.example.org",
+            std::slice::from_ref(&b),
+            &[],
+        );
+        let key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        let url = publish_tree(
+            &mut zone,
+            &key,
+            "nodes.example.org",
+            std::slice::from_ref(&a),
+            &[linked],
+        );
+
+        // tampered entry doesn't match its hash and is dropped
+        let tampered = enr(30313);
+        zone.insert(
+            &format!("{}.nodes.example.org", entry_hash(&a.to_base64())),
+            &tampered.to_base64(),
+        );
+
+        let nodes =
+            resolve_enrtrees(zone.clone(), std::slice::from_ref(&url), RESOLVE_TIMEOUT).await;
+        assert_eq!(nodes, vec![enr_to_node_record(&b).unwrap()]);
+
+        zone.insert(
+            &format!("{}.nodes.example.org", entry_hash(&a.to_base64())),
+            &a.to_base64(),
+        );
+        let mut nodes = resolve_enrtrees(zone, &[url], RESOLVE_TIMEOUT).await;
+        nodes.sort_by_key(|n| n.tcp_port);
+        assert_eq!(
+            nodes,
+            vec![
+                enr_to_node_record(&a).unwrap(),
+                enr_to_node_record(&b).unwrap()
+            ]
+        );
+        assert_eq!(nodes[0].tcp_port, 30311);
+    }
+
+    /// Zone which answers slowly and remembers how many lookups were in flight at once
+    struct SlowZone {
+        zone: MemoryResolver,
+        in_flight: std::sync::atomic::AtomicUsize,
+        max_in_flight: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Resolver for SlowZone {
+        async fn lookup_txt(&self, name: &str) -> Result<Option<String>, DnsDiscoveryError> {
+            use std::sync::atomic::Ordering;
+
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            self.zone.lookup_txt(name).await
+        }
+    }
+
+    #[tokio::test]
+    async fn lookups_are_limited() {
+        let mut zone = MemoryResolver::default();
+        let key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        let enrs = (0..4 * MAX_CONCURRENT_LOOKUPS as u16)
+            .map(|i| enr(30000 + i))
+            .collect::<Vec<_>>();
+        let url = publish_tree(&mut zone, &key, "nodes.example.org", &enrs, &[]);
+
+        let client = DnsClient::new(SlowZone {
+            zone,
+            in_flight: Default::default(),
+            max_in_flight: Default::default(),
+        });
+        assert_eq!(client.resolve_tree(&url).await.unwrap().len(), enrs.len());
+        assert_eq!(
+            client
+                .resolver
+                .max_in_flight
+                .load(std::sync::atomic::Ordering::SeqCst),
+            MAX_CONCURRENT_LOOKUPS
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use trust_dns_client::client::{AsyncClient, ClientHandle};
+use trust_dns_client::rr::{DNSClass, Name, RData, RecordType};
+use trust_dns_client::udp::UdpClientStream;
+
+use super::errors::DnsDiscoveryError;
+
+/// Used when config doesn't set `dns_server`
+pub const DEFAULT_DNS_SERVER: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(1, 1, 1, 1), 53));
+
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Source of TXT records of a tree, so trees can be read from a real name server, a local stand-in
+/// or an in-memory zone
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// TXT record at `name`, `None` if there is no such record
+    async fn lookup_txt(&self, name: &str) -> Result<Option<String>, DnsDiscoveryError>;
+}
+
+/// Queries a name server over UDP, one client (and its background task) serves all lookups
+pub struct DnsResolver {
+    server: SocketAddr,
+    client: Mutex<Option<(AsyncClient, JoinHandle<()>)>>,
+}
+
+impl DnsResolver {
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            client: Mutex::new(None),
+        }
+    }
+
+    /// Client is created on the first lookup, and again if its background task is gone
+    async fn client(&self) -> Result<AsyncClient, String> {
+        let mut client = self.client.lock().await;
+        if let Some((client, bg)) = client.as_ref() {
+            if !bg.is_finished() {
+                return Ok(client.clone());
+            }
+        }
+
+        let stream = UdpClientStream::<UdpSocket>::with_timeout(self.server, LOOKUP_TIMEOUT);
+        let (new_client, bg) = AsyncClient::connect(stream)
+            .await
+            .map_err(|e| e.to_string())?;
+        let bg = tokio::spawn(async move {
+            let _ = bg.await;
+        });
+        Ok(client.insert((new_client, bg)).0.clone())
+    }
+
+    async fn query(&self, name: &str) -> Result<Option<String>, String> {
+        let name = Name::from_str(name).map_err(|e| e.to_string())?;
+        let mut client = self.client().await?;
+
+        let response = client
+            .query(name, DNSClass::IN, RecordType::TXT)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // long records are split into several strings of one TXT record
+        Ok(response
+            .answers()
+            .iter()
+            .find_map(|record| match record.rdata() {
+                RData::TXT(txt) => Some(
+                    txt.txt_data()
+                        .iter()
+                        .map(|part| String::from_utf8_lossy(part))
+                        .collect::<String>(),
+                ),
+                _ => None,
+            }))
+    }
+}
+
+#[async_trait]
+impl Resolver for DnsResolver {
+    async fn lookup_txt(&self, name: &str) -> Result<Option<String>, DnsDiscoveryError> {
+        self.query(name)
+            .await
+            .map_err(|e| DnsDiscoveryError::Lookup(name.to_string(), e))
+    }
+}
+
+/// Zone kept in memory, name to TXT record
+#[derive(Debug, Default, Clone)]
+pub struct MemoryResolver {
+    records: HashMap<String, String>,
+}
+
+impl MemoryResolver {
+    pub fn insert(&mut self, name: &str, txt: &str) {
+        self.records.insert(name.to_lowercase(), txt.to_string());
+    }
+}
+
+#[async_trait]
+impl Resolver for MemoryResolver {
+    async fn lookup_txt(&self, name: &str) -> Result<Option<String>, DnsDiscoveryError> {
+        Ok(self.records.get(&name.to_lowercase()).cloned())
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use data_encoding::{BASE32_NOPAD, BASE64URL_NOPAD};
+use enr::Enr;
+use ethers::utils::keccak256;
+use secp256k1::ecdsa::Signature;
+use secp256k1::{PublicKey, SecretKey, SECP256K1};
+
+use super::errors::DnsDiscoveryError;
+
+const URL_PREFIX: &str = "enrtree://";
+const ROOT_PREFIX: &str = "enrtree-root:v1";
+const BRANCH_PREFIX: &str = "enrtree-branch:";
+const ENR_PREFIX: &str = "enr:";
+
+/// Subdomains are base32 of this many bytes of keccak256 of the entry
+const HASH_SIZE: usize = 16;
+
+/*
+* EIP-1459 node list (https://eips.ethereum.org/EIPS/eip-1459)
+*
+* Tree is published as TXT records. The root, at the domain itself, is signed by the key from the
+* `enrtree://<base32 public key>@<domain>` url and points to two subtrees:
+*   enrtree-root:v1 e=<enr subtree root> l=<link subtree root> seq=<n> sig=<signature>
+* Every other entry is at `<base32(keccak256(entry)[..16])>.<domain>`, so content of the whole
+* tree is authenticated by the root signature:
+*   enrtree-branch:<hash>,<hash>,...    inner node
+*   enr:<base64 ENR>                    leaf of the ENR subtree
+*   enrtree://<key>@<domain>            leaf of the link subtree, another signed tree
+* */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeUrl {
+    pub public_key: PublicKey,
+    pub domain: String,
+}
+
+impl FromStr for TreeUrl {
+    type Err = DnsDiscoveryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DnsDiscoveryError::InvalidUrl(s.to_string());
+
+        let (key, domain) = s
+            .strip_prefix(URL_PREFIX)
+            .and_then(|rest| rest.split_once('@'))
+            .ok_or_else(invalid)?;
+        let key = BASE32_NOPAD.decode(key.as_bytes()).map_err(|_| invalid())?;
+        if domain.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            public_key: PublicKey::from_slice(&key).map_err(|_| invalid())?,
+            domain: domain.to_string(),
+        })
+    }
+}
+
+impl Display for TreeUrl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}@{}",
+            URL_PREFIX,
+            BASE32_NOPAD.encode(&self.public_key.serialize()),
+            self.domain
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Root {
+    pub enr_root: String,
+    pub link_root: String,
+    pub seq: u64,
+    /// 65 bytes, recovery id last
+    pub signature: Vec<u8>,
+}
+
+impl Root {
+    /// Text covered by the signature, the root entry without ` sig=...`
+    pub fn signed_text(&self) -> String {
+        format!(
+            "{} e={} l={} seq={}",
+            ROOT_PREFIX, self.enr_root, self.link_root, self.seq
+        )
+    }
+
+    pub fn verify(&self, public_key: &PublicKey) -> bool {
+        if self.signature.len() != 65 {
+            return false;
+        }
+
+        let msg = secp256k1::Message::from_slice(&keccak256(self.signed_text()))
+            .expect("keccak256 is 32 bytes");
+        Signature::from_compact(&self.signature[..64])
+            .and_then(|sig| SECP256K1.verify_ecdsa(&msg, &sig, public_key))
+            .is_ok()
+    }
+
+    fn parse(s: &str) -> Result<Self, DnsDiscoveryError> {
+        let invalid = || DnsDiscoveryError::InvalidEntry(s.to_string());
+
+        let mut fields = s.split_whitespace();
+        if fields.next() != Some(ROOT_PREFIX) {
+            return Err(invalid());
+        }
+        let mut field = |name: &str| {
+            fields
+                .next()
+                .and_then(|f| f.strip_prefix(name))
+                .ok_or_else(invalid)
+        };
+
+        let enr_root = field("e=")?.to_string();
+        let link_root = field("l=")?.to_string();
+        let seq = field("seq=")?.parse().map_err(|_| invalid())?;
+        let signature = BASE64URL_NOPAD
+            .decode(field("sig=")?.as_bytes())
+            .map_err(|_| invalid())?;
+        if !is_valid_hash(&enr_root) || !is_valid_hash(&link_root) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            enr_root,
+            link_root,
+            seq,
+            signature,
+        })
+    }
+}
+
+impl Display for Root {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} sig={}",
+            self.signed_text(),
+            BASE64URL_NOPAD.encode(&self.signature)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    Root(Root),
+    /// Subdomain hashes of child entries
+    Branch(Vec<String>),
+    Enr(Box<Enr<SecretKey>>),
+    Link(TreeUrl),
+}
+
+impl FromStr for Entry {
+    type Err = DnsDiscoveryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DnsDiscoveryError::InvalidEntry(s.to_string());
+
+        if s.starts_with(ROOT_PREFIX) {
+            Ok(Entry::Root(Root::parse(s)?))
+        } else if let Some(hashes) = s.strip_prefix(BRANCH_PREFIX) {
+            let hashes = hashes
+                .split(',')
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            if !hashes.iter().all(|h| is_valid_hash(h)) {
+                return Err(invalid());
+            }
+            Ok(Entry::Branch(hashes))
+        } else if s.starts_with(ENR_PREFIX) {
+            Ok(Entry::Enr(Box::new(s.parse().map_err(|_| invalid())?)))
+        } else if s.starts_with(URL_PREFIX) {
+            Ok(Entry::Link(s.parse()?))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+/// Subdomain an entry is published at
+pub fn entry_hash(entry: &str) -> String {
+    BASE32_NOPAD.encode(&keccak256(entry)[..HASH_SIZE])
+}
+
+fn is_valid_hash(hash: &str) -> bool {
+    BASE32_NOPAD
+        .decode(hash.as_bytes())
+        .is_ok_and(|h| h.len() == HASH_SIZE)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_entries() {
+        let url =
+            "enrtree://AM5FCQLWIZX2QFPNJAP7VUERCCRNGRHWZG3YYHIUV7BVDQ5FDPRT2@nodes.example.org";
+        let tree_url = url.parse::<TreeUrl>().unwrap();
+        assert_eq!(tree_url.domain, "nodes.example.org");
+        assert_eq!(tree_url.to_string(), url);
+        assert_eq!(url.parse::<Entry>().unwrap(), Entry::Link(tree_url.clone()));
+
+        let root = "enrtree-root:v1 e=JWXYDBPXYWG6FX3GMDIBFA6CJ4 l=C7HRFPF3BLGF3YR4DY5KX3SMBE seq=1 sig=o908WmNp7LibOfPsr4btQwatZJ5URBr2ZAuxvK4UWHlsB9sUOTJQaGAlLPVAhM__XJesCHxLISo94z5Z2a463gA";
+        let Entry::Root(parsed) = root.parse::<Entry>().unwrap() else {
+            panic!("not a root");
+        };
+        assert_eq!(parsed.to_string(), root);
+        // example tree of EIP-1459 is signed by another key than the example url
+        assert!(!parsed.verify(&tree_url.public_key));
+
+        let signer = "enrtree://AKPYQIUQIL7PSIACI32J7FGZW56E5FKHEFCCOFHILBIMW3M6LWXS2@n"
+            .parse::<TreeUrl>()
+            .unwrap();
+        assert!(parsed.verify(&signer.public_key));
+        let forged = Root {
+            seq: 2,
+            ..parsed.clone()
+        };
+        assert!(!forged.verify(&signer.public_key));
+
+        assert_eq!(
+            "enrtree-branch:2XS2367YHAXJFGLZHVAWLQD4ZY,H4FHT4B454P6UXFD7JCYQ5PWDY"
+                .parse::<Entry>()
+                .unwrap(),
+            Entry::Branch(vec![
+                "2XS2367YHAXJFGLZHVAWLQD4ZY".into(),
+                "H4FHT4B454P6UXFD7JCYQ5PWDY".into()
+            ])
+        );
+        assert!("enrtree-branch:notahash".parse::<Entry>().is_err());
+        assert!("v=spf1 -all".parse::<Entry>().is_err());
+    }
+}
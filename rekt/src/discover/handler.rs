@@ -1,9 +1,12 @@
 use std::collections::HashMap;
 
 use futures::{stream::FuturesUnordered, StreamExt};
+use tokio::sync::mpsc::UnboundedSender;
 
 use crate::{
-    blockchain::bsc_chain_spec::BSC_MAINNET_FORK_FILTER,
+    blockchain::{fork::ForkId, head_tracker::validate_fork_id},
+    cli::Cli,
+    local_node::LocalNode,
     server::{
         connection_task::ConnectionTask,
         errors::ConnectionTaskError,
@@ -12,7 +15,7 @@ use crate::{
             BLACKLIST_PEERS_BY_ID,
         },
     },
-    types::hash::H512,
+    types::{hash::H512, node_record::NodeRecord},
 };
 
 use super::{
@@ -25,6 +28,36 @@ use super::{
     server::Server,
 };
 
+/// Nodes found by discv4 and discv5 alike are dialed if fork id from their ENR matches ours, and
+/// blacklisted otherwise. Returns whether the fork id matched
+pub(super) fn dial_if_fork_matches(
+    fork_id: Option<ForkId>,
+    node_record: &NodeRecord,
+    local_node: &LocalNode,
+    server_config: &Cli,
+    conn_tx: &UnboundedSender<ConnectionTaskError>,
+) -> bool {
+    let forks_match = fork_id.is_some_and(|fork_id| validate_fork_id(fork_id).is_ok());
+    if !forks_match {
+        blacklist_peer(node_record);
+        return false;
+    }
+
+    let conn_task = ConnectionTask::new(
+        &node_record.str,
+        local_node.public_key,
+        local_node.private_key,
+        server_config.clone(),
+    );
+    if check_if_already_connected_to_peer(&conn_task.node).is_ok()
+        && !peer_is_blacklisted(node_record)
+    {
+        let _ = conn_tx.send(ConnectionTaskError::new_no_err(conn_task));
+    }
+
+    true
+}
+
 impl Server {
     pub(super) async fn handle_received_msg(&self, msg: DecodedDiscoverMessage) {
         match msg.msg {
@@ -62,12 +95,11 @@ impl Server {
                 if let Some(node) = &mut self.nodes.get_mut(&msg.node_id) {
                     node.mark_pong_received();
                 }
+                self.on_pong(msg.node_id);
             }
             DiscoverMessage::EnrRequest(_) => {
-                let enr_response = DiscoverMessage::EnrResponse(EnrResponse::new(
-                    msg.hash,
-                    self.local_node.enr.clone(),
-                ));
+                let enr_response =
+                    DiscoverMessage::EnrResponse(EnrResponse::new(msg.hash, self.local_node.enr()));
                 let packet = DiscoverMessage::create_disc_v4_packet(
                     enr_response,
                     &self.local_node.private_key,
@@ -75,37 +107,23 @@ impl Server {
                 let _ = self.udp_sender.send((msg.from, packet));
             }
             DiscoverMessage::EnrResponse(resp) => {
-                let forks_match = {
-                    if let Some(fork_id) = resp.eth_fork_id() {
-                        BSC_MAINNET_FORK_FILTER.validate(fork_id).is_ok()
-                    } else {
-                        false
-                    }
-                };
+                let fork_id = resp.eth_fork_id();
 
                 if let Some(node) = &mut self.nodes.get_mut(&msg.node_id) {
+                    node.fork_id = fork_id;
+                    let forks_match = dial_if_fork_matches(
+                        fork_id,
+                        &node.node_record,
+                        &self.local_node,
+                        &self.server_config,
+                        &self.conn_tx,
+                    );
                     node.set_is_bsc(forks_match);
+                }
 
-                    if forks_match {
-                        let conn_task = ConnectionTask::new(
-                            &node.node_record.str,
-                            self.local_node.public_key,
-                            self.local_node.private_key,
-                            self.server_config.clone(),
-                        );
-                        if let Err(_) = check_if_already_connected_to_peer(&conn_task.node) {
-                            return;
-                        }
-
-                        if peer_is_blacklisted(&node.node_record) {
-                            return;
-                        }
-                        let _ = self
-                            .conn_tx
-                            .send(ConnectionTaskError::new_no_err(conn_task));
-                    } else {
-                        blacklist_peer(&node.node_record);
-                    }
+                // nodes speaking discv4 often speak discv5 too, their ENRs seed discv5
+                if let Some(discv5) = self.discv5.get() {
+                    discv5.add_enr(resp.enr);
                 }
             }
             DiscoverMessage::Neighbours(neighbours) => {
@@ -2,62 +2,78 @@ use std::collections::{btree_map::Entry, BTreeMap};
 
 use dashmap::DashMap;
 
+use ethers::utils::keccak256;
+
 use crate::{
     discover::{
         discover_node::{AuthStatus, DiscoverNode},
         server::Server,
+        table::{distance, BUCKET_SIZE},
     },
-    types::hash::H512,
+    types::hash::{H256, H512},
 };
 
-const ALPHA: usize = 300;
+/// Nodes queried in parallel for every neighbours response of a lookup
+const ALPHA: usize = 3;
+
+/// Random lookups besides the lookup of our own id when buckets are refreshed
+const REFRESH_RANDOM_LOOKUPS: usize = 3;
 
 impl Server {
     pub fn get_next_lookup_id(&self) -> H512 {
         H512::random()
     }
 
-    pub fn get_closest_nodes(&self, lookup_id: H512) -> Vec<DiscoverNode> {
-        let mut nodes = self
-            .nodes
-            .iter()
-            //NOTE: maybe just return BSC nodes?
-            // think about this when we implement requesting ENR
-            .filter(|n| n.auth_status() == AuthStatus::Authed)
-            .map(|n| n.value().clone())
-            .collect::<Vec<DiscoverNode>>();
-
-        nodes.sort_by(|a, b| {
-            let a_distance = a.id().distance(&lookup_id);
-            let b_distance = b.id().distance(&lookup_id);
-
-            a_distance.cmp(&b_distance)
-        });
+    /// Targets of the lookups to run now: a random one, plus our own id and few random ids when
+    /// the table is due for refresh
+    pub fn get_lookup_ids(&self) -> Vec<H512> {
+        let mut ids = vec![self.get_next_lookup_id()];
+        if self
+            .table
+            .lock()
+            .unwrap()
+            .should_refresh(std::time::Instant::now())
+        {
+            ids.push(self.local_node.node_record.id);
+            ids.extend((0..REFRESH_RANDOM_LOOKUPS).map(|_| self.get_next_lookup_id()));
+        }
+        ids
+    }
 
-        nodes.into_iter().take(ALPHA).collect()
+    /// `BUCKET_SIZE` nodes from the table closest to the lookup id
+    pub fn get_closest_nodes(&self, lookup_id: H512) -> Vec<DiscoverNode> {
+        let ids = self.table.lock().unwrap().closest(&lookup_id, BUCKET_SIZE);
+        ids.iter()
+            .filter_map(|id| self.nodes.get(id).map(|n| n.value().clone()))
+            .collect()
     }
 }
 
 pub struct Lookup {
     pub lookup_id: H512,
-    pub closest_nodes: BTreeMap<H512, LookupNode>,
+    /// Keccak of the lookup id, distances to it order the nodes
+    lookup_hash: H256,
+    /// `BUCKET_SIZE` closest nodes we know of, by distance
+    pub closest_nodes: BTreeMap<H256, LookupNode>,
     pub queried_count: usize,
     pub responded_count: usize,
 }
 
 impl Lookup {
     pub fn new(lookup_id: H512, closest_nodes: Vec<DiscoverNode>) -> Self {
+        let lookup_hash = H256(keccak256(lookup_id));
         let closest_nodes = closest_nodes
             .into_iter()
             .map(|n| {
                 let mut node = LookupNode::from(n);
                 node.request_sent = true;
-                (node.node.id().distance(&lookup_id), node)
+                (distance(&node.node.id(), &lookup_hash), node)
             })
-            .collect::<BTreeMap<H512, LookupNode>>();
+            .collect::<BTreeMap<H256, LookupNode>>();
 
         Self {
             lookup_id,
+            lookup_hash,
             closest_nodes,
             queried_count: 0,
             responded_count: 0,
@@ -91,11 +107,16 @@ impl Lookup {
         nodes.into_iter().for_each(|node| {
             if let Entry::Vacant(entry) = self
                 .closest_nodes
-                .entry(node.id().distance(&self.lookup_id))
+                .entry(distance(&node.id(), &self.lookup_hash))
             {
                 entry.insert(node.into());
             }
         });
+
+        // lookup converges on the closest nodes, farther ones are not worth querying
+        while self.closest_nodes.len() > BUCKET_SIZE {
+            self.closest_nodes.pop_last();
+        }
     }
 
     pub fn get_next_nodes_to_query(
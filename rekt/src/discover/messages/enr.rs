@@ -37,7 +37,12 @@ impl EnrResponse {
     ///
     /// See also <https://github.com/ethereum/go-ethereum/blob/9244d5cd61f3ea5a7645fdf2a1a96d53421e412f/eth/protocols/eth/discovery.go#L36>
     pub fn eth_fork_id(&self) -> Option<ForkId> {
-        let mut maybe_fork_id = self.enr.get(b"eth")?;
-        ForkId::decode(&mut maybe_fork_id).ok()
+        enr_fork_id(&self.enr)
     }
 }
+
+/// [`ForkId`] from the `eth` entry of an ENR, the same for ENRs from discv4 and discv5
+pub fn enr_fork_id(enr: &enr::Enr<secp256k1::SecretKey>) -> Option<ForkId> {
+    let mut maybe_fork_id = enr.get(b"eth")?;
+    ForkId::decode(&mut maybe_fork_id).ok()
+}
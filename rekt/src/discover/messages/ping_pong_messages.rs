@@ -61,7 +61,7 @@ impl PingMessage {
             from: Endpoint::from(&our_node.node_record),
             to: Endpoint::from(target_node),
             expiration: expires,
-            enr_seq: our_node.enr.seq(),
+            enr_seq: our_node.enr().seq(),
         }
     }
 }
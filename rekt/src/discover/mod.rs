@@ -1,6 +1,10 @@
 pub mod decoder;
 pub mod discover_node;
+pub mod dns;
 pub mod handler;
 pub mod lookup;
 pub mod messages;
+pub mod node_db;
 pub mod server;
+pub mod table;
+pub mod v5;
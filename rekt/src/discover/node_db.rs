@@ -0,0 +1,226 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::fork::ForkId;
+use crate::types::hash::H512;
+
+use super::discover_node::{AuthStatus, DiscoverNode, DiscoverNodeType};
+
+pub const DEFAULT_NODE_DB_FILE: &str = "nodes.json";
+
+/// Nodes not seen for this long are not written back to the db
+const NODE_DB_MAX_AGE: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+/// Outcome of our last dial to a node, with unix time of the attempt
+static CONNECTION_OUTCOMES: Lazy<DashMap<H512, (ConnectionOutcome, u64)>> = Lazy::new(DashMap::new);
+
+/*
+* Discovered nodes are written to a json file every now and then (and on shutdown), and loaded
+* back when discovery server starts, so after a restart we dial BSC nodes we already know
+* instead of re-crawling the network for them.
+*
+* `Instant`s can't be persisted, so ping/pong times are stored as unix time and turned back into
+* `Instant`s on load, this keeps 12h auth window of discv4 intact across restarts.
+* */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionOutcome {
+    Connected,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredNode {
+    pub enode: String,
+    pub node_type: DiscoverNodeType,
+    /// Auth status at the time of saving, informational only, it is derived from ping/pong times
+    pub auth_status: AuthStatus,
+    pub last_seen: Option<u64>,
+    pub pong_received_at: Option<u64>,
+    pub ping_received_at: Option<u64>,
+    pub is_bsc_node: Option<bool>,
+    pub fork_id: Option<ForkId>,
+    pub last_connection: Option<ConnectionOutcome>,
+    pub last_connection_at: Option<u64>,
+}
+
+pub fn record_connection_outcome(id: H512, outcome: ConnectionOutcome) {
+    CONNECTION_OUTCOMES.insert(id, (outcome, unix_now()));
+}
+
+pub fn connection_outcome(id: &H512) -> Option<(ConnectionOutcome, u64)> {
+    CONNECTION_OUTCOMES.get(id).map(|o| o.value().clone())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub(super) fn instant_to_unix(instant: Instant) -> u64 {
+    unix_now().saturating_sub(instant.elapsed().as_secs())
+}
+
+/// `None` if the time is before the start of the monotonic clock (e.g. machine rebooted since)
+pub(super) fn unix_to_instant(unix: u64) -> Option<Instant> {
+    Instant::now().checked_sub(Duration::from_secs(unix_now().saturating_sub(unix)))
+}
+
+pub struct NodeDb {
+    path: PathBuf,
+}
+
+impl NodeDb {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Missing or unreadable db is not an error, discovery just starts from bootnodes
+    pub fn load(&self) -> Vec<DiscoverNode> {
+        let stored = match fs::read(&self.path) {
+            Ok(bytes) => match serde_json::from_slice::<Vec<StoredNode>>(&bytes) {
+                Ok(stored) => stored,
+                Err(e) => {
+                    println!("Could not parse node db {}: {}", self.path.display(), e);
+                    return Vec::new();
+                }
+            },
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    println!("Could not read node db {}: {}", self.path.display(), e);
+                }
+                return Vec::new();
+            }
+        };
+
+        stored
+            .into_iter()
+            .filter_map(|stored| {
+                let node = DiscoverNode::try_from(&stored).ok()?;
+                if let (Some(outcome), Some(at)) =
+                    (stored.last_connection, stored.last_connection_at)
+                {
+                    CONNECTION_OUTCOMES
+                        .entry(node.id())
+                        .or_insert((outcome, at));
+                }
+                Some(node)
+            })
+            .collect()
+    }
+
+    /// Writes nodes seen within `NODE_DB_MAX_AGE` (and static nodes), returns how many were written
+    pub fn save(&self, nodes: &DashMap<H512, DiscoverNode>) -> io::Result<usize> {
+        let oldest = unix_now().saturating_sub(NODE_DB_MAX_AGE.as_secs());
+        let stored = nodes
+            .iter()
+            .map(|n| n.to_stored())
+            .filter(|n| {
+                n.node_type == DiscoverNodeType::Static || n.last_seen.is_some_and(|t| t >= oldest)
+            })
+            .collect::<Vec<_>>();
+
+        let bytes = serde_json::to_vec(&stored)?;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        // written aside and renamed, so a crash mid write doesn't leave us with half a db
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &self.path)?;
+
+        Ok(stored.len())
+    }
+}
+
+impl DiscoverNode {
+    pub(super) fn to_stored(&self) -> StoredNode {
+        let (pong_received_on, ping_received_on) = self.auth_times();
+        let pong_received_at = pong_received_on.map(instant_to_unix);
+        let ping_received_at = ping_received_on.map(instant_to_unix);
+        let connection = connection_outcome(&self.id());
+
+        let last_seen = [
+            pong_received_at,
+            ping_received_at,
+            connection
+                .as_ref()
+                .filter(|(outcome, _)| *outcome == ConnectionOutcome::Connected)
+                .map(|(_, at)| *at),
+        ]
+        .into_iter()
+        .flatten()
+        .max();
+
+        StoredNode {
+            enode: self.node_record.str.clone(),
+            node_type: self.node_type.clone(),
+            auth_status: self.auth_status(),
+            last_seen,
+            pong_received_at,
+            ping_received_at,
+            is_bsc_node: self.is_bsc_node,
+            fork_id: self.fork_id,
+            last_connection_at: connection.as_ref().map(|(_, at)| *at),
+            last_connection: connection.map(|(outcome, _)| outcome),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ENODE: &str = "enode://6f8a80d14311c39f35f516fa664deaaaa13e85b2f7493f37f6144d86991ec012937307647bd3b9a82abe2974e1407241d54947bbb39763a4cac9f77166ad92a0@10.3.58.6:30303";
+
+    #[test]
+    fn nodes_survive_restart() {
+        let path = std::env::temp_dir()
+            .join(format!("rekt-nodedb-{}", std::process::id()))
+            .join(DEFAULT_NODE_DB_FILE);
+        let db = NodeDb::new(&path);
+        assert!(db.load().is_empty());
+
+        let mut node = DiscoverNode::try_from(
+            ENODE
+                .parse::<crate::types::node_record::NodeRecord>()
+                .unwrap(),
+        )
+        .unwrap();
+        node.mark_pong_received();
+        node.fork_id = Some(ForkId {
+            hash: crate::blockchain::fork::ForkHash([0xb2, 0x03, 0x04, 0x05]),
+            next: 0,
+        });
+        record_connection_outcome(
+            node.id(),
+            ConnectionOutcome::Failed("too many peers".into()),
+        );
+
+        let nodes = DashMap::new();
+        nodes.insert(node.id(), node.clone());
+        assert_eq!(db.save(&nodes).unwrap(), 1);
+
+        let loaded = db.load();
+        assert_eq!(loaded.len(), 1);
+        let stored = loaded[0].to_stored();
+        assert_eq!(stored, node.to_stored());
+        assert_eq!(stored.auth_status, AuthStatus::WeAuthedThem);
+        assert_eq!(stored.is_bsc_node, Some(true));
+        assert_eq!(
+            stored.last_connection,
+            Some(ConnectionOutcome::Failed("too many peers".into()))
+        );
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -1,34 +1,42 @@
 use std::io;
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use bytes::Bytes;
 use dashmap::DashMap;
 use futures::stream::FuturesUnordered;
+use once_cell::sync::OnceCell;
 use tokio::net::UdpSocket;
 use tokio::sync::mpsc::{self, UnboundedReceiver};
 use tokio::time::interval;
 use tokio_stream::StreamExt;
 
 use crate::constants::DEFAULT_PORT;
-use crate::discover::decoder::packet_size_is_valid;
+use crate::discover::decoder::is_v4_packet;
 use crate::discover::discover_node::AuthStatus;
 use crate::local_node::LocalNode;
-use crate::p2p::peer::is_buy_in_progress;
+use crate::server::connection_task::ConnectionTask;
 use crate::server::errors::ConnectionTaskError;
+use crate::server::peers::peer_is_blacklisted;
 use crate::types::hash::H512;
 use crate::types::node_record::NodeRecord;
 
 use super::decoder::{decode_msg_and_create_response, MAX_PACKET_SIZE};
-use super::discover_node::DiscoverNode;
+use super::discover_node::{DiscoverNode, DiscoverNodeType};
 use super::lookup::{Lookup, PendingNeighboursReq};
 use super::messages::discover_message::{DiscoverMessage, DEFAULT_MESSAGE_EXPIRATION};
+use super::node_db::NodeDb;
+use super::table::Table;
+use super::v5::service::Discv5;
 
 use super::messages::enr::EnrRequest;
 use super::messages::find_node::FindNode;
 use super::messages::ping_pong_messages::PingMessage;
 
+/// How often discovered nodes are written to the node db
+const NODE_DB_SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
 pub struct Server {
     pub(super) local_node: LocalNode,
     udp_socket: Arc<UdpSocket>,
@@ -36,6 +44,10 @@ pub struct Server {
     pub(super) udp_sender: mpsc::UnboundedSender<(SocketAddr, Bytes)>,
 
     pub(super) nodes: DashMap<H512, DiscoverNode>,
+    /// Kademlia buckets of nodes from `nodes` which answered our ping
+    pub(super) table: Mutex<Table>,
+    /// Table entries pinged to check they are still alive
+    pub(super) pending_revalidations: DashMap<H512, std::time::Instant>,
 
     pub(super) pending_pings: DashMap<H512, std::time::Instant>,
 
@@ -47,6 +59,13 @@ pub struct Server {
     pub(super) is_paused: AtomicBool,
 
     pub(super) server_config: crate::cli::Cli,
+
+    node_db: NodeDb,
+
+    /// discv5 is seeded with ENRs discv4 learns about
+    pub(super) discv5: OnceCell<Arc<Discv5>>,
+    /// discv5 sharing our UDP port also gets packets which are not discv4
+    discv5_shares_port: AtomicBool,
 }
 
 impl Server {
@@ -56,6 +75,7 @@ impl Server {
         conn_tx: tokio::sync::mpsc::UnboundedSender<ConnectionTaskError>,
         udp_sender: mpsc::UnboundedSender<(SocketAddr, Bytes)>,
         server_config: crate::cli::Cli,
+        node_db: NodeDb,
     ) -> Result<Self, io::Error> {
         let udp_socket = Arc::new(
             UdpSocket::bind(SocketAddr::V4(SocketAddrV4::new(
@@ -65,18 +85,44 @@ impl Server {
             .await?,
         );
 
-        let nodes = DashMap::from_iter(
-            nodes
-                .into_iter()
-                .filter_map(|n| n.parse::<NodeRecord>().ok())
-                .filter_map(|n| DiscoverNode::try_from(n).ok())
-                .map(|n| (n.node_record.id, n)),
-        );
+        let known_nodes =
+            DashMap::from_iter(node_db.load().into_iter().map(|n| (n.node_record.id, n)));
+        println!("Loaded {} nodes from node db", known_nodes.len());
+
+        // static nodes from config stay static, even if db remembers them as discovered
+        nodes
+            .into_iter()
+            .filter_map(|n| n.parse::<NodeRecord>().ok())
+            .filter_map(|n| DiscoverNode::try_from(n).ok())
+            .for_each(|n| match known_nodes.entry(n.node_record.id) {
+                dashmap::mapref::entry::Entry::Occupied(mut entry) => {
+                    entry.get_mut().node_type = DiscoverNodeType::Static;
+                    entry.get_mut().set_is_bsc(true);
+                }
+                dashmap::mapref::entry::Entry::Vacant(entry) => {
+                    entry.insert(n);
+                }
+            });
+
+        let mut table = Table::new(local_node.node_record.id);
+        known_nodes
+            .iter()
+            .filter(|n| {
+                matches!(
+                    n.auth_status(),
+                    AuthStatus::Authed | AuthStatus::WeAuthedThem
+                )
+            })
+            .for_each(|n| {
+                table.add_seen(n.id());
+            });
 
         Ok(Self {
             local_node,
+            table: Mutex::new(table),
+            pending_revalidations: DashMap::with_capacity(100),
             udp_socket,
-            nodes,
+            nodes: known_nodes,
             udp_sender,
             conn_tx,
             server_config,
@@ -84,9 +130,19 @@ impl Server {
             pending_neighbours_req: DashMap::with_capacity(100),
             pending_lookups: DashMap::with_capacity(100),
             is_paused: AtomicBool::new(false),
+            node_db,
+            discv5: OnceCell::new(),
+            discv5_shares_port: AtomicBool::new(false),
         })
     }
 
+    /// `shares_port` when discv5 runs on our port, the one on its own port reads its own packets
+    pub fn attach_discv5(&self, discv5: Arc<Discv5>, shares_port: bool) {
+        self.discv5_shares_port
+            .store(shares_port, std::sync::atomic::Ordering::Relaxed);
+        let _ = self.discv5.set(discv5);
+    }
+
     pub fn is_paused(&self) -> bool {
         self.is_paused.load(std::sync::atomic::Ordering::Relaxed)
     }
@@ -132,11 +188,6 @@ impl Server {
                     continue;
                 }
 
-                if is_buy_in_progress() {
-                    tokio::time::sleep(std::time::Duration::from_secs(120)).await;
-                    continue;
-                }
-
                 let _ = udp_socket.send_to(&packet, dest).await;
             }
         }
@@ -152,18 +203,18 @@ impl Server {
                 continue;
             }
 
-            if is_buy_in_progress() {
-                tokio::time::sleep(std::time::Duration::from_secs(120)).await;
-                continue;
-            }
-
             if let Ok((size, src)) = packet {
-                if !packet_size_is_valid(size) {
-                    continue;
-                }
-
-                if let Ok(msg) = decode_msg_and_create_response(src, &buf[..size]) {
-                    self.handle_received_msg(msg).await;
+                if is_v4_packet(&buf[..size]) {
+                    if let Ok(msg) = decode_msg_and_create_response(src, &buf[..size]) {
+                        self.handle_received_msg(msg).await;
+                    }
+                } else if self
+                    .discv5_shares_port
+                    .load(std::sync::atomic::Ordering::Relaxed)
+                {
+                    if let Some(discv5) = self.discv5.get() {
+                        discv5.handle_packet(src, &buf[..size]);
+                    }
                 }
             }
         }
@@ -183,9 +234,13 @@ impl Server {
             n.mark_ping_attempt();
         }
 
+        self.ping(id, &node_record, ip, udp);
+    }
+
+    fn ping(&self, id: H512, node_record: &NodeRecord, ip: Ipv4Addr, udp: u16) {
         self.pending_pings.insert(id, std::time::Instant::now());
         let packet = DiscoverMessage::create_disc_v4_packet(
-            DiscoverMessage::Ping(PingMessage::new(&self.local_node, &node_record)),
+            DiscoverMessage::Ping(PingMessage::new(&self.local_node, node_record)),
             &self.local_node.private_key,
         );
 
@@ -194,6 +249,48 @@ impl Server {
             .send((SocketAddr::V4(SocketAddrV4::new(ip, udp)), packet));
     }
 
+    /// Node answered our ping, it goes to (or to the front of) its bucket
+    pub(super) fn on_pong(&self, id: H512) {
+        self.pending_revalidations.remove(&id);
+        if self.nodes.contains_key(&id) {
+            self.table.lock().unwrap().add_seen(id);
+        }
+    }
+
+    /*
+     * Entries that didn't answer revalidation ping in time are dropped from the table (replacement
+     * takes their place), then least recently seen node of a random bucket is pinged.
+     * Runs once per worker tick, every `DEFAULT_MESSAGE_EXPIRATION` seconds.
+     * Unlike regular pings this one is sent even if the node was authed recently, that is the point.
+     * */
+    fn revalidate(&self) {
+        let dead = self
+            .pending_revalidations
+            .iter()
+            .filter(|r| r.elapsed().as_secs() >= DEFAULT_MESSAGE_EXPIRATION)
+            .map(|r| *r.key())
+            .collect::<Vec<_>>();
+        for id in dead {
+            self.pending_revalidations.remove(&id);
+            self.table.lock().unwrap().remove(&id);
+        }
+
+        let Some(id) = self.table.lock().unwrap().node_to_revalidate() else {
+            return;
+        };
+        if self.pending_revalidations.contains_key(&id) {
+            return;
+        }
+
+        let Some(node) = self.nodes.get(&id).map(|n| n.value().clone()) else {
+            self.table.lock().unwrap().remove(&id);
+            return;
+        };
+        self.pending_revalidations
+            .insert(id, std::time::Instant::now());
+        self.ping(id, &node.node_record, node.ip_v4_addr, node.udp_port());
+    }
+
     pub(super) async fn send_neighbours_packet(&self, lookup_id: H512, to: (Ipv4Addr, u16)) {
         let packet = DiscoverMessage::create_disc_v4_packet(
             DiscoverMessage::FindNode(FindNode::new(lookup_id)),
@@ -218,7 +315,44 @@ impl Server {
             .send((SocketAddr::V4(SocketAddrV4::new(ip, udp)), packet));
     }
 
+    pub fn save_node_db(&self) {
+        match self.node_db.save(&self.nodes) {
+            Ok(count) => println!("Saved {} nodes to node db", count),
+            Err(e) => println!("Could not save node db: {}", e),
+        }
+    }
+
+    /// BSC nodes we know from previous runs are dialed right away, static nodes are dialed by
+    /// `OutboundConnections` already
+    fn dial_known_bsc_nodes(&self) {
+        let mut dialed = 0;
+        for n in self
+            .nodes
+            .iter()
+            .filter(|n| n.is_bsc() && n.node_type != DiscoverNodeType::Static)
+        {
+            if peer_is_blacklisted(&n.node_record) {
+                continue;
+            }
+
+            let conn_task = ConnectionTask::new(
+                &n.node_record.str,
+                self.local_node.public_key,
+                self.local_node.private_key,
+                self.server_config.clone(),
+            );
+            let _ = self
+                .conn_tx
+                .send(ConnectionTaskError::new_no_err(conn_task));
+            dialed += 1;
+        }
+
+        println!("Dialing {} known BSC nodes from node db", dialed);
+    }
+
     async fn run_worker(&self) -> anyhow::Result<()> {
+        self.dial_known_bsc_nodes();
+
         let tasks = FuturesUnordered::from_iter(self.nodes.iter().map(|n| {
             self.send_ping_packet((n.id(), n.node_record.clone(), n.ip_v4_addr, n.udp_port()))
         }));
@@ -228,17 +362,13 @@ impl Server {
         let mut stream = tokio_stream::wrappers::IntervalStream::new(interval(
             std::time::Duration::from_secs(DEFAULT_MESSAGE_EXPIRATION),
         ));
+        let mut node_db_saved_on = std::time::Instant::now();
 
         while let Some(_) = stream.next().await {
             if self.is_paused() {
                 continue;
             }
 
-            if is_buy_in_progress() {
-                tokio::time::sleep(std::time::Duration::from_secs(120)).await;
-                continue;
-            }
-
             self.pending_pings
                 .retain(|_, v| v.elapsed().as_secs() < DEFAULT_MESSAGE_EXPIRATION);
 
@@ -269,23 +399,26 @@ impl Server {
             );
             let _result = tasks.collect::<Vec<_>>().await;
 
-            let next_lookup_id = self.get_next_lookup_id();
-            let closest_nodes = self.get_closest_nodes(next_lookup_id);
-            self.pending_lookups.insert(
-                next_lookup_id,
-                Lookup::new(next_lookup_id, closest_nodes.clone()),
-            );
+            self.revalidate();
 
-            for n in closest_nodes.iter() {
-                self.pending_neighbours_req
-                    .insert(n.id(), PendingNeighboursReq::new(next_lookup_id, n));
-            }
+            for next_lookup_id in self.get_lookup_ids() {
+                let closest_nodes = self.get_closest_nodes(next_lookup_id);
+                self.pending_lookups.insert(
+                    next_lookup_id,
+                    Lookup::new(next_lookup_id, closest_nodes.clone()),
+                );
 
-            let tasks = FuturesUnordered::from_iter(closest_nodes.iter().map(|n| {
-                self.send_neighbours_packet(next_lookup_id, (n.ip_v4_addr, n.udp_port()))
-            }));
+                for n in closest_nodes.iter() {
+                    self.pending_neighbours_req
+                        .insert(n.id(), PendingNeighboursReq::new(next_lookup_id, n));
+                }
 
-            let _result = tasks.collect::<Vec<_>>().await;
+                let tasks = FuturesUnordered::from_iter(closest_nodes.iter().map(|n| {
+                    self.send_neighbours_packet(next_lookup_id, (n.ip_v4_addr, n.udp_port()))
+                }));
+
+                let _result = tasks.collect::<Vec<_>>().await;
+            }
 
             let tasks = FuturesUnordered::from_iter(
                 self.nodes
@@ -299,6 +432,11 @@ impl Server {
             );
 
             let _result = tasks.collect::<Vec<_>>().await;
+
+            if node_db_saved_on.elapsed() >= NODE_DB_SAVE_INTERVAL {
+                self.save_node_db();
+                node_db_saved_on = std::time::Instant::now();
+            }
         }
 
         Ok(())
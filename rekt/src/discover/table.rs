@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use ethers::utils::keccak256;
+use rand::Rng;
+
+use crate::types::hash::{H256, H512};
+
+/// Nodes per bucket, `k` of Kademlia
+pub const BUCKET_SIZE: usize = 16;
+
+/// Candidates which take place of a bucket entry that stops answering pings
+const MAX_REPLACEMENTS: usize = 10;
+
+/// One bucket per log distance 1..=256, distance 0 is our own id
+const NUM_BUCKETS: usize = 256;
+
+/// How often we look up our own id and few random ids to keep buckets filled
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/*
+* Kademlia routing table of discv4. Distance between nodes is xor of keccak256 of their ids, bucket
+* of a node is the log distance (index of the highest differing bit) between it and us.
+*
+* Only nodes which answered our ping (proved their endpoint) are put into buckets, most recently
+* seen first. When a bucket is full new nodes go to its replacement cache, and get promoted when
+* revalidation finds a bucket entry dead, so buckets are only ever emptied by nodes that stop
+* responding, never by new nodes pushing out good old ones.
+*
+* Table stores only ids, everything else about a node is in `Server.nodes`.
+* */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    id: H512,
+    hash: H256,
+}
+
+impl Entry {
+    fn new(id: H512) -> Self {
+        Self {
+            id,
+            hash: H256(keccak256(id)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct KBucket {
+    /// Most recently seen first
+    entries: VecDeque<Entry>,
+    /// Most recently seen first
+    replacements: VecDeque<Entry>,
+}
+
+impl KBucket {
+    fn bump_or_insert(&mut self, entry: Entry) -> bool {
+        if let Some(pos) = self.entries.iter().position(|e| e.id == entry.id) {
+            self.entries.remove(pos);
+            self.entries.push_front(entry);
+            return true;
+        }
+
+        if self.entries.len() < BUCKET_SIZE {
+            self.entries.push_front(entry);
+            self.replacements.retain(|e| e.id != entry.id);
+            return true;
+        }
+
+        self.replacements.retain(|e| e.id != entry.id);
+        self.replacements.push_front(entry);
+        self.replacements.truncate(MAX_REPLACEMENTS);
+        false
+    }
+
+    fn remove(&mut self, id: &H512) -> bool {
+        let Some(pos) = self.entries.iter().position(|e| e.id == *id) else {
+            self.replacements.retain(|e| e.id != *id);
+            return false;
+        };
+
+        self.entries.remove(pos);
+        if let Some(replacement) = self.replacements.pop_front() {
+            self.entries.push_back(replacement);
+        }
+        true
+    }
+}
+
+pub struct Table {
+    local_hash: H256,
+    buckets: Vec<KBucket>,
+    last_refresh: Option<Instant>,
+}
+
+/// Index of the highest differing bit of two hashes (1..=256), 0 for the same hashes
+pub fn log_distance(a: &H256, b: &H256) -> usize {
+    for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+        let x = a ^ b;
+        if x != 0 {
+            return (32 - i) * 8 - x.leading_zeros() as usize;
+        }
+    }
+    0
+}
+
+/// Distance used by discv4 to order nodes for a lookup target
+pub fn distance(id: &H512, target: &H256) -> H256 {
+    H256(keccak256(id)) ^ *target
+}
+
+impl Table {
+    pub fn new(local_id: H512) -> Self {
+        Self {
+            local_hash: H256(keccak256(local_id)),
+            buckets: (0..NUM_BUCKETS).map(|_| KBucket::default()).collect(),
+            last_refresh: None,
+        }
+    }
+
+    fn bucket_index(&self, hash: &H256) -> Option<usize> {
+        log_distance(&self.local_hash, hash).checked_sub(1)
+    }
+
+    /// Node answered our ping, returns false if its bucket is full and it went to replacements
+    pub fn add_seen(&mut self, id: H512) -> bool {
+        let entry = Entry::new(id);
+        match self.bucket_index(&entry.hash) {
+            Some(index) => self.buckets[index].bump_or_insert(entry),
+            None => false,
+        }
+    }
+
+    /// Node failed revalidation, its place is taken by the most recently seen replacement
+    pub fn remove(&mut self, id: &H512) -> bool {
+        let hash = H256(keccak256(id));
+        match self.bucket_index(&hash) {
+            Some(index) => self.buckets[index].remove(id),
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, id: &H512) -> bool {
+        let hash = H256(keccak256(id));
+        self.bucket_index(&hash)
+            .is_some_and(|index| self.buckets[index].entries.iter().any(|e| e.id == *id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|b| b.entries.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /*
+     * For a target at log distance `t` from us, nodes of bucket `t` are the closest ones (their
+     * highest differing bit from target is below `t`), then come all buckets below `t` (highest
+     * differing bit is exactly `t`), then buckets above `t` in increasing order.
+     * Buckets are visited in that order until `count` nodes are collected, so only few buckets
+     * are touched and sorted instead of the whole table.
+     * */
+    pub fn closest(&self, target: &H512, count: usize) -> Vec<H512> {
+        let target = H256(keccak256(target));
+        let t = log_distance(&self.local_hash, &target);
+
+        let mut groups = Vec::with_capacity(NUM_BUCKETS);
+        if t > 0 {
+            groups.push(vec![t - 1]);
+            groups.push((0..t - 1).collect::<Vec<_>>());
+        }
+        groups.extend((t..NUM_BUCKETS).map(|index| vec![index]));
+
+        let mut closest = Vec::with_capacity(count + BUCKET_SIZE);
+        for group in groups {
+            let mut entries = group
+                .iter()
+                .flat_map(|index| self.buckets[*index].entries.iter())
+                .copied()
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|e| e.hash ^ target);
+            closest.extend(entries);
+
+            if closest.len() >= count {
+                break;
+            }
+        }
+
+        closest.truncate(count);
+        closest.into_iter().map(|e| e.id).collect()
+    }
+
+    /// Nodes of the bucket at the given log distance from us (1..=256)
+    pub fn nodes_at_distance(&self, log_distance: usize) -> Vec<H512> {
+        log_distance
+            .checked_sub(1)
+            .and_then(|index| self.buckets.get(index))
+            .map(|b| b.entries.iter().map(|e| e.id).collect())
+            .unwrap_or_default()
+    }
+
+    /// Least recently seen node of a random non empty bucket
+    pub fn node_to_revalidate(&self) -> Option<H512> {
+        let non_empty = self
+            .buckets
+            .iter()
+            .filter(|b| !b.entries.is_empty())
+            .collect::<Vec<_>>();
+        if non_empty.is_empty() {
+            return None;
+        }
+
+        let bucket = non_empty[rand::thread_rng().gen_range(0..non_empty.len())];
+        bucket.entries.back().map(|e| e.id)
+    }
+
+    /// Returns true (and starts a new refresh period) if buckets are due for refresh
+    pub fn should_refresh(&mut self, now: Instant) -> bool {
+        if self
+            .last_refresh
+            .is_some_and(|last| now.saturating_duration_since(last) < REFRESH_INTERVAL)
+        {
+            return false;
+        }
+
+        self.last_refresh = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ids(count: usize) -> Vec<H512> {
+        (0..count).map(|_| H512::random()).collect()
+    }
+
+    #[test]
+    fn log_distances() {
+        let a = H256::zero();
+        assert_eq!(log_distance(&a, &a), 0);
+        assert_eq!(log_distance(&a, &H256::from_low_u64_be(1)), 1);
+        assert_eq!(log_distance(&a, &H256::from_low_u64_be(0x80)), 8);
+        assert_eq!(log_distance(&a, &H256::repeat_byte(0xff)), 256);
+    }
+
+    #[test]
+    fn closest_matches_full_sort() {
+        let mut table = Table::new(H512::random());
+        let all = ids(2_000);
+        all.iter().for_each(|id| {
+            table.add_seen(*id);
+        });
+        assert!(table.len() <= NUM_BUCKETS * BUCKET_SIZE);
+
+        for _ in 0..20 {
+            let target = H512::random();
+            let target_hash = H256(keccak256(target));
+
+            let mut expected = all
+                .iter()
+                .filter(|id| table.contains(id))
+                .copied()
+                .collect::<Vec<_>>();
+            expected.sort_by_key(|id| distance(id, &target_hash));
+            expected.truncate(BUCKET_SIZE);
+
+            assert_eq!(table.closest(&target, BUCKET_SIZE), expected);
+        }
+    }
+
+    #[test]
+    fn full_bucket_keeps_old_nodes_until_they_are_removed() {
+        let local = H512::random();
+        let local_hash = H256(keccak256(local));
+        let mut table = Table::new(local);
+
+        // about half of random ids are at distance 256
+        let far = ids(200)
+            .into_iter()
+            .filter(|id| log_distance(&local_hash, &H256(keccak256(id))) == 256)
+            .take(BUCKET_SIZE + 2)
+            .collect::<Vec<_>>();
+
+        for id in far.iter() {
+            table.add_seen(*id);
+        }
+        let (in_bucket, replacements) = far.split_at(BUCKET_SIZE);
+        assert!(in_bucket.iter().all(|id| table.contains(id)));
+        assert!(replacements.iter().all(|id| !table.contains(id)));
+
+        // least recently seen is revalidated, answering moves it to the front
+        assert_eq!(table.node_to_revalidate(), Some(in_bucket[0]));
+        assert!(table.add_seen(in_bucket[0]));
+        assert_eq!(table.node_to_revalidate(), Some(in_bucket[1]));
+
+        // dead node is replaced by the most recently seen replacement
+        assert!(table.remove(&in_bucket[1]));
+        assert!(!table.contains(&in_bucket[1]));
+        assert!(table.contains(&replacements[1]));
+        assert!(!table.contains(&replacements[0]));
+        assert_eq!(table.len(), BUCKET_SIZE);
+    }
+}
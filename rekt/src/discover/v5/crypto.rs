@@ -0,0 +1,263 @@
+use aes::Aes128;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes128Gcm, KeyInit};
+use cipher::{KeyIvInit, StreamCipher};
+use ethers::utils::keccak256;
+use hmac::{Hmac, Mac};
+use secp256k1::ecdsa::Signature;
+use secp256k1::{PublicKey, SecretKey, SECP256K1};
+use sha2::{Digest, Sha256};
+
+use crate::types::hash::H256;
+
+use super::errors::Discv5Error;
+
+const KEY_AGREEMENT_INFO: &[u8] = b"discovery v5 key agreement";
+const ID_SIGNATURE_TEXT: &[u8] = b"discovery v5 identity proof";
+
+/*
+* Crypto of discv5 wire protocol (https://github.com/ethereum/devp2p/blob/master/discv5/discv5-theory.md)
+* - packet header is masked with AES-128-CTR, key is the first 16 bytes of recipient's node id
+* - messages are encrypted with AES-128-GCM using session keys
+* - session keys come from HKDF-SHA256 over ECDH of an ephemeral key and recipient's static key
+* */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionKeys {
+    pub initiator_key: [u8; 16],
+    pub recipient_key: [u8; 16],
+}
+
+/// Node id of discv5 is keccak of uncompressed public key without the prefix
+pub fn node_id(public_key: &PublicKey) -> H256 {
+    H256(keccak256(&public_key.serialize_uncompressed()[1..]))
+}
+
+pub fn aes_ctr(key: &[u8; 16], iv: &[u8; 16], buf: &mut [u8]) {
+    let mut cipher = ctr::Ctr128BE::<Aes128>::new(key.into(), iv.into());
+    cipher.apply_keystream(buf);
+}
+
+/// Returns ciphertext with the tag appended
+pub fn aes_gcm_encrypt(key: &[u8; 16], nonce: &[u8; 12], plaintext: &[u8], ad: &[u8]) -> Vec<u8> {
+    Aes128Gcm::new(key.into())
+        .encrypt(
+            nonce.into(),
+            Payload {
+                msg: plaintext,
+                aad: ad,
+            },
+        )
+        .expect("messages are far below the GCM size limit")
+}
+
+pub fn aes_gcm_decrypt(
+    key: &[u8; 16],
+    nonce: &[u8; 12],
+    ciphertext: &[u8],
+    ad: &[u8],
+) -> Result<Vec<u8>, Discv5Error> {
+    Aes128Gcm::new(key.into())
+        .decrypt(
+            nonce.into(),
+            Payload {
+                msg: ciphertext,
+                aad: ad,
+            },
+        )
+        .map_err(|_| Discv5Error::DecryptionFailed)
+}
+
+/// ECDH shared secret of discv5: the shared point in compressed form
+pub fn ecdh(public_key: &PublicKey, secret_key: &SecretKey) -> [u8; 33] {
+    let point = secp256k1::ecdh::shared_secret_point(public_key, secret_key);
+    let mut secret = [0u8; 33];
+    secret[0] = 0x02 | (point[63] & 1);
+    secret[1..].copy_from_slice(&point[..32]);
+    secret
+}
+
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> [u8; 32] {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    data.iter().for_each(|d| mac.update(d));
+    mac.finalize().into_bytes().into()
+}
+
+/// `node_id_a` is the initiator of the handshake, `node_id_b` the recipient
+pub fn derive_keys(
+    secret: &[u8; 33],
+    node_id_a: &H256,
+    node_id_b: &H256,
+    challenge_data: &[u8],
+) -> SessionKeys {
+    // HKDF-SHA256, 32 bytes of output is a single expand round
+    let prk = hmac_sha256(challenge_data, &[secret]);
+    let key_data = hmac_sha256(
+        &prk,
+        &[
+            KEY_AGREEMENT_INFO,
+            node_id_a.as_bytes(),
+            node_id_b.as_bytes(),
+            &[1],
+        ],
+    );
+
+    let mut keys = SessionKeys {
+        initiator_key: [0; 16],
+        recipient_key: [0; 16],
+    };
+    keys.initiator_key.copy_from_slice(&key_data[..16]);
+    keys.recipient_key.copy_from_slice(&key_data[16..]);
+    keys
+}
+
+fn id_signature_hash(
+    challenge_data: &[u8],
+    ephemeral_pubkey: &[u8],
+    node_id_b: &H256,
+) -> secp256k1::Message {
+    let hash = Sha256::new()
+        .chain_update(ID_SIGNATURE_TEXT)
+        .chain_update(challenge_data)
+        .chain_update(ephemeral_pubkey)
+        .chain_update(node_id_b.as_bytes())
+        .finalize();
+    secp256k1::Message::from_slice(&hash).expect("sha256 is 32 bytes")
+}
+
+pub fn id_signature(
+    secret_key: &SecretKey,
+    challenge_data: &[u8],
+    ephemeral_pubkey: &[u8],
+    node_id_b: &H256,
+) -> [u8; 64] {
+    let msg = id_signature_hash(challenge_data, ephemeral_pubkey, node_id_b);
+    SECP256K1.sign_ecdsa(&msg, secret_key).serialize_compact()
+}
+
+pub fn verify_id_signature(
+    public_key: &PublicKey,
+    signature: &[u8],
+    challenge_data: &[u8],
+    ephemeral_pubkey: &[u8],
+    node_id_b: &H256,
+) -> bool {
+    let msg = id_signature_hash(challenge_data, ephemeral_pubkey, node_id_b);
+    Signature::from_compact(signature)
+        .and_then(|sig| SECP256K1.verify_ecdsa(&msg, &sig, public_key))
+        .is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn aes_gcm_nist_vectors() {
+        // test cases 1 and 2 of the GCM spec
+        let key = [0u8; 16];
+        let nonce = [0u8; 12];
+        assert_eq!(
+            aes_gcm_encrypt(&key, &nonce, &[], &[]),
+            hex!("58e2fccefa7e3061367f1d57a4e7455a")
+        );
+
+        let ciphertext = aes_gcm_encrypt(&key, &nonce, &[0u8; 16], &[]);
+        assert_eq!(
+            ciphertext,
+            hex!("0388dace60b6a392f328c2b971b2fe78ab6e47d42cec13bdf53a67b21257bddf")
+        );
+        assert_eq!(
+            aes_gcm_decrypt(&key, &nonce, &ciphertext, &[]).unwrap(),
+            [0u8; 16]
+        );
+
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 1;
+        assert!(aes_gcm_decrypt(&key, &nonce, &tampered, &[]).is_err());
+    }
+
+    // https://github.com/ethereum/devp2p/blob/master/discv5/discv5-wire-test-vectors.md
+    const CHALLENGE_DATA: [u8; 63] = hex!("000000000000000000000000000000006469736376350001010102030405060708090a0b0c00180102030405060708090a0b0c0d0e0f100000000000000000");
+    const NODE_ID_A: [u8; 32] =
+        hex!("aaaa8419e9f49d0083561b48287df592939a8d19947d8c0ef88f2a4856a69fbb");
+    const NODE_ID_B: [u8; 32] =
+        hex!("bbbb9d047f0488c0b5a93c1c3f2d8bafc7c8ff337024a55434a0d0555de64db9");
+
+    #[test]
+    fn spec_ecdh() {
+        let secret_key = SecretKey::from_slice(&hex!(
+            "fb757dc581730490a1d7a00deea65e9b1936924caaea8f44d476014856b68736"
+        ))
+        .unwrap();
+        let public_key = PublicKey::from_slice(&hex!(
+            "039961e4c2356d61bedb83052c115d311acb3a96f5777296dcf297351130266231"
+        ))
+        .unwrap();
+        assert_eq!(
+            ecdh(&public_key, &secret_key),
+            hex!("033b11a2a1f214567e1537ce5e509ffd9b21373247f2a3ff6841f4976f53165e7e")
+        );
+    }
+
+    #[test]
+    fn spec_key_derivation() {
+        let ephemeral_key = SecretKey::from_slice(&hex!(
+            "fb757dc581730490a1d7a00deea65e9b1936924caaea8f44d476014856b68736"
+        ))
+        .unwrap();
+        let dest_pubkey = PublicKey::from_slice(&hex!(
+            "0317931e6e0840220642f230037d285d122bc59063221ef3226b1f403ddc69ca91"
+        ))
+        .unwrap();
+
+        let keys = derive_keys(
+            &ecdh(&dest_pubkey, &ephemeral_key),
+            &H256(NODE_ID_A),
+            &H256(NODE_ID_B),
+            &CHALLENGE_DATA,
+        );
+        assert_eq!(keys.initiator_key, hex!("dccc82d81bd610f4f76d3ebe97a40571"));
+        assert_eq!(keys.recipient_key, hex!("ac74bb8773749920b0d3a8881c173ec5"));
+    }
+
+    #[test]
+    fn spec_id_signature() {
+        let static_key = SecretKey::from_slice(&hex!(
+            "fb757dc581730490a1d7a00deea65e9b1936924caaea8f44d476014856b68736"
+        ))
+        .unwrap();
+        let ephemeral_pubkey =
+            hex!("039961e4c2356d61bedb83052c115d311acb3a96f5777296dcf297351130266231");
+
+        let signature = id_signature(
+            &static_key,
+            &CHALLENGE_DATA,
+            &ephemeral_pubkey,
+            &H256(NODE_ID_B),
+        );
+        assert_eq!(signature, hex!("94852a1e2318c4e5e9d422c98eaf19d1d90d876b29cd06ca7cb7546d0fff7b484fe86c09a064fe72bdbef73ba8e9c34df0cd2b53e9d65528c2c7f336d5dfc6e6"));
+
+        let public_key = PublicKey::from_secret_key(SECP256K1, &static_key);
+        assert!(verify_id_signature(
+            &public_key,
+            &signature,
+            &CHALLENGE_DATA,
+            &ephemeral_pubkey,
+            &H256(NODE_ID_B)
+        ));
+    }
+
+    #[test]
+    fn spec_message_encryption() {
+        let ciphertext = aes_gcm_encrypt(
+            &hex!("9f2d77db7004bf8a1a85107ac686990b"),
+            &hex!("27b5af763c446acd2749fe8e"),
+            &hex!("01c20101"),
+            &hex!("93a7400fa0d6a694ebc24d5cf570f65d04215b6ac00757875e3f3a5f42107903"),
+        );
+        assert_eq!(ciphertext, hex!("a5d12a2d94b8ccb3ba55558229867dc13bfa3648"));
+    }
+}
@@ -0,0 +1,23 @@
+use open_fastrlp::DecodeError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Discv5Error {
+    #[error("invalid packet: {0}")]
+    InvalidPacket(&'static str),
+    /// Message could not be decrypted with session keys, remote should get WHOAREYOU
+    #[error("message decryption failed")]
+    DecryptionFailed,
+    #[error("invalid id signature")]
+    InvalidIdSignature,
+    #[error("no challenge for handshake")]
+    NoChallenge,
+    #[error("unknown node")]
+    UnknownNode,
+    #[error("unknown message type {0}")]
+    UnknownMessageType(u8),
+    #[error(transparent)]
+    Secp256k1(#[from] secp256k1::Error),
+    #[error(transparent)]
+    RLPDecoding(#[from] DecodeError),
+}
@@ -0,0 +1,146 @@
+use bytes::{Bytes, BytesMut};
+use enr::Enr;
+use open_fastrlp::{Decodable, Encodable, RlpDecodable, RlpEncodable};
+use secp256k1::SecretKey;
+
+use super::errors::Discv5Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+pub struct Ping {
+    pub request_id: Bytes,
+    pub enr_seq: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+pub struct Pong {
+    pub request_id: Bytes,
+    pub enr_seq: u64,
+    /// IP we see the packet coming from, 4 or 16 bytes
+    pub recipient_ip: Bytes,
+    pub recipient_port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+pub struct FindNode {
+    pub request_id: Bytes,
+    /// Log distances from the recipient, 0 asks for the recipient's own record
+    pub distances: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+pub struct Nodes {
+    pub request_id: Bytes,
+    /// Number of NODES messages in the response
+    pub total: u64,
+    pub enrs: Vec<Enr<SecretKey>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+pub struct TalkReq {
+    pub request_id: Bytes,
+    pub protocol: Bytes,
+    pub request: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+pub struct TalkResp {
+    pub request_id: Bytes,
+    pub response: Bytes,
+}
+
+/// Plaintext of an encrypted discv5 message: `message-type || rlp(message-data)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Ping(Ping),
+    Pong(Pong),
+    FindNode(FindNode),
+    Nodes(Nodes),
+    TalkReq(TalkReq),
+    TalkResp(TalkResp),
+}
+
+impl Message {
+    pub fn request_id(&self) -> &Bytes {
+        match self {
+            Message::Ping(m) => &m.request_id,
+            Message::Pong(m) => &m.request_id,
+            Message::FindNode(m) => &m.request_id,
+            Message::Nodes(m) => &m.request_id,
+            Message::TalkReq(m) => &m.request_id,
+            Message::TalkResp(m) => &m.request_id,
+        }
+    }
+
+    pub fn is_request(&self) -> bool {
+        matches!(
+            self,
+            Message::Ping(_) | Message::FindNode(_) | Message::TalkReq(_)
+        )
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = BytesMut::new();
+        match self {
+            Message::Ping(m) => {
+                out.extend_from_slice(&[0x01]);
+                m.encode(&mut out);
+            }
+            Message::Pong(m) => {
+                out.extend_from_slice(&[0x02]);
+                m.encode(&mut out);
+            }
+            Message::FindNode(m) => {
+                out.extend_from_slice(&[0x03]);
+                m.encode(&mut out);
+            }
+            Message::Nodes(m) => {
+                out.extend_from_slice(&[0x04]);
+                m.encode(&mut out);
+            }
+            Message::TalkReq(m) => {
+                out.extend_from_slice(&[0x05]);
+                m.encode(&mut out);
+            }
+            Message::TalkResp(m) => {
+                out.extend_from_slice(&[0x06]);
+                m.encode(&mut out);
+            }
+        }
+        out.to_vec()
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, Discv5Error> {
+        let (msg_type, data) = buf
+            .split_first()
+            .ok_or(Discv5Error::InvalidPacket("empty message"))?;
+        let data = &mut &data[..];
+
+        Ok(match msg_type {
+            0x01 => Message::Ping(Ping::decode(data)?),
+            0x02 => Message::Pong(Pong::decode(data)?),
+            0x03 => Message::FindNode(FindNode::decode(data)?),
+            0x04 => Message::Nodes(Nodes::decode(data)?),
+            0x05 => Message::TalkReq(TalkReq::decode(data)?),
+            0x06 => Message::TalkResp(TalkResp::decode(data)?),
+            _ => return Err(Discv5Error::UnknownMessageType(*msg_type)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn spec_ping_plaintext() {
+        // plaintext of the ping message from the discv5 wire test vectors
+        let ping = Message::Ping(Ping {
+            request_id: Bytes::from_static(&hex!("00000001")),
+            enr_seq: 2,
+        });
+        assert_eq!(ping.encode(), hex!("01c6840000000102"));
+        assert_eq!(Message::decode(&hex!("01c6840000000102")).unwrap(), ping);
+    }
+}
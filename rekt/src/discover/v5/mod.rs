@@ -0,0 +1,5 @@
+pub mod crypto;
+pub mod errors;
+pub mod messages;
+pub mod packet;
+pub mod service;
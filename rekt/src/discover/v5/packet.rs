@@ -0,0 +1,279 @@
+use bytes::Bytes;
+
+use crate::types::hash::H256;
+
+use super::crypto::aes_ctr;
+use super::errors::Discv5Error;
+
+pub const PROTOCOL_ID: &[u8; 6] = b"discv5";
+pub const PROTOCOL_VERSION: u16 = 1;
+
+const MASKING_IV_SIZE: usize = 16;
+const STATIC_HEADER_SIZE: usize = 23;
+pub const NONCE_SIZE: usize = 12;
+pub const ID_NONCE_SIZE: usize = 16;
+const WHOAREYOU_AUTHDATA_SIZE: usize = ID_NONCE_SIZE + 8;
+
+/// Smallest valid packet is WHOAREYOU
+pub const MIN_PACKET_SIZE: usize = MASKING_IV_SIZE + STATIC_HEADER_SIZE + WHOAREYOU_AUTHDATA_SIZE;
+
+const FLAG_MESSAGE: u8 = 0;
+const FLAG_WHOAREYOU: u8 = 1;
+const FLAG_HANDSHAKE: u8 = 2;
+
+/*
+* packet        = masking-iv || masked-header || message
+* masked-header = aes_ctr(masking-key = dest-id[:16], masking-iv, header)
+* header        = static-header || authdata
+* static-header = "discv5" || version || flag || nonce || authdata-size
+*
+* Unmasked `masking-iv || header` is both the associated data of the encrypted message and,
+* for WHOAREYOU, the challenge data handshake keys are derived from.
+* */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacketKind {
+    Message {
+        src_id: H256,
+    },
+    WhoAreYou {
+        id_nonce: [u8; ID_NONCE_SIZE],
+        /// Highest ENR seq the sender knows for us, 0 if it doesn't know our ENR
+        enr_seq: u64,
+    },
+    Handshake {
+        src_id: H256,
+        id_signature: Vec<u8>,
+        ephemeral_pubkey: Vec<u8>,
+        /// Sender's ENR, only if the one the recipient knows is outdated
+        record: Option<Vec<u8>>,
+    },
+}
+
+impl PacketKind {
+    fn flag(&self) -> u8 {
+        match self {
+            PacketKind::Message { .. } => FLAG_MESSAGE,
+            PacketKind::WhoAreYou { .. } => FLAG_WHOAREYOU,
+            PacketKind::Handshake { .. } => FLAG_HANDSHAKE,
+        }
+    }
+
+    fn authdata(&self) -> Vec<u8> {
+        match self {
+            PacketKind::Message { src_id } => src_id.as_bytes().to_vec(),
+            PacketKind::WhoAreYou { id_nonce, enr_seq } => {
+                let mut authdata = id_nonce.to_vec();
+                authdata.extend_from_slice(&enr_seq.to_be_bytes());
+                authdata
+            }
+            PacketKind::Handshake {
+                src_id,
+                id_signature,
+                ephemeral_pubkey,
+                record,
+            } => {
+                let mut authdata = src_id.as_bytes().to_vec();
+                authdata.push(id_signature.len() as u8);
+                authdata.push(ephemeral_pubkey.len() as u8);
+                authdata.extend_from_slice(id_signature);
+                authdata.extend_from_slice(ephemeral_pubkey);
+                if let Some(record) = record {
+                    authdata.extend_from_slice(record);
+                }
+                authdata
+            }
+        }
+    }
+
+    fn decode(flag: u8, authdata: &[u8]) -> Result<Self, Discv5Error> {
+        match flag {
+            FLAG_MESSAGE => {
+                if authdata.len() != 32 {
+                    return Err(Discv5Error::InvalidPacket("message authdata size"));
+                }
+                Ok(PacketKind::Message {
+                    src_id: H256::from_slice(authdata),
+                })
+            }
+            FLAG_WHOAREYOU => {
+                if authdata.len() != WHOAREYOU_AUTHDATA_SIZE {
+                    return Err(Discv5Error::InvalidPacket("whoareyou authdata size"));
+                }
+                let mut id_nonce = [0u8; ID_NONCE_SIZE];
+                id_nonce.copy_from_slice(&authdata[..ID_NONCE_SIZE]);
+                let mut enr_seq = [0u8; 8];
+                enr_seq.copy_from_slice(&authdata[ID_NONCE_SIZE..]);
+                Ok(PacketKind::WhoAreYou {
+                    id_nonce,
+                    enr_seq: u64::from_be_bytes(enr_seq),
+                })
+            }
+            FLAG_HANDSHAKE => {
+                if authdata.len() < 34 {
+                    return Err(Discv5Error::InvalidPacket("handshake authdata size"));
+                }
+                let (sig_size, key_size) = (authdata[32] as usize, authdata[33] as usize);
+                let rest = &authdata[34..];
+                if rest.len() < sig_size + key_size {
+                    return Err(Discv5Error::InvalidPacket("handshake authdata size"));
+                }
+                let record = &rest[sig_size + key_size..];
+                Ok(PacketKind::Handshake {
+                    src_id: H256::from_slice(&authdata[..32]),
+                    id_signature: rest[..sig_size].to_vec(),
+                    ephemeral_pubkey: rest[sig_size..sig_size + key_size].to_vec(),
+                    record: (!record.is_empty()).then(|| record.to_vec()),
+                })
+            }
+            _ => Err(Discv5Error::InvalidPacket("unknown flag")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub masking_iv: [u8; MASKING_IV_SIZE],
+    /// Unmasked `static-header || authdata`
+    pub header: Vec<u8>,
+    pub nonce: [u8; NONCE_SIZE],
+    pub kind: PacketKind,
+    /// Encrypted message, empty for WHOAREYOU
+    pub message: Vec<u8>,
+}
+
+impl Packet {
+    pub fn new(kind: PacketKind, nonce: [u8; NONCE_SIZE]) -> Self {
+        let authdata = kind.authdata();
+        let mut header = Vec::with_capacity(STATIC_HEADER_SIZE + authdata.len());
+        header.extend_from_slice(PROTOCOL_ID);
+        header.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+        header.push(kind.flag());
+        header.extend_from_slice(&nonce);
+        header.extend_from_slice(&(authdata.len() as u16).to_be_bytes());
+        header.extend_from_slice(&authdata);
+
+        Self {
+            masking_iv: rand::random(),
+            header,
+            nonce,
+            kind,
+            message: Vec::new(),
+        }
+    }
+
+    /// `masking-iv || header`: associated data of the message, challenge data of WHOAREYOU
+    pub fn authenticated_data(&self) -> Vec<u8> {
+        let mut data = self.masking_iv.to_vec();
+        data.extend_from_slice(&self.header);
+        data
+    }
+
+    pub fn encode(&self, dest_id: &H256) -> Bytes {
+        let mut masked_header = self.header.clone();
+        aes_ctr(&masking_key(dest_id), &self.masking_iv, &mut masked_header);
+
+        let mut out =
+            Vec::with_capacity(MASKING_IV_SIZE + masked_header.len() + self.message.len());
+        out.extend_from_slice(&self.masking_iv);
+        out.extend_from_slice(&masked_header);
+        out.extend_from_slice(&self.message);
+        Bytes::from(out)
+    }
+
+    pub fn decode(local_id: &H256, buf: &[u8]) -> Result<Self, Discv5Error> {
+        if buf.len() < MIN_PACKET_SIZE {
+            return Err(Discv5Error::InvalidPacket("too small"));
+        }
+
+        let mut masking_iv = [0u8; MASKING_IV_SIZE];
+        masking_iv.copy_from_slice(&buf[..MASKING_IV_SIZE]);
+        let key = masking_key(local_id);
+
+        let mut static_header = buf[MASKING_IV_SIZE..MASKING_IV_SIZE + STATIC_HEADER_SIZE].to_vec();
+        aes_ctr(&key, &masking_iv, &mut static_header);
+        if &static_header[..6] != PROTOCOL_ID
+            || static_header[6..8] != PROTOCOL_VERSION.to_be_bytes()
+        {
+            return Err(Discv5Error::InvalidPacket("not a discv5 packet"));
+        }
+
+        let flag = static_header[8];
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce.copy_from_slice(&static_header[9..9 + NONCE_SIZE]);
+        let authdata_size = u16::from_be_bytes([static_header[21], static_header[22]]) as usize;
+
+        let header_end = MASKING_IV_SIZE + STATIC_HEADER_SIZE + authdata_size;
+        if buf.len() < header_end {
+            return Err(Discv5Error::InvalidPacket("truncated header"));
+        }
+        let mut header = buf[MASKING_IV_SIZE..header_end].to_vec();
+        aes_ctr(&key, &masking_iv, &mut header);
+
+        let kind = PacketKind::decode(flag, &header[STATIC_HEADER_SIZE..])?;
+        let message = buf[header_end..].to_vec();
+        if matches!(kind, PacketKind::WhoAreYou { .. }) && !message.is_empty() {
+            return Err(Discv5Error::InvalidPacket("whoareyou with message"));
+        }
+
+        Ok(Self {
+            masking_iv,
+            header,
+            nonce,
+            kind,
+            message,
+        })
+    }
+}
+
+fn masking_key(dest_id: &H256) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&dest_id[..16]);
+    key
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+
+    const NODE_A: [u8; 32] =
+        hex!("aaaa8419e9f49d0083561b48287df592939a8d19947d8c0ef88f2a4856a69fbb");
+    const NODE_B: [u8; 32] =
+        hex!("bbbb9d047f0488c0b5a93c1c3f2d8bafc7c8ff337024a55434a0d0555de64db9");
+
+    #[test]
+    fn spec_whoareyou_packet() {
+        let encoded = hex!("00000000000000000000000000000000088b3d434277464933a1ccc59f5967ad1d6035f15e528627dde75cd68292f9e6c27d6b66c8100a873fcbaed4e16b8d");
+
+        let packet = Packet::decode(&H256(NODE_B), &encoded).unwrap();
+        assert_eq!(packet.nonce, hex!("0102030405060708090a0b0c"));
+        assert_eq!(
+            packet.kind,
+            PacketKind::WhoAreYou {
+                id_nonce: hex!("0102030405060708090a0b0c0d0e0f10"),
+                enr_seq: 0,
+            }
+        );
+
+        let mut again = Packet::new(packet.kind.clone(), packet.nonce);
+        again.masking_iv = packet.masking_iv;
+        assert_eq!(&again.encode(&H256(NODE_B))[..], &encoded[..]);
+    }
+
+    #[test]
+    fn handshake_roundtrip() {
+        let kind = PacketKind::Handshake {
+            src_id: H256(NODE_A),
+            id_signature: vec![0xb2; 64],
+            ephemeral_pubkey: vec![0x02; 33],
+            record: Some(vec![0xc1, 0x80]),
+        };
+        let mut packet = Packet::new(kind, [7; NONCE_SIZE]);
+        packet.message = vec![0xb2; 20];
+
+        let decoded = Packet::decode(&H256(NODE_B), &packet.encode(&H256(NODE_B))).unwrap();
+        assert_eq!(decoded, packet);
+        assert!(Packet::decode(&H256(NODE_A), &packet.encode(&H256(NODE_B))).is_err());
+    }
+}
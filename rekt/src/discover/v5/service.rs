@@ -0,0 +1,909 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use dashmap::DashMap;
+use enr::Enr;
+use ethers::utils::keccak256;
+use open_fastrlp::{Decodable, Encodable};
+use secp256k1::{PublicKey, SecretKey, SECP256K1};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::time::interval;
+
+use crate::discover::decoder::MAX_PACKET_SIZE;
+use crate::discover::handler::dial_if_fork_matches;
+use crate::discover::messages::discover_message::DEFAULT_MESSAGE_EXPIRATION;
+use crate::discover::messages::enr::enr_fork_id;
+use crate::discover::table::{log_distance, Table, BUCKET_SIZE};
+use crate::local_node::LocalNode;
+use crate::rlpx::utils::pk2id;
+use crate::server::errors::ConnectionTaskError;
+use crate::types::hash::{H256, H512};
+use crate::types::node_record::NodeRecord;
+
+use super::crypto::{
+    aes_gcm_decrypt, aes_gcm_encrypt, derive_keys, ecdh, id_signature, node_id, verify_id_signature,
+};
+use super::errors::Discv5Error;
+use super::messages::{FindNode, Message, Nodes, Ping, Pong, TalkResp};
+use super::packet::{Packet, PacketKind, NONCE_SIZE};
+
+/// Requests, challenges and handshakes not answered within this are dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(DEFAULT_MESSAGE_EXPIRATION);
+
+/// ENRs are up to 300 bytes, three of them fit into a packet
+const MAX_ENRS_PER_NODES_MSG: usize = 3;
+
+/// Table nodes closest to a random target asked for neighbours every worker tick
+const LOOKUP_ALPHA: usize = 3;
+
+/// Known but never queried nodes asked for neighbours every worker tick
+const CRAWL_BATCH: usize = 16;
+
+/// Farthest buckets hold the most nodes, that is what we ask new nodes for
+const CRAWL_DISTANCES: [u64; 3] = [256, 255, 254];
+
+/// NODES messages accepted for one FINDNODE, whatever `total` the node claims
+const MAX_NODES_MSGS: u64 = 8;
+
+/// Socket of discv5 running on its own port, with the channel of packets to send
+pub type OwnSocket = (Arc<UdpSocket>, UnboundedReceiver<(SocketAddr, Bytes)>);
+
+/*
+* Discovery v5 (https://github.com/ethereum/devp2p/blob/master/discv5/discv5-wire.md)
+*
+* Every message is encrypted with session keys. Sending to a node without a session:
+* 1. we send a message packet with random content, and keep the real message by packet nonce
+* 2. node can't decrypt it and answers WHOAREYOU with the same nonce and a random id nonce
+* 3. we derive session keys from ECDH of an ephemeral key and node's static key, salted with the
+*    WHOAREYOU (challenge data), sign the challenge with our static key and send handshake packet
+*    with the real message encrypted by the new keys
+* Receiving from a node without a session is the same with roles swapped.
+*
+* Sessions, challenges and requests are bound to the address the node used, a packet from another
+* address starts a new handshake. Responses are taken only for requests pending at the node which
+* sent them, and ENRs of NODES only at the distances asked for.
+*
+* Nodes which answer our requests go to a k-bucket table (the one discv4 uses), FINDNODE is
+* answered from it. Every ENR we learn about is checked for `eth` fork id and BSC nodes are dialed,
+* exactly like nodes found by discv4. TALKREQ is answered with an empty response, we don't serve
+* any talk protocols.
+* */
+struct Session {
+    encrypt_key: [u8; 16],
+    decrypt_key: [u8; 16],
+    addr: SocketAddr,
+}
+
+/// WHOAREYOU we sent, waiting for the handshake
+struct Challenge {
+    data: Vec<u8>,
+    addr: SocketAddr,
+    sent_on: Instant,
+}
+
+/// Request we sent, resent inside a handshake if the node answers with WHOAREYOU
+struct SentRequest {
+    node_id: H256,
+    addr: SocketAddr,
+    message: Message,
+    sent_on: Instant,
+}
+
+/// Request waiting for a response
+struct PendingResponse {
+    node_id: H256,
+    /// Distances of FINDNODE, `None` for other requests
+    distances: Option<Vec<u64>>,
+    /// NODES messages still expected, known after the first one
+    nodes_left: Option<u64>,
+    sent_on: Instant,
+}
+
+struct KnownNode {
+    enr: Enr<SecretKey>,
+    queried: bool,
+}
+
+pub struct Discv5 {
+    local_node: LocalNode,
+    local_id: H256,
+    udp_sender: UnboundedSender<(SocketAddr, Bytes)>,
+
+    nodes: DashMap<H256, KnownNode>,
+    /// Nodes which answered our requests, by public key id like in discv4
+    table: Mutex<Table>,
+
+    sessions: DashMap<H256, Session>,
+    challenges: DashMap<H256, Challenge>,
+    sent_requests: DashMap<[u8; NONCE_SIZE], SentRequest>,
+    /// Requests waiting for a response, by request id
+    pending_responses: DashMap<Bytes, PendingResponse>,
+
+    conn_tx: UnboundedSender<ConnectionTaskError>,
+    server_config: crate::cli::Cli,
+}
+
+impl Discv5 {
+    pub fn new(
+        local_node: LocalNode,
+        udp_sender: UnboundedSender<(SocketAddr, Bytes)>,
+        conn_tx: UnboundedSender<ConnectionTaskError>,
+        server_config: crate::cli::Cli,
+        bootnodes: &[String],
+    ) -> Self {
+        let this = Self {
+            local_id: node_id(&local_node.public_key),
+            table: Mutex::new(Table::new(local_node.node_record.id)),
+            local_node,
+            udp_sender,
+            nodes: DashMap::with_capacity(10_000),
+            sessions: DashMap::with_capacity(1_000),
+            challenges: DashMap::with_capacity(100),
+            sent_requests: DashMap::with_capacity(1_000),
+            pending_responses: DashMap::with_capacity(1_000),
+            conn_tx,
+            server_config,
+        };
+
+        bootnodes
+            .iter()
+            .filter_map(|enr| enr.parse::<Enr<SecretKey>>().ok())
+            .for_each(|enr| this.add_enr(enr));
+
+        this
+    }
+
+    /// Binds discv5 to its own port, when it doesn't share the port with discv4
+    pub async fn bind(port: u16) -> Result<Arc<UdpSocket>, io::Error> {
+        Ok(Arc::new(
+            UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], port))).await?,
+        ))
+    }
+
+    /// `socket` is given when discv5 has its own port, otherwise discv4 server passes packets
+    /// to `handle_packet` and sends ours
+    pub fn start(this: Arc<Self>, socket: Option<OwnSocket>) {
+        if let Some((socket, mut udp_receiver)) = socket {
+            let writer_socket = socket.clone();
+            tokio::spawn(async move {
+                while let Some((dest, packet)) = udp_receiver.recv().await {
+                    let _ = writer_socket.send_to(&packet, dest).await;
+                }
+            });
+
+            let reader = this.clone();
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; MAX_PACKET_SIZE];
+                loop {
+                    if let Ok((size, src)) = socket.recv_from(&mut buf).await {
+                        reader.handle_packet(src, &buf[..size]);
+                    }
+                }
+            });
+        }
+
+        tokio::spawn(async move {
+            this.run_worker().await;
+        });
+    }
+
+    async fn run_worker(&self) {
+        let mut interval = interval(REQUEST_TIMEOUT);
+        loop {
+            interval.tick().await;
+
+            self.expire_requests();
+            self.revalidate();
+            self.lookup();
+            self.crawl();
+        }
+    }
+
+    /// Adds (or updates) a node we learned about, new BSC nodes are dialed
+    pub fn add_enr(&self, enr: Enr<SecretKey>) {
+        let id = H256(enr.node_id().raw());
+        let (Some(ip), Some(udp)) = (enr.ip4(), enr.udp4()) else {
+            return;
+        };
+        if id == self.local_id {
+            return;
+        }
+
+        let is_new = match self.nodes.entry(id) {
+            dashmap::mapref::entry::Entry::Occupied(mut entry) => {
+                if enr.seq() > entry.get().enr.seq() {
+                    entry.get_mut().enr = enr.clone();
+                }
+                false
+            }
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                entry.insert(KnownNode {
+                    enr: enr.clone(),
+                    queried: false,
+                });
+                true
+            }
+        };
+
+        if is_new {
+            let node_record = NodeRecord::new(
+                IpAddr::V4(ip),
+                enr.tcp4().unwrap_or(udp),
+                udp,
+                enr.public_key(),
+            );
+            dial_if_fork_matches(
+                enr_fork_id(&enr),
+                &node_record,
+                &self.local_node,
+                &self.server_config,
+                &self.conn_tx,
+            );
+        }
+    }
+
+    pub fn handle_packet(&self, src: SocketAddr, buf: &[u8]) {
+        let Ok(packet) = Packet::decode(&self.local_id, buf) else {
+            return;
+        };
+
+        let _ = match &packet.kind {
+            PacketKind::Message { src_id } => self.handle_message_packet(src, *src_id, &packet),
+            PacketKind::WhoAreYou { enr_seq, .. } => self.handle_whoareyou(src, *enr_seq, &packet),
+            PacketKind::Handshake { .. } => self.handle_handshake(src, &packet),
+        };
+    }
+
+    fn send(&self, addr: SocketAddr, packet: Bytes) {
+        let _ = self.udp_sender.send((addr, packet));
+    }
+
+    fn node_addr(&self, id: &H256) -> Option<SocketAddr> {
+        if let Some(session) = self.sessions.get(id) {
+            return Some(session.addr);
+        }
+        self.nodes
+            .get(id)
+            .and_then(|n| n.enr.udp4_socket())
+            .map(SocketAddr::V4)
+    }
+
+    fn send_message(&self, node_id: H256, message: Message) {
+        let Some(addr) = self.node_addr(&node_id) else {
+            return;
+        };
+
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let mut packet = Packet::new(
+            PacketKind::Message {
+                src_id: self.local_id,
+            },
+            nonce,
+        );
+        packet.message = match self.sessions.get(&node_id) {
+            Some(session) => aes_gcm_encrypt(
+                &session.encrypt_key,
+                &nonce,
+                &message.encode(),
+                &packet.authenticated_data(),
+            ),
+            // can't be decrypted, node answers with WHOAREYOU and we start a handshake
+            None if message.is_request() => rand::random::<[u8; 20]>().to_vec(),
+            None => return,
+        };
+
+        if message.is_request() {
+            let now = Instant::now();
+            let distances = match &message {
+                Message::FindNode(find_node) => Some(find_node.distances.clone()),
+                _ => None,
+            };
+            self.pending_responses.insert(
+                message.request_id().clone(),
+                PendingResponse {
+                    node_id,
+                    distances,
+                    nodes_left: None,
+                    sent_on: now,
+                },
+            );
+            self.sent_requests.insert(
+                nonce,
+                SentRequest {
+                    node_id,
+                    addr,
+                    message,
+                    sent_on: now,
+                },
+            );
+        }
+
+        self.send(addr, packet.encode(&node_id));
+    }
+
+    fn handle_message_packet(
+        &self,
+        src: SocketAddr,
+        src_id: H256,
+        packet: &Packet,
+    ) -> Result<(), Discv5Error> {
+        let plaintext = self.sessions.get(&src_id).and_then(|session| {
+            if session.addr != src {
+                return None;
+            }
+            aes_gcm_decrypt(
+                &session.decrypt_key,
+                &packet.nonce,
+                &packet.message,
+                &packet.authenticated_data(),
+            )
+            .ok()
+        });
+
+        match plaintext {
+            Some(plaintext) => self.handle_message(src, src_id, Message::decode(&plaintext)?),
+            None => {
+                self.send_whoareyou(src, src_id, packet.nonce);
+                Ok(())
+            }
+        }
+    }
+
+    fn send_whoareyou(&self, src: SocketAddr, src_id: H256, nonce: [u8; NONCE_SIZE]) {
+        if self
+            .challenges
+            .get(&src_id)
+            .is_some_and(|c| c.sent_on.elapsed() < REQUEST_TIMEOUT)
+        {
+            return;
+        }
+
+        let enr_seq = self.nodes.get(&src_id).map_or(0, |n| n.enr.seq());
+        let packet = Packet::new(
+            PacketKind::WhoAreYou {
+                id_nonce: rand::random(),
+                enr_seq,
+            },
+            nonce,
+        );
+        self.challenges.insert(
+            src_id,
+            Challenge {
+                data: packet.authenticated_data(),
+                addr: src,
+                sent_on: Instant::now(),
+            },
+        );
+
+        self.send(src, packet.encode(&src_id));
+    }
+
+    fn handle_whoareyou(
+        &self,
+        src: SocketAddr,
+        enr_seq: u64,
+        packet: &Packet,
+    ) -> Result<(), Discv5Error> {
+        let (_, request) = self
+            .sent_requests
+            .remove_if(&packet.nonce, |_, r| r.addr == src)
+            .ok_or(Discv5Error::UnknownNode)?;
+        let remote_key = self
+            .nodes
+            .get(&request.node_id)
+            .map(|n| n.enr.public_key())
+            .ok_or(Discv5Error::UnknownNode)?;
+
+        let challenge_data = packet.authenticated_data();
+        let ephemeral_key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+        let ephemeral_pubkey = PublicKey::from_secret_key(SECP256K1, &ephemeral_key).serialize();
+        let keys = derive_keys(
+            &ecdh(&remote_key, &ephemeral_key),
+            &self.local_id,
+            &request.node_id,
+            &challenge_data,
+        );
+
+        let local_enr = self.local_node.enr();
+        let record = (enr_seq < local_enr.seq()).then(|| {
+            let mut record = BytesMut::new();
+            local_enr.encode(&mut record);
+            record.to_vec()
+        });
+
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let mut handshake = Packet::new(
+            PacketKind::Handshake {
+                src_id: self.local_id,
+                id_signature: id_signature(
+                    &self.local_node.private_key,
+                    &challenge_data,
+                    &ephemeral_pubkey,
+                    &request.node_id,
+                )
+                .to_vec(),
+                ephemeral_pubkey: ephemeral_pubkey.to_vec(),
+                record,
+            },
+            nonce,
+        );
+        handshake.message = aes_gcm_encrypt(
+            &keys.initiator_key,
+            &nonce,
+            &request.message.encode(),
+            &handshake.authenticated_data(),
+        );
+
+        self.sessions.insert(
+            request.node_id,
+            Session {
+                encrypt_key: keys.initiator_key,
+                decrypt_key: keys.recipient_key,
+                addr: src,
+            },
+        );
+        self.send(src, handshake.encode(&request.node_id));
+        Ok(())
+    }
+
+    fn handle_handshake(&self, src: SocketAddr, packet: &Packet) -> Result<(), Discv5Error> {
+        let PacketKind::Handshake {
+            src_id,
+            id_signature,
+            ephemeral_pubkey,
+            record,
+        } = &packet.kind
+        else {
+            return Err(Discv5Error::InvalidPacket("not a handshake"));
+        };
+
+        let (_, challenge) = self
+            .challenges
+            .remove_if(src_id, |_, c| c.addr == src)
+            .ok_or(Discv5Error::NoChallenge)?;
+
+        let enr = match record {
+            Some(record) => {
+                let enr = Enr::<SecretKey>::decode(&mut &record[..])?;
+                if node_id(&enr.public_key()) != *src_id {
+                    return Err(Discv5Error::InvalidPacket("record of another node"));
+                }
+                enr
+            }
+            None => self
+                .nodes
+                .get(src_id)
+                .map(|n| n.enr.clone())
+                .ok_or(Discv5Error::UnknownNode)?,
+        };
+
+        if !verify_id_signature(
+            &enr.public_key(),
+            id_signature,
+            &challenge.data,
+            ephemeral_pubkey,
+            &self.local_id,
+        ) {
+            return Err(Discv5Error::InvalidIdSignature);
+        }
+
+        let keys = derive_keys(
+            &ecdh(
+                &PublicKey::from_slice(ephemeral_pubkey)?,
+                &self.local_node.private_key,
+            ),
+            src_id,
+            &self.local_id,
+            &challenge.data,
+        );
+        let plaintext = aes_gcm_decrypt(
+            &keys.initiator_key,
+            &packet.nonce,
+            &packet.message,
+            &packet.authenticated_data(),
+        )?;
+
+        self.sessions.insert(
+            *src_id,
+            Session {
+                encrypt_key: keys.recipient_key,
+                decrypt_key: keys.initiator_key,
+                addr: src,
+            },
+        );
+        if record.is_some() {
+            self.add_enr(enr);
+        }
+
+        self.handle_message(src, *src_id, Message::decode(&plaintext)?)
+    }
+
+    fn handle_message(
+        &self,
+        src: SocketAddr,
+        node_id: H256,
+        message: Message,
+    ) -> Result<(), Discv5Error> {
+        match message {
+            Message::Ping(ping) => {
+                let recipient_ip = match src.ip() {
+                    IpAddr::V4(ip) => Bytes::copy_from_slice(&ip.octets()),
+                    IpAddr::V6(ip) => Bytes::copy_from_slice(&ip.octets()),
+                };
+                self.send_message(
+                    node_id,
+                    Message::Pong(Pong {
+                        request_id: ping.request_id,
+                        enr_seq: self.local_node.enr().seq(),
+                        recipient_ip,
+                        recipient_port: src.port(),
+                    }),
+                );
+
+                // their record changed (or we don't have it), ask for the new one
+                if self
+                    .nodes
+                    .get(&node_id)
+                    .is_none_or(|n| n.enr.seq() < ping.enr_seq)
+                {
+                    self.send_message(node_id, find_node(vec![0]));
+                }
+            }
+            Message::FindNode(find_node) => self.send_nodes(node_id, find_node),
+            Message::TalkReq(talk_req) => self.send_message(
+                node_id,
+                Message::TalkResp(TalkResp {
+                    request_id: talk_req.request_id,
+                    response: Bytes::new(),
+                }),
+            ),
+            Message::Pong(_) | Message::Nodes(_) | Message::TalkResp(_) => {
+                // not something we asked this node for
+                let Some(distances) = self.take_pending_response(node_id, &message) else {
+                    return Ok(());
+                };
+                if let Some(node) = self.nodes.get(&node_id) {
+                    self.table
+                        .lock()
+                        .unwrap()
+                        .add_seen(pk2id(&node.enr.public_key()));
+                }
+
+                if let Message::Nodes(nodes) = message {
+                    nodes
+                        .enrs
+                        .into_iter()
+                        .filter(|enr| {
+                            let distance = log_distance(&H256(enr.node_id().raw()), &node_id);
+                            distances.contains(&(distance as u64))
+                        })
+                        .for_each(|enr| self.add_enr(enr));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Request the response answers, `None` if it wasn't sent to `node_id` or is of another kind.
+    /// FINDNODE stays pending until all NODES of the response came, its distances are returned.
+    fn take_pending_response(&self, node_id: H256, response: &Message) -> Option<Vec<u64>> {
+        let dashmap::mapref::entry::Entry::Occupied(mut entry) =
+            self.pending_responses.entry(response.request_id().clone())
+        else {
+            return None;
+        };
+        let pending = entry.get_mut();
+        if pending.node_id != node_id {
+            return None;
+        }
+
+        match (response, &pending.distances) {
+            (Message::Nodes(nodes), Some(distances)) => {
+                let distances = distances.clone();
+                let left = pending
+                    .nodes_left
+                    .get_or_insert(nodes.total.clamp(1, MAX_NODES_MSGS));
+                *left -= 1;
+                if *left == 0 {
+                    entry.remove();
+                }
+                Some(distances)
+            }
+            (Message::Nodes(_), None) | (_, Some(_)) => None,
+            _ => {
+                entry.remove();
+                Some(Vec::new())
+            }
+        }
+    }
+
+    fn send_nodes(&self, node_id: H256, find_node: FindNode) {
+        let mut enrs = Vec::new();
+        for distance in find_node.distances.iter().take(3) {
+            if *distance == 0 {
+                enrs.push(self.local_node.enr());
+                continue;
+            }
+
+            let ids = self
+                .table
+                .lock()
+                .unwrap()
+                .nodes_at_distance(*distance as usize);
+            enrs.extend(
+                ids.iter()
+                    .filter_map(|id| self.nodes.get(&H256(keccak256(id))).map(|n| n.enr.clone())),
+            );
+        }
+        enrs.truncate(BUCKET_SIZE);
+
+        let chunks = enrs
+            .chunks(MAX_ENRS_PER_NODES_MSG)
+            .map(|chunk| chunk.to_vec())
+            .collect::<Vec<_>>();
+        let total = chunks.len().max(1) as u64;
+        if chunks.is_empty() {
+            self.send_message(
+                node_id,
+                Message::Nodes(Nodes {
+                    request_id: find_node.request_id,
+                    total,
+                    enrs: Vec::new(),
+                }),
+            );
+            return;
+        }
+
+        for enrs in chunks {
+            self.send_message(
+                node_id,
+                Message::Nodes(Nodes {
+                    request_id: find_node.request_id.clone(),
+                    total,
+                    enrs,
+                }),
+            );
+        }
+    }
+
+    /// Nodes which didn't answer in time are dropped from the table
+    fn expire_requests(&self) {
+        self.sent_requests
+            .retain(|_, r| r.sent_on.elapsed() < REQUEST_TIMEOUT);
+        self.challenges
+            .retain(|_, c| c.sent_on.elapsed() < REQUEST_TIMEOUT);
+
+        let expired = self
+            .pending_responses
+            .iter()
+            .filter(|r| r.sent_on.elapsed() >= REQUEST_TIMEOUT)
+            .map(|r| (r.key().clone(), r.node_id, r.nodes_left.is_some()))
+            .collect::<Vec<_>>();
+        for (request_id, node_id, answered) in expired {
+            self.pending_responses.remove(&request_id);
+            if answered {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(&node_id) {
+                self.table
+                    .lock()
+                    .unwrap()
+                    .remove(&pk2id(&node.enr.public_key()));
+            }
+        }
+    }
+
+    fn revalidate(&self) {
+        let Some(id) = self.table.lock().unwrap().node_to_revalidate() else {
+            return;
+        };
+        self.send_message(
+            H256(keccak256(id)),
+            Message::Ping(Ping {
+                request_id: request_id(),
+                enr_seq: self.local_node.enr().seq(),
+            }),
+        );
+    }
+
+    /// Asks table nodes closest to a random target for nodes around the target
+    fn lookup(&self) {
+        let target = H512::random();
+        let target_hash = H256(keccak256(target));
+        let closest = self.table.lock().unwrap().closest(&target, LOOKUP_ALPHA);
+
+        for id in closest {
+            let node_id = H256(keccak256(id));
+            let distance = log_distance(&node_id, &target_hash) as u64;
+            let distances = [distance, distance + 1, distance.saturating_sub(1)]
+                .into_iter()
+                .filter(|d| (1..=256).contains(d))
+                .collect();
+            self.send_message(node_id, find_node(distances));
+        }
+    }
+
+    /// Asks nodes we never talked to for their far buckets, nodes which answer join the table
+    fn crawl(&self) {
+        let to_query = self
+            .nodes
+            .iter_mut()
+            .filter(|n| !n.queried)
+            .take(CRAWL_BATCH)
+            .map(|mut n| {
+                n.queried = true;
+                *n.key()
+            })
+            .collect::<Vec<_>>();
+
+        for node_id in to_query {
+            self.send_message(node_id, find_node(CRAWL_DISTANCES.to_vec()));
+        }
+    }
+}
+
+fn request_id() -> Bytes {
+    Bytes::copy_from_slice(&rand::random::<[u8; 8]>())
+}
+
+fn find_node(distances: Vec<u64>) -> Message {
+    Message::FindNode(FindNode {
+        request_id: request_id(),
+        distances,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn node() -> (Discv5, UnboundedReceiver<(SocketAddr, Bytes)>) {
+        let local_node = LocalNode::new(
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            SecretKey::new(&mut secp256k1::rand::thread_rng()),
+        );
+        let (udp_tx, udp_rx) = unbounded_channel();
+        let (conn_tx, _) = unbounded_channel();
+        let discv5 = Discv5::new(local_node, udp_tx, conn_tx, crate::cli::Cli::default(), &[]);
+        (discv5, udp_rx)
+    }
+
+    /// Address the node's packets come from, the one of its record
+    fn addr(node: &Discv5) -> SocketAddr {
+        SocketAddr::V4(node.local_node.enr().udp4_socket().unwrap())
+    }
+
+    /// Passes packets between the nodes until both are quiet
+    fn exchange(
+        a: &Discv5,
+        a_rx: &mut UnboundedReceiver<(SocketAddr, Bytes)>,
+        b: &Discv5,
+        b_rx: &mut UnboundedReceiver<(SocketAddr, Bytes)>,
+    ) {
+        let (a_addr, b_addr) = (addr(a), addr(b));
+        loop {
+            let mut quiet = true;
+            while let Ok((_, packet)) = a_rx.try_recv() {
+                b.handle_packet(a_addr, &packet);
+                quiet = false;
+            }
+            while let Ok((_, packet)) = b_rx.try_recv() {
+                a.handle_packet(b_addr, &packet);
+                quiet = false;
+            }
+            if quiet {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn handshake_and_find_node() {
+        let (a, mut a_rx) = node();
+        let (b, mut b_rx) = node();
+        a.add_enr(b.local_node.enr());
+
+        // b doesn't know a, learns its ENR from the handshake
+        a.send_message(
+            b.local_id,
+            Message::Ping(Ping {
+                request_id: request_id(),
+                enr_seq: a.local_node.enr().seq(),
+            }),
+        );
+        exchange(&a, &mut a_rx, &b, &mut b_rx);
+
+        assert!(a.sessions.contains_key(&b.local_id));
+        assert!(b.sessions.contains_key(&a.local_id));
+        assert!(b.nodes.contains_key(&a.local_id));
+        assert!(a.pending_responses.is_empty());
+        assert!(a
+            .table
+            .lock()
+            .unwrap()
+            .contains(&b.local_node.node_record.id));
+
+        // session works both ways, distance 0 is the recipient's own record
+        b.send_message(a.local_id, find_node(vec![0]));
+        exchange(&a, &mut a_rx, &b, &mut b_rx);
+        assert!(b.pending_responses.is_empty());
+        assert!(b
+            .table
+            .lock()
+            .unwrap()
+            .contains(&a.local_node.node_record.id));
+        assert!(b.challenges.is_empty() && a.challenges.is_empty());
+    }
+
+    #[test]
+    fn only_requested_nodes_are_taken() {
+        let (a, mut a_rx) = node();
+        let (b, mut b_rx) = node();
+        a.add_enr(b.local_node.enr());
+        a.send_message(b.local_id, find_node(vec![0]));
+        exchange(&a, &mut a_rx, &b, &mut b_rx);
+        assert!(a.sessions.contains_key(&b.local_id));
+
+        let (c, _) = node();
+        let c_distance = log_distance(&c.local_id, &b.local_id) as u64;
+        let e = std::iter::repeat_with(|| node().0)
+            .find(|e| log_distance(&e.local_id, &b.local_id) as u64 != c_distance)
+            .unwrap();
+        let nodes = |request_id: &Bytes| {
+            Message::Nodes(Nodes {
+                request_id: request_id.clone(),
+                total: 1,
+                enrs: vec![c.local_node.enr(), e.local_node.enr()],
+            })
+        };
+
+        // nobody asked for these
+        a.handle_message(addr(&b), b.local_id, nodes(&request_id()))
+            .unwrap();
+        assert!(!a.nodes.contains_key(&c.local_id) && !a.nodes.contains_key(&e.local_id));
+
+        let request = find_node(vec![c_distance]);
+        let request_id = request.request_id().clone();
+        a.send_message(b.local_id, request);
+        // only the node which was asked can answer
+        a.handle_message(addr(&b), c.local_id, nodes(&request_id))
+            .unwrap();
+        assert!(!a.nodes.contains_key(&c.local_id));
+
+        a.handle_message(addr(&b), b.local_id, nodes(&request_id))
+            .unwrap();
+        assert!(a.nodes.contains_key(&c.local_id));
+        assert!(!a.nodes.contains_key(&e.local_id));
+        assert!(a.pending_responses.is_empty());
+    }
+
+    #[test]
+    fn session_is_bound_to_address() {
+        let (a, mut a_rx) = node();
+        let (b, mut b_rx) = node();
+        a.add_enr(b.local_node.enr());
+        a.send_message(b.local_id, find_node(vec![0]));
+        exchange(&a, &mut a_rx, &b, &mut b_rx);
+        while a_rx.try_recv().is_ok() {}
+
+        // message of an established session, sent from another address, is not decrypted
+        b.send_message(a.local_id, find_node(vec![0]));
+        let (_, packet) = b_rx.try_recv().unwrap();
+        a.handle_packet(SocketAddr::from(([10, 0, 0, 1], 30303)), &packet);
+        let (dest, whoareyou) = a_rx.try_recv().unwrap();
+        assert_eq!(dest, SocketAddr::from(([10, 0, 0, 1], 30303)));
+        assert!(matches!(
+            Packet::decode(&b.local_id, &whoareyou).unwrap().kind,
+            PacketKind::WhoAreYou { .. }
+        ));
+    }
+}
@@ -0,0 +1,129 @@
+use bytes::{Bytes, BytesMut};
+use open_fastrlp::{Decodable, DecodeError, Encodable, Header, RlpEncodable};
+
+use crate::eth::types::protocol::EthProtocol;
+use crate::p2p::protocol::ProtocolVersion;
+
+use super::eth_message::EthMessage;
+
+/*
+* We don't store any chain data, but peers which don't get answers to their header/body/receipt
+* requests eventually time out and drop us. Every such request is answered with an empty
+* response, which is valid reply ("don't have it") for all of them.
+* Since eth/66 requests are [request_id, payload], and response has to echo the request id.
+* Before eth/66 (eth/65 is still advertised) there is no request id, response is a bare list.
+* eth/69 changed the receipt encoding (no bloom), but an empty receipts list is the same in
+* every version.
+* */
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable)]
+pub struct EmptyResponse {
+    request_id: u64,
+    items: Vec<Bytes>,
+}
+
+impl EmptyResponse {
+    /// Response for the request, `None` if the message is not a request we answer
+    pub fn for_request(
+        request: &EthMessage,
+        proto_v: ProtocolVersion,
+    ) -> Result<Option<EthMessage>, DecodeError> {
+        let response_id = match request.id {
+            EthProtocol::GetBlockHeadersMsg => EthProtocol::BlockHeadersMsg,
+            EthProtocol::GetBlockBodiesMsg => EthProtocol::BlockBodiesMsg,
+            EthProtocol::GetReceiptsMsg => EthProtocol::ReceiptsMsg,
+            EthProtocol::GetNodeDataMsg => EthProtocol::NodeDataMsg,
+            _ => return Ok(None),
+        };
+
+        if proto_v < ProtocolVersion::Eth66 {
+            let mut rlp = BytesMut::new();
+            Vec::<Bytes>::new().encode(&mut rlp);
+            return Ok(Some(EthMessage::new(response_id, rlp.freeze())));
+        }
+
+        let response = Self {
+            request_id: decode_request_id(&mut &request.data[..])?,
+            items: Vec::new(),
+        };
+        Ok(Some(EthMessage::new(response_id, response.rlp_encode())))
+    }
+
+    pub fn rlp_encode(&self) -> Bytes {
+        let mut rlp = BytesMut::new();
+        self.encode(&mut rlp);
+        rlp.freeze()
+    }
+}
+
+/// Request id of eth/66+ request, the rest of the request is not decoded
+fn decode_request_id(buf: &mut &[u8]) -> Result<u64, DecodeError> {
+    let h = Header::decode(buf)?;
+    if !h.list {
+        return Err(DecodeError::UnexpectedString);
+    }
+
+    u64::decode(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::types::hash::H256;
+
+    #[derive(RlpEncodable)]
+    struct GetBlockBodies {
+        request_id: u64,
+        hashes: Vec<H256>,
+    }
+
+    #[test]
+    fn echoes_request_id() {
+        let mut request = BytesMut::new();
+        GetBlockBodies {
+            request_id: 0x1234,
+            hashes: vec![H256::repeat_byte(1), H256::repeat_byte(2)],
+        }
+        .encode(&mut request);
+
+        let response = EmptyResponse::for_request(
+            &EthMessage::new(EthProtocol::GetBlockBodiesMsg, request.freeze()),
+            ProtocolVersion::Eth66,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(response.id, EthProtocol::BlockBodiesMsg);
+        // [0x1234, []]
+        assert_eq!(&response.data[..], &hex!("c4821234c0")[..]);
+    }
+
+    #[test]
+    fn answers_eth65_without_request_id() {
+        let mut request = BytesMut::new();
+        vec![H256::repeat_byte(1)].encode(&mut request);
+
+        let response = EmptyResponse::for_request(
+            &EthMessage::new(EthProtocol::GetReceiptsMsg, request.freeze()),
+            ProtocolVersion::Eth65,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(response.id, EthProtocol::ReceiptsMsg);
+        assert_eq!(&response.data[..], &hex!("c0")[..]);
+    }
+
+    #[test]
+    fn ignores_other_messages() {
+        let msg = EthMessage::new(EthProtocol::TransactionsMsg, Bytes::new());
+        assert_eq!(
+            EmptyResponse::for_request(&msg, ProtocolVersion::Eth68),
+            Ok(None)
+        );
+
+        let msg = EthMessage::new(EthProtocol::GetReceiptsMsg, Bytes::from_static(&[0x80]));
+        assert!(EmptyResponse::for_request(&msg, ProtocolVersion::Eth68).is_err());
+    }
+}
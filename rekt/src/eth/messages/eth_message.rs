@@ -39,6 +39,36 @@ impl EthMessage {
         }
     }
 
+    /// Snappy compresses the message up front, so it skips the writer queue like our buy txs do
+    pub fn new_compressed_message(id: EthProtocol, rlp: &[u8]) -> Self {
+        let mut compressed = BytesMut::zeroed(1 + snap::raw::max_compress_len(rlp.len()));
+        let compressed_size = snap::raw::Encoder::new()
+            .compress(rlp, &mut compressed[1..])
+            .expect("Failed to snappy compress message");
+
+        compressed[0] = id as u8 + ETH_PROTOCOL_OFFSET;
+        compressed.truncate(compressed_size + 1);
+
+        Self {
+            data: compressed.freeze(),
+            id,
+            compressed: EthMessageCompressionStatus::Compressed,
+        }
+    }
+
+    /// Rlp of the message, pre-compressed messages are decompressed
+    pub fn decompressed_rlp(&self) -> Result<Bytes, DecodeError> {
+        if !self.is_compressed() {
+            return Ok(self.data.clone());
+        }
+
+        // pre-compressed message starts with message id
+        snap::raw::Decoder::new()
+            .decompress_vec(self.data.get(1..).unwrap_or_default())
+            .map(Bytes::from)
+            .map_err(|_| DecodeError::Custom("Could not snap decompress msg"))
+    }
+
     pub fn new_devp2p_ping_message() -> Self {
         Self {
             data: Bytes::new(),
@@ -71,9 +101,8 @@ impl EthMessage {
 
 impl From<P2pWireMessage> for EthMessage {
     fn from(msg: P2pWireMessage) -> Self {
-        let id = msg.id - ETH_PROTOCOL_OFFSET;
         Self {
-            id: EthProtocol::from(id),
+            id: EthProtocol::from(msg.id),
             data: msg.data,
             compressed: EthMessageCompressionStatus::Uncompressed,
         }
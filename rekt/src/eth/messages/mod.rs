@@ -1,3 +1,6 @@
+pub mod empty_response;
 pub mod eth_message;
+pub mod new_block;
 pub mod status_message;
 pub mod transactions_request;
+pub mod trie;
@@ -0,0 +1,269 @@
+use bytes::{Buf, Bytes};
+use ethers::utils::keccak256;
+use open_fastrlp::{Decodable, DecodeError, Header, HeaderInfo, RlpDecodable};
+
+use super::trie::ordered_trie_root;
+use crate::blockchain::head::Head;
+use crate::types::hash::H256;
+
+/// Number of header fields before txs root: parent hash, uncles hash, coinbase and state root
+const HEADER_FIELDS_BEFORE_TXS_ROOT: usize = 4;
+/// Number of header fields between txs root and `difficulty`: receipts root and bloom
+const HEADER_FIELDS_BEFORE_DIFFICULTY: usize = 2;
+
+/// Block header fields we care about, the rest is skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub hash: H256,
+    pub txs_root: H256,
+    pub number: u64,
+    pub difficulty: u64,
+    pub gas_limit: u64,
+    pub timestamp: u64,
+}
+
+/// `NewBlockMsg`: [[header, txs, uncles, ...], td]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewBlock {
+    pub header: BlockHeader,
+    /// Txs as they are hashed: rlp list for legacy txs, `type || rlp` for typed ones
+    pub txs: Vec<Bytes>,
+    pub td: u128,
+}
+
+/// Single entry of `NewBlockHashesMsg`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, RlpDecodable)]
+pub struct BlockHashNumber {
+    pub hash: H256,
+    pub number: u64,
+}
+
+impl BlockHeader {
+    /// Decodes header fields needed for the head, block hash is keccak of the whole header rlp
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let info = HeaderInfo::decode(buf)?;
+        if !info.list {
+            return Err(DecodeError::UnexpectedString);
+        }
+        let hash = H256(keccak256(&buf[..info.total_len]));
+
+        let header = Header::decode_from_info(buf, info)?;
+        let payload_view = &mut &buf[..header.payload_length];
+        buf.advance(header.payload_length);
+
+        for _ in 0..HEADER_FIELDS_BEFORE_TXS_ROOT {
+            HeaderInfo::skip_next_item(payload_view)?;
+        }
+        let txs_root = H256::decode(payload_view)?;
+        for _ in 0..HEADER_FIELDS_BEFORE_DIFFICULTY {
+            HeaderInfo::skip_next_item(payload_view)?;
+        }
+        let difficulty = u64::decode(payload_view)?;
+        let number = u64::decode(payload_view)?;
+        let gas_limit = u64::decode(payload_view)?;
+        let _skip_gas_used = HeaderInfo::skip_next_item(payload_view)?;
+        let timestamp = u64::decode(payload_view)?;
+
+        Ok(Self {
+            hash,
+            txs_root,
+            number,
+            difficulty,
+            gas_limit,
+            timestamp,
+        })
+    }
+
+    pub fn head(&self, total_difficulty: u128) -> Head {
+        Head {
+            number: self.number,
+            hash: self.hash,
+            difficulty: self.difficulty,
+            total_difficulty: u64::try_from(total_difficulty).unwrap_or(u64::MAX),
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+impl NewBlock {
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let h = Header::decode(buf)?;
+        if !h.list {
+            return Err(DecodeError::UnexpectedString);
+        }
+
+        let block = Header::decode(buf)?;
+        if !block.list {
+            return Err(DecodeError::UnexpectedString);
+        }
+        // uncles and the rest of the block are skipped
+        let block_view = &mut &buf[..block.payload_length];
+        let header = BlockHeader::decode(block_view)?;
+        let txs = decode_block_txs(block_view)?;
+        buf.advance(block.payload_length);
+
+        let td = u128::decode(buf)?;
+
+        Ok(Self { header, txs, td })
+    }
+
+    /// Whether txs are the ones the header commits to, peers can't make up txs of a real block
+    pub fn has_valid_txs_root(&self) -> bool {
+        ordered_trie_root(&self.txs) == self.header.txs_root
+    }
+}
+
+/// Txs of the block body, each one is copied so it can outlive the message
+pub fn decode_block_txs(buf: &mut &[u8]) -> Result<Vec<Bytes>, DecodeError> {
+    let h = Header::decode(buf)?;
+    if !h.list {
+        return Err(DecodeError::UnexpectedString);
+    }
+
+    let payload_view = &mut &buf[..h.payload_length];
+    buf.advance(h.payload_length);
+
+    let mut txs = Vec::new();
+    while !payload_view.is_empty() {
+        let info = HeaderInfo::decode(payload_view)?;
+        if info.list {
+            // legacy tx is hashed with its rlp header
+            txs.push(Bytes::copy_from_slice(&payload_view[..info.total_len]));
+            payload_view.advance(info.total_len);
+        } else {
+            let tx = Header::decode_from_info(payload_view, info)?;
+            txs.push(Bytes::copy_from_slice(&payload_view[..tx.payload_length]));
+            payload_view.advance(tx.payload_length);
+        }
+    }
+
+    Ok(txs)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::{BufMut, BytesMut};
+    use open_fastrlp::{Encodable, RlpEncodable};
+
+    use super::*;
+
+    #[derive(RlpEncodable)]
+    struct TestHeader {
+        parent_hash: H256,
+        uncles_hash: H256,
+        coinbase: H256,
+        state_root: H256,
+        txs_root: H256,
+        receipts_root: H256,
+        bloom: Bytes,
+        difficulty: u64,
+        number: u64,
+        gas_limit: u64,
+        gas_used: u64,
+        timestamp: u64,
+        extra_data: Bytes,
+    }
+
+    /// Legacy tx is rlp list, typed one is string with `type || rlp`
+    enum TestTx {
+        Legacy(Vec<u64>),
+        Typed(Bytes),
+    }
+
+    impl Encodable for TestTx {
+        fn encode(&self, out: &mut dyn BufMut) {
+            match self {
+                TestTx::Legacy(fields) => fields.encode(out),
+                TestTx::Typed(tx) => tx.encode(out),
+            }
+        }
+
+        fn length(&self) -> usize {
+            match self {
+                TestTx::Legacy(fields) => fields.length(),
+                TestTx::Typed(tx) => tx.length(),
+            }
+        }
+    }
+
+    #[derive(RlpEncodable)]
+    struct TestBlock {
+        header: TestHeader,
+        txs: Vec<TestTx>,
+        uncles: Vec<u64>,
+    }
+
+    #[derive(RlpEncodable)]
+    struct TestNewBlock {
+        block: TestBlock,
+        td: u128,
+    }
+
+    #[test]
+    fn decode_new_block() {
+        let header = TestHeader {
+            parent_hash: H256::repeat_byte(1),
+            uncles_hash: H256::repeat_byte(2),
+            coinbase: H256::repeat_byte(3),
+            state_root: H256::repeat_byte(4),
+            txs_root: H256::repeat_byte(5),
+            receipts_root: H256::repeat_byte(6),
+            bloom: Bytes::from(vec![0; 256]),
+            difficulty: 2,
+            number: 40_000_000,
+            gas_limit: 140_000_000,
+            gas_used: 21_000,
+            timestamp: 1_720_000_000,
+            extra_data: Bytes::from(vec![7; 97]),
+        };
+        let mut header_rlp = BytesMut::new();
+        header.encode(&mut header_rlp);
+
+        let mut rlp = BytesMut::new();
+        TestNewBlock {
+            block: TestBlock {
+                header,
+                txs: vec![
+                    TestTx::Legacy(vec![1, 2, 3]),
+                    TestTx::Typed(Bytes::from_static(&[2, 0xc3, 1, 2, 3])),
+                ],
+                uncles: vec![],
+            },
+            td: 80_000_000,
+        }
+        .encode(&mut rlp);
+
+        let new_block = NewBlock::decode(&mut &rlp[..]).unwrap();
+        assert_eq!(new_block.td, 80_000_000);
+        assert_eq!(
+            new_block.header,
+            BlockHeader {
+                hash: H256(keccak256(&header_rlp)),
+                txs_root: H256::repeat_byte(5),
+                number: 40_000_000,
+                difficulty: 2,
+                gas_limit: 140_000_000,
+                timestamp: 1_720_000_000,
+            }
+        );
+        assert_eq!(
+            new_block.txs,
+            vec![
+                Bytes::from_static(&[0xc3, 1, 2, 3]),
+                Bytes::from_static(&[2, 0xc3, 1, 2, 3])
+            ]
+        );
+
+        // txs root of the header is made up
+        assert!(!new_block.has_valid_txs_root());
+        let committed = NewBlock {
+            header: BlockHeader {
+                txs_root: ordered_trie_root(&new_block.txs),
+                ..new_block.header
+            },
+            ..new_block.clone()
+        };
+        assert!(committed.has_valid_txs_root());
+        assert!(NewBlock::decode(&mut &rlp[..rlp.len() - 1]).is_err());
+    }
+}
@@ -8,9 +8,11 @@ use open_fastrlp::{Encodable, RlpDecodable, RlpEncodable};
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
-use crate::blockchain::bsc_chain_spec::{BSC_MAINNET_FORK_FILTER, BSC_MAINNET_FORK_ID};
+use crate::blockchain::chain_spec;
 use crate::blockchain::fork::ForkId;
-use crate::blockchain::BSC_MAINNET;
+use crate::blockchain::head_tracker::{
+    current_fork_id, current_head, status_td_and_hash, validate_fork_id,
+};
 use crate::eth::types::protocol::EthProtocol;
 use crate::p2p::protocol::ProtocolVersion;
 use crate::types::hash::H256;
@@ -27,10 +29,10 @@ pub struct StatusMessage {
 
     /// The chain id, as introduced in
     /// [EIP155](https://eips.ethereum.org/EIPS/eip-155#list-of-chain-ids).
-    pub chain: u8,
+    pub chain: u64,
 
     /// Total difficulty of the best chain.
-    pub total_difficulty: u64,
+    pub total_difficulty: u128,
 
     /// The highest difficulty block hash the peer has seen
     pub blockhash: H256,
@@ -48,14 +50,15 @@ pub struct StatusMessage {
 
 impl Default for StatusMessage {
     fn default() -> Self {
+        let (total_difficulty, blockhash) = status_td_and_hash();
         Self {
             version: ProtocolVersion::default() as u8,
             // negotiated
-            chain: BSC_MAINNET.chain,
-            total_difficulty: BSC_MAINNET.td,
-            blockhash: BSC_MAINNET.genesis_hash,
-            genesis: BSC_MAINNET.genesis_hash,
-            forkid: *BSC_MAINNET_FORK_ID,
+            chain: chain_spec().chain,
+            total_difficulty,
+            blockhash,
+            genesis: chain_spec().genesis_hash,
+            forkid: current_fork_id(),
         }
     }
 }
@@ -123,39 +126,110 @@ impl StatusMessage {
         peer_status_msg: &StatusMessage,
         proto_v_negotiated: &ProtocolVersion,
     ) -> Result<(), &'static str> {
-        if *proto_v_negotiated as u8 != peer_status_msg.version {
-            error!(
-                "Protocol version mismatch, received {:?}",
-                peer_status_msg.version
-            );
-            return Err("Protocol version mismatch");
-        }
+        validate_status(
+            proto_v_negotiated,
+            peer_status_msg.version,
+            peer_status_msg.chain,
+            peer_status_msg.genesis,
+            peer_status_msg.forkid,
+        )
+    }
+}
 
-        if BSC_MAINNET.chain != peer_status_msg.chain {
-            error!("Chain ID mismatch, received {:?}", peer_status_msg.chain);
-            return Err("Chain ID mismatch");
-        }
+/// Checks shared by status messages of all versions
+fn validate_status(
+    proto_v_negotiated: &ProtocolVersion,
+    version: u8,
+    chain: u64,
+    genesis: H256,
+    forkid: ForkId,
+) -> Result<(), &'static str> {
+    if *proto_v_negotiated as u8 != version {
+        error!("Protocol version mismatch, received {:?}", version);
+        return Err("Protocol version mismatch");
+    }
 
-        if BSC_MAINNET.genesis_hash != peer_status_msg.genesis {
-            error!(
-                "Genesis hash mismatch, received {:?}",
-                peer_status_msg.genesis
-            );
-            return Err("Genesis hash mismatch");
+    if chain_spec().chain != chain {
+        error!("Chain ID mismatch, received {:?}", chain);
+        return Err("Chain ID mismatch");
+    }
+
+    if chain_spec().genesis_hash != genesis {
+        error!("Genesis hash mismatch, received {:?}", genesis);
+        return Err("Genesis hash mismatch");
+    }
+
+    if validate_fork_id(forkid).is_err() {
+        error!("Fork ID mismatch, received {:X?}", forkid);
+        return Err("Fork ID Mismatch");
+    }
+
+    Ok(())
+}
+
+/*
+* eth/69 status drops total difficulty and best hash, instead peer tells which blocks it can serve
+* (earliest..=latest) and the hash of the latest one. The range is kept up to date with
+* `BlockRangeUpdateMsg` (eth/69 peers don't send `NewBlockMsg`/`NewBlockHashesMsg`).
+* We don't serve any blocks (all requests get empty responses), so our range is just the head.
+* */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable, Serialize, Deserialize)]
+pub struct StatusMessage69 {
+    pub version: u8,
+    pub chain: u64,
+    pub genesis: H256,
+    pub forkid: ForkId,
+    pub earliest_block: u64,
+    pub latest_block: u64,
+    pub latest_block_hash: H256,
+}
+
+impl Default for StatusMessage69 {
+    fn default() -> Self {
+        let head = current_head();
+        Self {
+            version: ProtocolVersion::Eth69 as u8,
+            chain: chain_spec().chain,
+            genesis: chain_spec().genesis_hash,
+            forkid: current_fork_id(),
+            earliest_block: head.number,
+            latest_block: head.number,
+            latest_block_hash: head.hash,
         }
+    }
+}
+
+impl StatusMessage69 {
+    pub fn get() -> EthMessage {
+        let mut status_rlp = BytesMut::new();
+        Self::default().encode(&mut status_rlp);
+        EthMessage::new(EthProtocol::StatusMsg, status_rlp.freeze())
+    }
 
-        if BSC_MAINNET_FORK_FILTER
-            .validate(peer_status_msg.forkid)
-            .is_err()
-        {
-            error!("Fork ID mismatch, received {:X?}", peer_status_msg.forkid);
-            return Err("Fork ID Mismatch");
+    pub fn validate(peer_status_msg: &StatusMessage69) -> Result<(), &'static str> {
+        if peer_status_msg.earliest_block > peer_status_msg.latest_block {
+            error!("Invalid block range, received {:?}", peer_status_msg);
+            return Err("Invalid block range");
         }
 
-        Ok(())
+        validate_status(
+            &ProtocolVersion::Eth69,
+            peer_status_msg.version,
+            peer_status_msg.chain,
+            peer_status_msg.genesis,
+            peer_status_msg.forkid,
+        )
     }
 }
 
+/// `BlockRangeUpdateMsg` of eth/69
+#[derive(Debug, Copy, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+pub struct BlockRangeUpdate {
+    pub earliest_block: u64,
+    pub latest_block: u64,
+    pub latest_block_hash: H256,
+}
+
 #[derive(
     Clone,
     Copy,
@@ -208,3 +282,37 @@ impl UpgradeStatusMessage {
         status_rlp.freeze()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use open_fastrlp::Decodable;
+
+    use super::*;
+
+    #[test]
+    fn eth69_status_roundtrip() {
+        let ours = StatusMessage69::default();
+        let msg = StatusMessage69::get();
+        assert_eq!(msg.id, EthProtocol::StatusMsg);
+
+        let decoded = StatusMessage69::decode(&mut &msg.data[..]).unwrap();
+        assert_eq!(decoded, ours);
+        assert_eq!(StatusMessage69::validate(&decoded), Ok(()));
+
+        // eth/69 status is not a valid legacy status and vice versa
+        assert!(StatusMessage::decode(&mut &msg.data[..]).is_err());
+        let legacy = StatusMessage::get(&ProtocolVersion::Eth68);
+        assert!(StatusMessage69::decode(&mut &legacy.data[..]).is_err());
+
+        let bad_range = StatusMessage69 {
+            earliest_block: ours.latest_block + 1,
+            ..ours
+        };
+        assert!(StatusMessage69::validate(&bad_range).is_err());
+        let old_version = StatusMessage69 {
+            version: 68,
+            ..ours
+        };
+        assert!(StatusMessage69::validate(&old_version).is_err());
+    }
+}
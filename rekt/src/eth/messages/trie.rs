@@ -0,0 +1,194 @@
+use bytes::BytesMut;
+use ethers::utils::keccak256;
+use hex_literal::hex;
+use open_fastrlp::{Encodable, Header};
+
+use crate::types::hash::H256;
+
+/// Root of a trie without entries, keccak256 of rlp of an empty string
+pub const EMPTY_ROOT: H256 = H256(hex!(
+    "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+));
+
+const EMPTY_STRING: u8 = 0x80;
+
+/*
+* Root of the Merkle Patricia trie, built at once from all entries, without keeping the nodes.
+* We only need it to check that txs of a `NewBlockMsg` are the ones committed to by its header.
+*
+* Nodes are rlp lists:
+*   leaf       [hex prefix path, value]
+*   extension  [hex prefix path, child]
+*   branch     [child for every nibble (16), value]
+* Child nodes shorter than 32 bytes are embedded, longer ones are referenced by their keccak256.
+* */
+
+/// Root of the trie of block txs (or receipts), keyed by rlp of their index
+pub fn ordered_trie_root<T: AsRef<[u8]>>(values: &[T]) -> H256 {
+    trie_root(values.iter().enumerate().map(|(index, value)| {
+        let mut key = BytesMut::new();
+        index.encode(&mut key);
+        (key.to_vec(), value.as_ref())
+    }))
+}
+
+pub fn trie_root<'a>(entries: impl IntoIterator<Item = (Vec<u8>, &'a [u8])>) -> H256 {
+    let mut entries = entries
+        .into_iter()
+        .map(|(key, value)| (nibbles(&key), value))
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return EMPTY_ROOT;
+    }
+
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    H256(keccak256(encode_node(&entries, 0)))
+}
+
+/// Entries are sorted and share the first `depth` nibbles of their keys
+fn encode_node(entries: &[(Vec<u8>, &[u8])], depth: usize) -> Vec<u8> {
+    if let [(key, value)] = entries {
+        return list(&[string(&hex_prefix(&key[depth..], true)), string(value)]);
+    }
+
+    // keys are sorted, so the prefix shared by all of them is the one of the first and the last
+    let (first, last) = (&entries[0].0, &entries[entries.len() - 1].0);
+    let shared = first[depth..]
+        .iter()
+        .zip(&last[depth..])
+        .take_while(|(a, b)| a == b)
+        .count();
+    if shared > 0 {
+        let child = encode_node(entries, depth + shared);
+        return list(&[
+            string(&hex_prefix(&first[depth..depth + shared], false)),
+            reference(child),
+        ]);
+    }
+
+    let mut rest = entries;
+    let mut value = vec![EMPTY_STRING];
+    if rest[0].0.len() == depth {
+        value = string(rest[0].1);
+        rest = &rest[1..];
+    }
+
+    let mut items = Vec::with_capacity(17);
+    for nibble in 0..16 {
+        let count = rest
+            .iter()
+            .take_while(|(key, _)| key[depth] == nibble)
+            .count();
+        let (children, tail) = rest.split_at(count);
+        rest = tail;
+        items.push(match children {
+            [] => vec![EMPTY_STRING],
+            _ => reference(encode_node(children, depth + 1)),
+        });
+    }
+    items.push(value);
+
+    list(&items)
+}
+
+fn reference(node: Vec<u8>) -> Vec<u8> {
+    if node.len() < 32 {
+        node
+    } else {
+        string(&keccak256(node))
+    }
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+/// Path packed to bytes, the first nibble says whether it's a leaf and whether its length is odd
+fn hex_prefix(path: &[u8], is_leaf: bool) -> Vec<u8> {
+    let flag = if is_leaf { 2 } else { 0 };
+    let mut packed = Vec::with_capacity(path.len() / 2 + 1);
+    let pairs = if path.len() % 2 == 1 {
+        packed.push(((flag + 1) << 4) | path[0]);
+        &path[1..]
+    } else {
+        packed.push(flag << 4);
+        path
+    };
+    packed.extend(pairs.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    packed
+}
+
+fn string(bytes: &[u8]) -> Vec<u8> {
+    let mut out = BytesMut::new();
+    bytes.encode(&mut out);
+    out.to_vec()
+}
+
+/// List of already encoded items
+fn list(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = BytesMut::new();
+    Header {
+        list: true,
+        payload_length: items.iter().map(Vec::len).sum(),
+    }
+    .encode(&mut out);
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    out.to_vec()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn root(entries: &[(&str, &str)]) -> H256 {
+        trie_root(
+            entries
+                .iter()
+                .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes())),
+        )
+    }
+
+    // vectors of geth `TestInsert`
+    #[test]
+    fn trie_roots() {
+        assert_eq!(trie_root(Vec::new()), EMPTY_ROOT);
+        assert_eq!(ordered_trie_root::<&[u8]>(&[]), EMPTY_ROOT);
+
+        assert_eq!(
+            root(&[
+                ("doe", "reindeer"),
+                ("dog", "puppy"),
+                ("dogglesworth", "cat")
+            ]),
+            H256(hex!(
+                "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"
+            ))
+        );
+        assert_eq!(
+            root(&[("A", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")]),
+            H256(hex!(
+                "d23786fb4a010da3ce639d66d5e904a11dbc02746d1ce25029e53290cabf28ab"
+            ))
+        );
+    }
+
+    #[test]
+    fn order_of_entries_does_not_matter() {
+        let entries = [
+            ("dogglesworth", "cat"),
+            ("doe", "reindeer"),
+            ("dog", "puppy"),
+        ];
+        assert_eq!(
+            root(&entries),
+            root(&[
+                ("doe", "reindeer"),
+                ("dog", "puppy"),
+                ("dogglesworth", "cat")
+            ])
+        );
+        assert_ne!(root(&entries), root(&entries[..2]));
+    }
+}
@@ -3,11 +3,16 @@ use std::usize;
 use bytes::Buf;
 use open_fastrlp::{Decodable, Header, HeaderInfo};
 
+use crate::blockchain::head_tracker;
 use crate::p2p::protocol::ProtocolVersion;
-use crate::token::tokens_to_buy::{MAX_SIZE, MIN_SIZE};
-use crate::types::hash::H256;
+use crate::p2p::tx_latency;
+use crate::token::tokens_to_buy::tx_size_is_ok;
+use crate::types::hash::{H256, H512};
 
+use super::empty_response::EmptyResponse;
 use super::eth_message::EthMessage;
+use super::new_block::{BlockHashNumber, NewBlock};
+use super::status_message::BlockRangeUpdate;
 use super::transactions::decoder::{decode_txs, decode_txs_request, BuyTokenInfo};
 use super::transactions::*;
 use super::transactions_request::TransactionsRequest;
@@ -23,30 +28,88 @@ pub enum EthMessageHandler {
 pub fn handle_eth_message(
     msg: EthMessage,
     proto_v: ProtocolVersion,
+    peer: &H512,
 ) -> Result<EthMessageHandler, ETHError> {
     match msg.id {
-        EthProtocol::TransactionsMsg => handle_txs(msg),
-        EthProtocol::PooledTransactionsMsg => handle_txs(msg),
-        EthProtocol::NewPooledTransactionHashesMsg => handle_tx_hashes(msg, proto_v),
+        EthProtocol::TransactionsMsg => handle_txs(msg, peer),
+        EthProtocol::PooledTransactionsMsg => handle_txs(msg, peer),
+        EthProtocol::NewPooledTransactionHashesMsg => handle_tx_hashes(msg, proto_v, peer),
+        EthProtocol::NewBlockMsg => handle_new_block(msg, peer),
+        EthProtocol::NewBlockHashesMsg => handle_new_block_hashes(msg, peer),
+        EthProtocol::BlockRangeUpdateMsg => handle_block_range_update(msg, peer),
+        EthProtocol::GetPooledTransactionsMsg => handle_pooled_txs_request(msg),
+        EthProtocol::GetBlockHeadersMsg
+        | EthProtocol::GetBlockBodiesMsg
+        | EthProtocol::GetReceiptsMsg
+        | EthProtocol::GetNodeDataMsg => handle_chain_data_request(msg, proto_v),
         _ => Ok(EthMessageHandler::None),
     }
 }
 
+fn handle_chain_data_request(
+    msg: EthMessage,
+    proto_v: ProtocolVersion,
+) -> Result<EthMessageHandler, ETHError> {
+    match EmptyResponse::for_request(&msg, proto_v)? {
+        Some(response) => Ok(EthMessageHandler::Response(response)),
+        None => Ok(EthMessageHandler::None),
+    }
+}
+
+fn handle_pooled_txs_request(msg: EthMessage) -> Result<EthMessageHandler, ETHError> {
+    Ok(EthMessageHandler::Response(our_pool::pooled_txs_response(
+        &msg,
+    )?))
+}
+
 fn handle_tx_hashes(
     msg: EthMessage,
     proto_v: ProtocolVersion,
+    peer: &H512,
 ) -> Result<EthMessageHandler, ETHError> {
     if proto_v < ProtocolVersion::Eth68 {
-        handle_tx_hashes_before_eth_68(msg)
+        handle_tx_hashes_before_eth_68(msg, peer)
     } else {
-        handle_tx_hashes_after_eth_68(msg)
+        handle_tx_hashes_after_eth_68(msg, peer)
     }
 }
 
-fn handle_txs(msg: EthMessage) -> Result<EthMessageHandler, ETHError> {
+fn handle_new_block(msg: EthMessage, peer: &H512) -> Result<EthMessageHandler, ETHError> {
+    let new_block = NewBlock::decode(&mut &msg.data[..])?;
+    head_tracker::on_new_block(
+        *peer,
+        new_block.header.head(new_block.td),
+        new_block.td,
+        new_block.header.gas_limit,
+    );
+    confirmations::on_block(&new_block);
+
+    Ok(EthMessageHandler::None)
+}
+
+fn handle_new_block_hashes(msg: EthMessage, peer: &H512) -> Result<EthMessageHandler, ETHError> {
+    let announced: Vec<BlockHashNumber> = Vec::decode(&mut &msg.data[..])?;
+    if let Some(latest) = announced.iter().max_by_key(|block| block.number) {
+        head_tracker::on_block_hash(*peer, latest.number, latest.hash);
+    }
+
+    Ok(EthMessageHandler::None)
+}
+
+/// eth/69 peers announce their latest block only through block range updates
+fn handle_block_range_update(msg: EthMessage, peer: &H512) -> Result<EthMessageHandler, ETHError> {
+    let update = BlockRangeUpdate::decode(&mut &msg.data[..])?;
+    if update.earliest_block <= update.latest_block {
+        head_tracker::on_block_hash(*peer, update.latest_block, update.latest_block_hash);
+    }
+
+    Ok(EthMessageHandler::None)
+}
+
+fn handle_txs(msg: EthMessage, peer: &H512) -> Result<EthMessageHandler, ETHError> {
     let buy_info = match msg.id {
-        EthProtocol::TransactionsMsg => decode_txs(&mut &msg.data[..], true),
-        EthProtocol::PooledTransactionsMsg => decode_txs_request(&mut &msg.data[..]),
+        EthProtocol::TransactionsMsg => decode_txs(&mut &msg.data[..], true, peer),
+        EthProtocol::PooledTransactionsMsg => decode_txs_request(&mut &msg.data[..], peer),
         _ => Ok(None),
     };
 
@@ -57,12 +120,16 @@ fn handle_txs(msg: EthMessage) -> Result<EthMessageHandler, ETHError> {
     Ok(EthMessageHandler::None)
 }
 
-fn handle_tx_hashes_before_eth_68(msg: EthMessage) -> Result<EthMessageHandler, ETHError> {
+fn handle_tx_hashes_before_eth_68(
+    msg: EthMessage,
+    peer: &H512,
+) -> Result<EthMessageHandler, ETHError> {
     //TODO: optimize with custom rlp decoder
     let hashes: Vec<H256> = Vec::decode(&mut &msg.data[..])?;
 
     let hashes_to_request = hashes
         .into_iter()
+        .inspect(|hash| tx_latency::on_tx_seen(hash, peer))
         .filter(|hash| cache::mark_as_requested(hash) == cache::TxCacheStatus::NotRequested)
         .collect::<Vec<_>>();
 
@@ -80,7 +147,10 @@ fn handle_tx_hashes_before_eth_68(msg: EthMessage) -> Result<EthMessageHandler,
     )))
 }
 
-fn handle_tx_hashes_after_eth_68(msg: EthMessage) -> Result<EthMessageHandler, ETHError> {
+fn handle_tx_hashes_after_eth_68(
+    msg: EthMessage,
+    peer: &H512,
+) -> Result<EthMessageHandler, ETHError> {
     let buf = &mut &msg.data[..];
     let h = Header::decode(buf)?;
     if !h.list {
@@ -119,7 +189,8 @@ fn handle_tx_hashes_after_eth_68(msg: EthMessage) -> Result<EthMessageHandler, E
     let mut i = 0;
     while !payload_view.is_empty() {
         let hash = H256::decode(payload_view)?;
-        if !tx_size_is_valid(sizes[i]) {
+        tx_latency::on_tx_seen(&hash, peer);
+        if !tx_size_is_ok(sizes[i]) {
             cache::mark_as_fetched(&hash);
             continue;
         }
@@ -143,13 +214,3 @@ fn handle_tx_hashes_after_eth_68(msg: EthMessage) -> Result<EthMessageHandler, E
         TransactionsRequest::new(hashes).rlp_encode(),
     )))
 }
-
-fn tx_size_is_valid(size: usize) -> bool {
-    unsafe {
-        if MIN_SIZE == 0 && MAX_SIZE == 0 {
-            return true;
-        }
-
-        size >= MIN_SIZE && size <= MAX_SIZE
-    }
-}
@@ -1,9 +1,36 @@
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use once_cell::sync::OnceCell;
+
 use crate::types::hash::H256;
 
-pub(super) static mut CACHE: Vec<u8> = Vec::new();
 pub(super) const ALREADY_FETCHED_MARKER: u8 = 100;
 pub(super) const MAX_REQUEST_COUNT: u8 = 2;
 
+/// How many txs we expect to see before the oldest generation is dropped.
+/// BSC blocks carry a few hundred txs, and we see a lot more than that in the tx-pool, so this
+/// keeps a couple of minutes worth of hashes around.
+pub const DEFAULT_TXS_PER_GENERATION: usize = 500_000;
+
+/// Number of generations we keep in memory, a hash is "forgotten" after it was not seen
+/// in any of them.
+const GENERATIONS: usize = 4;
+
+/// One more generation is kept aside, cleared and ready to become the current one
+const ALLOCATED_GENERATIONS: usize = GENERATIONS + 1;
+
+/// Number of slots per tx in one generation, this is what drives false positive rate
+/// (see [SeenTxCache::false_positive_rate])
+const SLOTS_PER_TX: usize = 16;
+
+/// How many slots we mark for every tx hash.
+/// Tx hash is keccak output, so every 8 bytes of it are "random enough" to be used as index.
+const SLOTS_PER_HASH: usize = 4;
+
+static CACHE: OnceCell<SeenTxCache> = OnceCell::new();
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum TxCacheStatus {
     NotRequested,
@@ -12,46 +39,342 @@ pub enum TxCacheStatus {
     NotFetched,
 }
 
-pub fn init_cache() {
-    unsafe {
-        let cache_size = u32::MAX as usize + 1;
-        CACHE.reserve_exact(cache_size);
-        for _ in 0..cache_size {
-            CACHE.push(0);
+/*
+* The seen-tx cache used to be 4GiB array indexed by first 4 bytes of tx hash.
+* Apart from memory, the issue with it was that unrelated txs were colliding on the same index,
+* and we'd silently treat them as "already fetched".
+*
+* This is count-min-sketch like structure:
+* 1. every hash is mapped to `SLOTS_PER_HASH` slots
+* 2. the value for the hash is the minimum of the slots (so collision on one slot doesn't matter)
+* 3. on update we only ever raise slots (conservative update), so the counts are never
+*    underestimated
+*
+* To make sure the structure doesn't "fill up" over time, slots are split into generations.
+* New values are always written to the current generation, lookups check all of them.
+* Once the current generation saw enough distinct txs, the spare generation (cleared in advance)
+* becomes the current one, and the oldest generation is dropped out of lookups and becomes the
+* spare. It is cleared on its own thread, so the insert which rotated doesn't clear millions of
+* slots inline, and no generation is cleared while it can be written to.
+* */
+pub struct SeenTxCache {
+    generations: Vec<Arc<Generation>>,
+    current: AtomicUsize,
+    txs_per_generation: usize,
+    /// Clearing of the spare generation, if it was started since the last rotation
+    clearing: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct Generation {
+    slots: Vec<AtomicU8>,
+    inserted: AtomicUsize,
+}
+
+impl Generation {
+    fn new(slot_count: usize) -> Self {
+        Self {
+            slots: (0..slot_count).map(|_| AtomicU8::new(0)).collect(),
+            inserted: AtomicUsize::new(0),
         }
-        println!("Tx cache initialized");
     }
-}
 
-pub fn mark_as_fetched(hash: &H256) -> TxCacheStatus {
-    unsafe {
-        let index = convert_hash_to_index(hash);
-        if CACHE[index] >= ALREADY_FETCHED_MARKER {
-            TxCacheStatus::Fetched
-        } else {
-            CACHE[index] = ALREADY_FETCHED_MARKER;
-            TxCacheStatus::NotFetched
+    #[inline(always)]
+    fn get(&self, indexes: &[usize; SLOTS_PER_HASH]) -> u8 {
+        indexes
+            .iter()
+            .map(|i| self.slots[*i].load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(0)
+    }
+
+    #[inline(always)]
+    fn raise_to(&self, indexes: &[usize; SLOTS_PER_HASH], value: u8) {
+        for i in indexes {
+            self.slots[*i].fetch_max(value, Ordering::Relaxed);
         }
     }
+
+    fn clear(&self) {
+        self.slots
+            .iter()
+            .for_each(|s| s.store(0, Ordering::Relaxed));
+        self.inserted.store(0, Ordering::Relaxed);
+    }
+
+    fn false_positive_rate(&self) -> f64 {
+        let slots = self.slots.len() as f64;
+        let inserted = self.inserted.load(Ordering::Relaxed) as f64;
+        let k = SLOTS_PER_HASH as f64;
+
+        (1.0 - (-k * inserted / slots).exp()).powf(k)
+    }
 }
 
-pub fn mark_as_requested(hash: &H256) -> TxCacheStatus {
-    unsafe {
-        let index = convert_hash_to_index(hash);
-        CACHE[index] += 1;
-        if CACHE[index] > MAX_REQUEST_COUNT {
+impl SeenTxCache {
+    pub fn new(txs_per_generation: usize) -> Self {
+        let txs_per_generation = txs_per_generation.max(1);
+        let slot_count = (txs_per_generation * SLOTS_PER_TX).next_power_of_two();
+
+        Self {
+            generations: (0..ALLOCATED_GENERATIONS)
+                .map(|_| Arc::new(Generation::new(slot_count)))
+                .collect(),
+            current: AtomicUsize::new(0),
+            txs_per_generation,
+            clearing: Mutex::new(None),
+        }
+    }
+
+    pub fn mark_as_fetched(&self, hash: &H256) -> TxCacheStatus {
+        let indexes = self.indexes(hash);
+        if self.get(&indexes) >= ALREADY_FETCHED_MARKER {
+            return TxCacheStatus::Fetched;
+        }
+
+        self.insert(&indexes, ALREADY_FETCHED_MARKER);
+        TxCacheStatus::NotFetched
+    }
+
+    pub fn mark_as_requested(&self, hash: &H256) -> TxCacheStatus {
+        let indexes = self.indexes(hash);
+        let request_count = self.get(&indexes).saturating_add(1);
+        self.insert(&indexes, request_count);
+
+        if request_count > MAX_REQUEST_COUNT {
             TxCacheStatus::Requested
         } else {
             TxCacheStatus::NotRequested
         }
     }
+
+    /// Drops the oldest generation and starts writing to the fresh one, called once the current
+    /// generation saw `txs_per_generation` distinct txs.
+    pub fn start_new_generation(&self) {
+        self.rotate_from(self.current.load(Ordering::Acquire));
+    }
+
+    fn rotate_from(&self, current: usize) {
+        // if someone else is rotating or already rotated, there is nothing to do
+        let Ok(mut clearing) = self.clearing.try_lock() else {
+            return;
+        };
+        if self.current.load(Ordering::Acquire) != current {
+            return;
+        }
+
+        // spare is normally cleared long before it's needed, this only waits if it's not
+        if let Some(handle) = clearing.take() {
+            let _ = handle.join();
+        }
+
+        let next = (current + 1) % ALLOCATED_GENERATIONS;
+        self.current.store(next, Ordering::Release);
+
+        let dropped = Arc::clone(&self.generations[Self::spare(next)]);
+        *clearing = Some(std::thread::spawn(move || dropped.clear()));
+    }
+
+    /// Generation after the current one is the spare, it's never looked up
+    #[inline(always)]
+    fn spare(current: usize) -> usize {
+        (current + 1) % ALLOCATED_GENERATIONS
+    }
+
+    #[inline(always)]
+    fn live_generations(&self) -> impl Iterator<Item = &Generation> {
+        let spare = Self::spare(self.current.load(Ordering::Acquire));
+        self.generations
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| *i != spare)
+            .map(|(_, g)| g.as_ref())
+    }
+
+    /// Estimated probability that the hash we have never seen is reported as seen
+    pub fn false_positive_rate(&self) -> f64 {
+        1.0 - self
+            .live_generations()
+            .map(|g| 1.0 - g.false_positive_rate())
+            .product::<f64>()
+    }
+
+    /// Memory used by the slots in bytes
+    pub fn size_in_bytes(&self) -> usize {
+        self.generations.iter().map(|g| g.slots.len()).sum()
+    }
+
+    #[inline(always)]
+    fn get(&self, indexes: &[usize; SLOTS_PER_HASH]) -> u8 {
+        self.live_generations()
+            .map(|g| g.get(indexes))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[inline(always)]
+    fn insert(&self, indexes: &[usize; SLOTS_PER_HASH], value: u8) {
+        let index = self.current.load(Ordering::Acquire);
+        let current = &self.generations[index];
+        // repeated marks of the same hash (requested, then fetched) count once
+        let is_new = current.get(indexes) == 0;
+        current.raise_to(indexes, value);
+
+        if is_new && current.inserted.fetch_add(1, Ordering::Relaxed) + 1 >= self.txs_per_generation
+        {
+            self.rotate_from(index);
+        }
+    }
+
+    #[inline(always)]
+    fn indexes(&self, hash: &H256) -> [usize; SLOTS_PER_HASH] {
+        // all generations have the same size, which is power of 2
+        let mask = self.generations[0].slots.len() - 1;
+        let mut indexes = [0usize; SLOTS_PER_HASH];
+        for (i, chunk) in hash.as_bytes().chunks_exact(8).enumerate() {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(chunk);
+            indexes[i] = u64::from_ne_bytes(bytes) as usize & mask;
+        }
+        indexes
+    }
+}
+
+pub fn init_cache(txs_per_generation: usize) {
+    let cache = CACHE.get_or_init(|| SeenTxCache::new(txs_per_generation));
+    println!(
+        "Tx cache initialized, size: {} MiB, false positive rate when full: {:.4}%",
+        cache.size_in_bytes() / (1024 * 1024),
+        expected_false_positive_rate_when_full(cache) * 100.0
+    );
 }
 
 #[inline(always)]
-fn convert_hash_to_index(hash: &H256) -> usize {
-    unsafe {
-        // This is safe because we're absolutely sure that `hash` has at least 4 bytes.
-        let bytes = *(&hash[..4] as *const [u8] as *const [u8; 4]);
-        u32::from_ne_bytes(bytes) as usize
+fn cache() -> &'static SeenTxCache {
+    CACHE.get_or_init(|| SeenTxCache::new(DEFAULT_TXS_PER_GENERATION))
+}
+
+pub fn mark_as_fetched(hash: &H256) -> TxCacheStatus {
+    cache().mark_as_fetched(hash)
+}
+
+pub fn mark_as_requested(hash: &H256) -> TxCacheStatus {
+    cache().mark_as_requested(hash)
+}
+
+pub fn false_positive_rate() -> f64 {
+    cache().false_positive_rate()
+}
+
+fn expected_false_positive_rate_when_full(cache: &SeenTxCache) -> f64 {
+    let k = SLOTS_PER_HASH as f64;
+    let slots = cache.generations[0].slots.len() as f64;
+    let per_generation = (1.0 - (-k * cache.txs_per_generation as f64 / slots).exp()).powf(k);
+
+    1.0 - (1.0 - per_generation).powi(GENERATIONS as i32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn requested_and_fetched_status() {
+        let cache = SeenTxCache::new(1_000);
+        let hash = H256::random();
+
+        assert_eq!(cache.mark_as_requested(&hash), TxCacheStatus::NotRequested);
+        assert_eq!(cache.mark_as_requested(&hash), TxCacheStatus::NotRequested);
+        assert_eq!(cache.mark_as_requested(&hash), TxCacheStatus::Requested);
+
+        assert_eq!(cache.mark_as_fetched(&hash), TxCacheStatus::NotFetched);
+        assert_eq!(cache.mark_as_fetched(&hash), TxCacheStatus::Fetched);
+        assert_eq!(cache.mark_as_requested(&hash), TxCacheStatus::Requested);
+    }
+
+    #[test]
+    fn hashes_with_same_prefix_do_not_collide() {
+        let cache = SeenTxCache::new(1_000);
+        let first = H256::random();
+        let mut second = H256::random();
+        second.0[..4].copy_from_slice(&first.0[..4]);
+
+        assert_eq!(cache.mark_as_fetched(&first), TxCacheStatus::NotFetched);
+        assert_eq!(cache.mark_as_fetched(&second), TxCacheStatus::NotFetched);
+    }
+
+    #[test]
+    fn old_generations_are_forgotten() {
+        let cache = SeenTxCache::new(1_000);
+        let hash = H256::random();
+        assert_eq!(cache.mark_as_fetched(&hash), TxCacheStatus::NotFetched);
+
+        for _ in 0..GENERATIONS - 1 {
+            cache.start_new_generation();
+            assert_eq!(cache.mark_as_fetched(&hash), TxCacheStatus::Fetched);
+        }
+
+        cache.start_new_generation();
+        assert_eq!(cache.mark_as_fetched(&hash), TxCacheStatus::NotFetched);
+    }
+
+    #[test]
+    fn repeated_marks_count_once() {
+        let cache = SeenTxCache::new(2);
+        let hash = H256::random();
+        cache.mark_as_requested(&hash);
+        cache.mark_as_requested(&hash);
+        cache.mark_as_fetched(&hash);
+        assert_eq!(cache.current.load(Ordering::Relaxed), 0);
+
+        cache.mark_as_fetched(&H256::random());
+        assert_eq!(cache.current.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn rotation_keeps_live_generations() {
+        let cache = SeenTxCache::new(1_000);
+        let old = H256::random();
+        assert_eq!(cache.mark_as_fetched(&old), TxCacheStatus::NotFetched);
+
+        cache.start_new_generation();
+        let new = H256::random();
+        assert_eq!(cache.mark_as_fetched(&new), TxCacheStatus::NotFetched);
+
+        // wait for the dropped generation to be cleared
+        let handle = cache.clearing.lock().unwrap().take().unwrap();
+        handle.join().unwrap();
+        assert_eq!(cache.mark_as_fetched(&old), TxCacheStatus::Fetched);
+        assert_eq!(cache.mark_as_fetched(&new), TxCacheStatus::Fetched);
+
+        // spare is cleared, the current generation kept its mark
+        let current = cache.current.load(Ordering::Relaxed);
+        let spare = SeenTxCache::spare(current);
+        assert!(cache.generations[spare]
+            .slots
+            .iter()
+            .all(|s| s.load(Ordering::Relaxed) == 0));
+        assert_eq!(
+            cache.generations[current].inserted.load(Ordering::Relaxed),
+            1
+        );
+    }
+
+    #[test]
+    fn false_positive_rate_is_within_estimate() {
+        let txs_per_generation = 10_000;
+        let cache = SeenTxCache::new(txs_per_generation);
+        for _ in 0..txs_per_generation - 1 {
+            cache.mark_as_fetched(&H256::random());
+        }
+
+        let estimate = cache.false_positive_rate();
+        let samples = 100_000;
+        let false_positives = (0..samples)
+            .filter(|_| cache.get(&cache.indexes(&H256::random())) >= ALREADY_FETCHED_MARKER)
+            .count();
+        let measured = false_positives as f64 / samples as f64;
+
+        assert!(estimate < 0.01);
+        assert!(measured < 2.0 * estimate + 0.001);
     }
 }
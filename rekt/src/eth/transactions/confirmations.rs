@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use color_print::cprintln;
+use dashmap::DashMap;
+use derive_more::Display;
+use ethers::types::U256;
+use ethers::utils::keccak256;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::blockchain::head_tracker;
+use crate::eth::eth_message::EthMessage;
+use crate::eth::messages::new_block::{decode_block_txs, NewBlock};
+use crate::token::token::TokenAddress;
+use crate::types::hash::H256;
+use crate::utils::helpers::get_bsc_tx_url;
+
+use super::transaction::Transaction;
+
+/// Tx which is not in any of this many blocks after it was sent is reported as not included
+const MAX_BLOCKS_FOR_INCLUSION: u64 = 20;
+/// Included and not included txs are kept for reports for this many blocks, then evicted
+const FINISHED_TXS_KEPT_FOR_BLOCKS: u64 = 1_200;
+
+static OUR_TXS: Lazy<DashMap<H256, OurTx>> = Lazy::new(DashMap::new);
+
+/// Where liquidity txs of our tokens were included: block number and index in the block
+static LIQ_INCLUSIONS: Lazy<DashMap<H256, (u64, usize)>> = Lazy::new(DashMap::new);
+
+/// Block hash to number of scanned blocks, the same block is announced by many peers
+static SCANNED_BLOCKS: Lazy<DashMap<H256, u64>> = Lazy::new(DashMap::new);
+
+/*
+* Every tx we broadcast (buy txs, sell txs, mev bundle txs) is tracked by its hash until it shows
+* up in the body of a `NewBlockMsg`, or until `MAX_BLOCKS_FOR_INCLUSION` blocks pass without it.
+* For included txs we report block number, index in the block, position relative to the
+* liquidity tx which triggered the buy and the gas price that was paid.
+*
+* Only blocks the head tracker agreed on (announced by several peers) are scanned, and only if
+* their txs match the txs root of the header, so a single peer can't make our txs look included
+* or missing. Blocks are scanned only while something is pending, so this costs nothing between
+* snipes. Finished txs are evicted once they are too old to be interesting.
+* */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+pub enum OurTxKind {
+    Buy,
+    Sell,
+    MevBid,
+    MevBuy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum LiqPosition {
+    /// In the same block, this many txs after the liquidity tx
+    After(usize),
+    /// In the same block, this many txs before the liquidity tx (buy reverted or bought nothing)
+    Before(usize),
+    /// This many blocks after the block with the liquidity tx
+    LaterBlock(u64),
+    /// Liquidity tx was not seen in any scanned block
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum TxStatus {
+    Pending {
+        sent_at_block: u64,
+    },
+    Included {
+        block: u64,
+        index: usize,
+        liq_position: LiqPosition,
+        /// Gas price for legacy txs, priority fee for dynamic fee txs (BSC base fee is 0)
+        gas_price: Option<U256>,
+    },
+    NotIncluded {
+        last_block: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OurTx {
+    pub hash: H256,
+    pub kind: OurTxKind,
+    pub token: TokenAddress,
+    pub liq_tx: Option<H256>,
+    pub status: TxStatus,
+}
+
+impl OurTx {
+    fn is_pending(&self) -> bool {
+        matches!(self.status, TxStatus::Pending { .. })
+    }
+}
+
+/// Tracks raw txs (as they are hashed: rlp list for legacy, `type || rlp` for typed txs)
+pub fn track_raw_txs(kind: OurTxKind, token: TokenAddress, liq_tx: Option<H256>, txs: &[Bytes]) {
+    let sent_at_block = head_tracker::current_head().number;
+    for tx in txs {
+        let hash = H256(keccak256(tx));
+        OUR_TXS.insert(
+            hash,
+            OurTx {
+                hash,
+                kind,
+                token,
+                liq_tx,
+                status: TxStatus::Pending { sent_at_block },
+            },
+        );
+    }
+}
+
+/// Tracks txs of `TransactionsMsg` we sent, message can be pre-compressed (buy txs) or not
+pub fn track_tx_message(
+    kind: OurTxKind,
+    token: TokenAddress,
+    liq_tx: Option<H256>,
+    msg: &EthMessage,
+) {
+    let rlp = match msg.decompressed_rlp() {
+        Ok(rlp) => rlp,
+        Err(e) => {
+            println!("Could not decompress our txs: {}", e);
+            return;
+        }
+    };
+
+    match decode_block_txs(&mut &rlp[..]) {
+        Ok(txs) => track_raw_txs(kind, token, liq_tx, &txs),
+        Err(e) => println!("Could not decode our txs: {}", e),
+    }
+}
+
+/// Tracks hex encoded raw tx, as it is sent in mev bundles
+pub fn track_hex_tx(kind: OurTxKind, token: TokenAddress, liq_tx: Option<H256>, tx: &str) {
+    match hex::decode(tx.trim_start_matches("0x")) {
+        Ok(tx) => track_raw_txs(kind, token, liq_tx, &[Bytes::from(tx)]),
+        Err(e) => println!("Could not decode our {} tx: {}", kind, e),
+    }
+}
+
+fn liq_position(block: u64, index: usize, liq_tx: Option<H256>) -> LiqPosition {
+    let Some((liq_block, liq_index)) = liq_tx.and_then(|liq| LIQ_INCLUSIONS.get(&liq).map(|l| *l))
+    else {
+        return LiqPosition::Unknown;
+    };
+
+    if liq_block == block {
+        if index > liq_index {
+            LiqPosition::After(index - liq_index)
+        } else {
+            LiqPosition::Before(liq_index - index)
+        }
+    } else if liq_block < block {
+        LiqPosition::LaterBlock(block - liq_block)
+    } else {
+        LiqPosition::Unknown
+    }
+}
+
+fn paid_gas_price(raw: &[u8]) -> Option<U256> {
+    let tx = Transaction::decode_raw(raw).ok()?;
+    tx.gas_price.or(tx.max_priority_fee_per_gas)
+}
+
+/// Scans txs of a new block for our pending txs
+pub fn on_block(block: &NewBlock) {
+    let (number, hash) = (block.header.number, block.header.hash);
+    if !OUR_TXS.iter().any(|tx| tx.is_pending())
+        || SCANNED_BLOCKS.contains_key(&hash)
+        || !head_tracker::is_agreed_block(number, hash)
+        || !block.has_valid_txs_root()
+    {
+        return;
+    }
+
+    scan_block(number, hash, &block.txs);
+}
+
+fn scan_block(number: u64, block_hash: H256, txs: &[Bytes]) {
+    if SCANNED_BLOCKS.insert(block_hash, number).is_some() {
+        return;
+    }
+    let pending = OUR_TXS
+        .iter()
+        .filter(|tx| tx.is_pending())
+        .map(|tx| (tx.hash, tx.liq_tx))
+        .collect::<HashMap<_, _>>();
+
+    let hashes = txs.iter().map(|tx| H256(keccak256(tx))).collect::<Vec<_>>();
+
+    for (index, hash) in hashes.iter().enumerate() {
+        if pending.values().any(|liq| liq.as_ref() == Some(hash)) {
+            LIQ_INCLUSIONS.insert(*hash, (number, index));
+        }
+    }
+
+    for (index, hash) in hashes.iter().enumerate() {
+        let Some(liq_tx) = pending.get(hash) else {
+            continue;
+        };
+        let status = TxStatus::Included {
+            block: number,
+            index,
+            liq_position: liq_position(number, index, *liq_tx),
+            gas_price: paid_gas_price(&txs[index]),
+        };
+        if let Some(mut tx) = OUR_TXS.get_mut(hash) {
+            tx.status = status;
+            cprintln!(
+                "<green>Our {} tx included: {}\n{:?}</>",
+                tx.kind,
+                get_bsc_tx_url(tx.hash),
+                tx.status
+            );
+        }
+    }
+
+    for mut tx in OUR_TXS.iter_mut() {
+        if let TxStatus::Pending { sent_at_block } = tx.status {
+            if number > sent_at_block + MAX_BLOCKS_FOR_INCLUSION {
+                tx.status = TxStatus::NotIncluded { last_block: number };
+                cprintln!(
+                    "<red>Our {} tx not included: {}</>",
+                    tx.kind,
+                    get_bsc_tx_url(tx.hash)
+                );
+            }
+        }
+    }
+
+    evict_old(number);
+}
+
+/// Evicts finished txs, and liquidity inclusions and scanned blocks of the same age
+fn evict_old(number: u64) {
+    let oldest = number.saturating_sub(FINISHED_TXS_KEPT_FOR_BLOCKS);
+    OUR_TXS.retain(|_, tx| match tx.status {
+        TxStatus::Pending { .. } => true,
+        TxStatus::Included { block, .. } => block >= oldest,
+        TxStatus::NotIncluded { last_block } => last_block >= oldest,
+    });
+    LIQ_INCLUSIONS.retain(|_, (block, _)| *block >= oldest);
+    SCANNED_BLOCKS.retain(|_, block| *block >= oldest);
+}
+
+/// All tracked txs, most recent first
+pub fn all_our_txs() -> Vec<OurTx> {
+    let mut txs = OUR_TXS
+        .iter()
+        .map(|tx| tx.value().clone())
+        .collect::<Vec<_>>();
+    txs.sort_by_key(|tx| {
+        std::cmp::Reverse(match tx.status {
+            TxStatus::Pending { sent_at_block } => sent_at_block,
+            TxStatus::Included { block, .. } => block,
+            TxStatus::NotIncluded { last_block } => last_block,
+        })
+    });
+    txs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eth::messages::new_block::BlockHeader;
+    use crate::eth::messages::trie::ordered_trie_root;
+
+    #[test]
+    fn reports_included_and_missing_txs() {
+        let token = TokenAddress::repeat_byte(0xc1);
+        let liq = Bytes::from_static(&[0xc1, 0x01]);
+        let liq_hash = H256(keccak256(&liq));
+        let (buy, sell, lost) = (
+            Bytes::from_static(&[0xc1, 0x02]),
+            Bytes::from_static(&[0xc1, 0x03]),
+            Bytes::from_static(&[0xc1, 0x04]),
+        );
+        let other = Bytes::from_static(&[0xc1, 0x05]);
+
+        track_raw_txs(
+            OurTxKind::Buy,
+            token,
+            Some(liq_hash),
+            &[buy.clone(), lost.clone()],
+        );
+        track_raw_txs(
+            OurTxKind::Sell,
+            token,
+            Some(liq_hash),
+            std::slice::from_ref(&sell),
+        );
+        let status = |tx: &Bytes| OUR_TXS.get(&H256(keccak256(tx))).unwrap().status.clone();
+        let TxStatus::Pending {
+            sent_at_block: sent_at,
+        } = status(&sell)
+        else {
+            panic!("sell tx should be pending");
+        };
+
+        let block_hash = |number: u64| H256::from_low_u64_be(number);
+        scan_block(
+            sent_at + 1,
+            block_hash(sent_at + 1),
+            &[liq, other.clone(), buy.clone()],
+        );
+        scan_block(sent_at + 3, block_hash(sent_at + 3), &[other, sell.clone()]);
+        // announced again by another peer
+        scan_block(sent_at + 3, block_hash(sent_at + 3), &[]);
+
+        assert_eq!(
+            status(&buy),
+            TxStatus::Included {
+                block: sent_at + 1,
+                index: 2,
+                liq_position: LiqPosition::After(2),
+                gas_price: None,
+            }
+        );
+        assert!(matches!(
+            status(&sell),
+            TxStatus::Included {
+                index: 1,
+                liq_position: LiqPosition::LaterBlock(2),
+                ..
+            }
+        ));
+        assert_eq!(
+            status(&lost),
+            TxStatus::Pending {
+                sent_at_block: sent_at
+            }
+        );
+
+        let last_block = sent_at + MAX_BLOCKS_FOR_INCLUSION + 1;
+        scan_block(last_block, block_hash(last_block), &[]);
+        assert_eq!(status(&lost), TxStatus::NotIncluded { last_block });
+
+        // finished txs are evicted after a while
+        let much_later = last_block + FINISHED_TXS_KEPT_FOR_BLOCKS;
+        scan_block(much_later, block_hash(much_later), &[]);
+        assert!(OUR_TXS.contains_key(&H256(keccak256(&lost))));
+        scan_block(much_later + 1, block_hash(much_later + 1), &[]);
+        for tx in [&buy, &sell, &lost] {
+            assert!(!OUR_TXS.contains_key(&H256(keccak256(tx))));
+        }
+        assert!(!LIQ_INCLUSIONS.contains_key(&liq_hash));
+    }
+
+    #[test]
+    fn only_agreed_blocks_with_valid_txs_root_are_scanned() {
+        let ours = Bytes::from_static(&[0xc2, 0x01]);
+        track_raw_txs(
+            OurTxKind::Buy,
+            TokenAddress::repeat_byte(0xc2),
+            None,
+            std::slice::from_ref(&ours),
+        );
+        let header = BlockHeader {
+            hash: H256::repeat_byte(0xc2),
+            txs_root: ordered_trie_root(std::slice::from_ref(&ours)),
+            number: head_tracker::current_head().number + 1,
+            difficulty: 2,
+            gas_limit: 0,
+            timestamp: 0,
+        };
+
+        // txs root matches, but no peers announced the block
+        on_block(&NewBlock {
+            header,
+            txs: vec![ours.clone()],
+            td: 0,
+        });
+        let status = OUR_TXS.get(&H256(keccak256(&ours))).unwrap().status.clone();
+        assert!(!matches!(status, TxStatus::Included { .. }));
+        assert!(!SCANNED_BLOCKS.contains_key(&header.hash));
+    }
+}
@@ -1,37 +1,21 @@
-use std::str::FromStr;
-
 use bytes::{Buf, Bytes, BytesMut};
 use chrono::{DateTime, Utc};
-use ethers::types::Address;
 use open_fastrlp::{Decodable, DecodeError, Header, HeaderInfo};
 use sha3::{Digest, Keccak256};
-use static_init::dynamic;
 
 use super::{cache, errors::DecodeTxError, types::TxType};
 use crate::{
-    constants::{
-        TOKEN_IN_TX_ENDS_AT, TOKEN_IN_TX_ENDS_AT_POSSIBLE_POSITION_2, TOKEN_IN_TX_STARTS_AT,
-        TOKEN_IN_TX_STARTS_AT_POSSIBLE_POSITION_2,
-    },
-    p2p::peer::BUY_IS_IN_PROGRESS,
+    p2p::tx_latency,
     token::{
         token::Token,
         tokens_to_buy::{
-            get_token_to_buy, tx_is_enable_buy, tx_nonce_is_ok, PCS_LIQ, TOKENS_TO_BUY,
+            get_triggered_token, is_token_to_buy, recipient_has_triggers, tx_is_enable_buy,
+            tx_nonce_is_ok,
         },
     },
-    types::hash::H256,
+    types::hash::{H256, H512},
 };
 
-#[dynamic]
-pub static PCS_V2_ROUTER: Address = Address::from_str("0x10ED43C718714eb63d5aA57B78B54704E256024E")
-    .expect("Invalid PCS V2 router address");
-
-#[dynamic]
-pub static PCS_V3_LIQ_CONTRACT: Address =
-    Address::from_str("0x46A15B0b27311cedF172AB29E4f4766fbE7F4364")
-        .expect("Invalid PCS V2 router address");
-
 pub enum TxDecodingResult {
     NoBuy(usize),
     Buy(BuyTokenInfo),
@@ -67,17 +51,24 @@ impl BuyTokenInfo {
     }
 }
 
-pub fn decode_txs_request(buf: &mut &[u8]) -> Result<Option<BuyTokenInfo>, DecodeTxError> {
+pub fn decode_txs_request(
+    buf: &mut &[u8],
+    peer: &H512,
+) -> Result<Option<BuyTokenInfo>, DecodeTxError> {
     let h = Header::decode(buf)?;
     if !h.list {
         return Err(DecodeTxError::from(DecodeError::UnexpectedString));
     }
 
     let _skip_decoding_request_id = HeaderInfo::skip_next_item(buf)?;
-    decode_txs(buf, false)
+    decode_txs(buf, false, peer)
 }
 
-pub fn decode_txs(buf: &mut &[u8], direct: bool) -> Result<Option<BuyTokenInfo>, DecodeTxError> {
+pub fn decode_txs(
+    buf: &mut &[u8],
+    direct: bool,
+    peer: &H512,
+) -> Result<Option<BuyTokenInfo>, DecodeTxError> {
     let metadata = Header::decode(buf)?;
     if !metadata.list {
         return Err(DecodeTxError::from(DecodeError::UnexpectedString));
@@ -89,7 +80,7 @@ pub fn decode_txs(buf: &mut &[u8], direct: bool) -> Result<Option<BuyTokenInfo>,
     let payload_view = &mut &buf[..metadata.payload_length];
 
     while !payload_view.is_empty() {
-        match decode_tx(payload_view)? {
+        match decode_tx(payload_view, peer)? {
             TxDecodingResult::Buy(mut buy_info) => {
                 buy_info.set_tx_direct(direct);
                 return Ok(Some(buy_info));
@@ -104,7 +95,7 @@ pub fn decode_txs(buf: &mut &[u8], direct: bool) -> Result<Option<BuyTokenInfo>,
     Ok(None)
 }
 
-fn decode_tx(buf: &mut &[u8]) -> Result<TxDecodingResult, DecodeTxError> {
+fn decode_tx(buf: &mut &[u8], peer: &H512) -> Result<TxDecodingResult, DecodeTxError> {
     let tx_metadata = HeaderInfo::decode(buf)?;
 
     //NOTE:
@@ -116,19 +107,23 @@ fn decode_tx(buf: &mut &[u8]) -> Result<TxDecodingResult, DecodeTxError> {
 
     let rlp_decoding_is_of_legacy_tx = tx_metadata.list;
     if rlp_decoding_is_of_legacy_tx {
-        return decode_legacy(buf, tx_metadata);
+        return decode_legacy(buf, tx_metadata, peer);
     }
 
-    let _typed_tx_metadata = Header::decode_from_info(buf, tx_metadata)?;
+    let typed_tx_metadata = Header::decode_from_info(buf, tx_metadata)?;
+    // EIP-2718 envelope (tx type + RLP list), needed only if we have to recover the sender
+    let envelope: &[u8] = buf;
+    let raw_tx = &envelope[..typed_tx_metadata.payload_length];
+
     let tx_type_flag = TxType::try_from(buf[0])?;
     match tx_type_flag {
         TxType::DynamicFee | TxType::Blob => {
             buf.advance(1);
-            decode_dynamic_and_blob_tx_types(tx_type_flag, buf)
+            decode_dynamic_and_blob_tx_types(tx_type_flag, buf, raw_tx, peer)
         }
         TxType::AccessList => {
             buf.advance(1);
-            decode_access_list_tx_type(tx_type_flag, buf)
+            decode_access_list_tx_type(tx_type_flag, buf, raw_tx, peer)
         }
         TxType::Legacy => unreachable!(),
     }
@@ -137,8 +132,12 @@ fn decode_tx(buf: &mut &[u8]) -> Result<TxDecodingResult, DecodeTxError> {
 fn decode_legacy(
     buf: &mut &[u8],
     tx_metadata: HeaderInfo,
+    peer: &H512,
 ) -> Result<TxDecodingResult, DecodeTxError> {
-    let hash = eth_tx_hash(TxType::Legacy, &buf[..tx_metadata.total_len]);
+    let whole_buf: &[u8] = buf;
+    let raw_tx = &whole_buf[..tx_metadata.total_len];
+    let hash = eth_tx_hash(TxType::Legacy, raw_tx);
+    tx_latency::on_tx_seen(&hash, peer);
     if cache::mark_as_fetched(&hash) == cache::TxCacheStatus::Fetched {
         return Ok(TxDecodingResult::NoBuy(tx_metadata.total_len));
     }
@@ -163,18 +162,33 @@ fn decode_legacy(
         }
     };
 
-    if unsafe { PCS_LIQ } && recipient_is_to_pcs(&recipient) {
-        return handle_pcs(tx_metadata, payload_view, hash, recipient, gas_price);
+    if recipient_has_triggers(&recipient) {
+        if let Some(buy_info) =
+            handle_triggers(payload_view, raw_tx, hash, nonce, gas_price, &recipient)?
+        {
+            return Ok(TxDecodingResult::Buy(buy_info));
+        }
     }
-    handle_token(tx_metadata, payload_view, hash, nonce, gas_price, recipient)
+    handle_token(
+        tx_metadata,
+        payload_view,
+        raw_tx,
+        hash,
+        nonce,
+        gas_price,
+        recipient,
+    )
 }
 
 fn decode_dynamic_and_blob_tx_types(
     tx_type: TxType,
     buf: &mut &[u8],
+    raw_tx: &[u8],
+    peer: &H512,
 ) -> Result<TxDecodingResult, DecodeTxError> {
     let tx_metadata = HeaderInfo::decode(buf)?;
     let hash = eth_tx_hash(tx_type, &buf[..tx_metadata.total_len]);
+    tx_latency::on_tx_seen(&hash, peer);
     if cache::mark_as_fetched(&hash) == cache::TxCacheStatus::Fetched {
         return Ok(TxDecodingResult::NoBuy(tx_metadata.total_len));
     }
@@ -209,18 +223,33 @@ fn decode_dynamic_and_blob_tx_types(
         }
     };
 
-    if unsafe { PCS_LIQ } && recipient_is_to_pcs(&recipient) {
-        return handle_pcs(tx_metadata, payload_view, hash, recipient, gas_price);
+    if recipient_has_triggers(&recipient) {
+        if let Some(buy_info) =
+            handle_triggers(payload_view, raw_tx, hash, nonce, gas_price, &recipient)?
+        {
+            return Ok(TxDecodingResult::Buy(buy_info));
+        }
     }
-    handle_token(tx_metadata, payload_view, hash, nonce, gas_price, recipient)
+    handle_token(
+        tx_metadata,
+        payload_view,
+        raw_tx,
+        hash,
+        nonce,
+        gas_price,
+        recipient,
+    )
 }
 
 fn decode_access_list_tx_type(
     tx_type: TxType,
     buf: &mut &[u8],
+    raw_tx: &[u8],
+    peer: &H512,
 ) -> Result<TxDecodingResult, DecodeTxError> {
     let tx_metadata = HeaderInfo::decode(buf)?;
     let hash = eth_tx_hash(tx_type, &buf[..tx_metadata.total_len]);
+    tx_latency::on_tx_seen(&hash, peer);
 
     if cache::mark_as_fetched(&hash) == cache::TxCacheStatus::Fetched {
         return Ok(TxDecodingResult::NoBuy(tx_metadata.total_len));
@@ -255,10 +284,22 @@ fn decode_access_list_tx_type(
         }
     };
 
-    if unsafe { PCS_LIQ } && recipient_is_to_pcs(&recipient) {
-        return handle_pcs(tx_metadata, payload_view, hash, recipient, gas_price);
+    if recipient_has_triggers(&recipient) {
+        if let Some(buy_info) =
+            handle_triggers(payload_view, raw_tx, hash, nonce, gas_price, &recipient)?
+        {
+            return Ok(TxDecodingResult::Buy(buy_info));
+        }
     }
-    handle_token(tx_metadata, payload_view, hash, nonce, gas_price, recipient)
+    handle_token(
+        tx_metadata,
+        payload_view,
+        raw_tx,
+        hash,
+        nonce,
+        gas_price,
+        recipient,
+    )
 }
 
 fn eth_tx_hash(tx_type: TxType, raw_tx: &[u8]) -> H256 {
@@ -273,28 +314,24 @@ fn eth_tx_hash(tx_type: TxType, raw_tx: &[u8]) -> H256 {
 fn handle_token(
     tx_metadata: Header,
     payload_view: &mut &[u8],
+    raw_tx: &[u8],
     hash: H256,
     nonce: u64,
     gas_price: u64,
     recipient: ethers::types::H160,
 ) -> Result<TxDecodingResult, DecodeTxError> {
-    let (token, index) = match get_token_to_buy(&recipient, nonce) {
-        Some((t, i)) => (t, i),
-        None => return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length)),
-    };
+    if !is_token_to_buy(&recipient, nonce) {
+        return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length));
+    }
 
     let _skip_decoding_value = HeaderInfo::skip_next_item(payload_view);
     let data = Bytes::decode(payload_view)?;
 
-    let token = match tx_is_enable_buy(token, index, &data) {
+    let token = match tx_is_enable_buy(&recipient, nonce, &data, raw_tx) {
         Some(token) => token,
         None => return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length)),
     };
 
-    unsafe {
-        BUY_IS_IN_PROGRESS = true;
-    }
-
     Ok(TxDecodingResult::Buy(BuyTokenInfo::new(
         token, gas_price, hash,
     )))
@@ -304,177 +341,21 @@ fn handle_token(
     // )))
 }
 
-fn handle_pcs(
-    tx_metadata: Header,
-    payload_view: &mut &[u8],
-    hash: H256,
-    recipient: ethers::types::H160,
-    gas_price: u64,
-) -> Result<TxDecodingResult, DecodeTxError> {
-    if recipient == *PCS_V2_ROUTER {
-        return handle_pcs_v2(tx_metadata, payload_view, hash, gas_price);
-    }
-
-    return handle_pcs_v3(tx_metadata, payload_view, hash, gas_price);
-}
-
-fn handle_pcs_v2(
-    tx_metadata: Header,
-    payload_view: &mut &[u8],
-    hash: H256,
-    gas_price: u64,
-) -> Result<TxDecodingResult, DecodeTxError> {
-    let _skip_decoding_value = HeaderInfo::skip_next_item(payload_view);
-    let data = Bytes::decode(payload_view)?;
-
-    if data.starts_with(&[0xe8, 0xe3, 0x37, 0x00]) {
-        let first_position_token = &data[TOKEN_IN_TX_STARTS_AT..TOKEN_IN_TX_ENDS_AT];
-        if let Some((token, index)) =
-            get_token_to_buy(&Address::from_slice(first_position_token), 0)
-        {
-            if !token.liq_will_be_added_via_pcs {
-                return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length));
-            }
-            unsafe {
-                BUY_IS_IN_PROGRESS = true;
-            }
-
-            let token = unsafe { TOKENS_TO_BUY.swap_remove(index) };
-
-            return Ok(TxDecodingResult::Buy(BuyTokenInfo::new(
-                token, gas_price, hash,
-            )));
-            // return Ok(TxDecodingResult::Buy(BuyTokenInfo::new(
-            //     tx, token, gas_price, hash,
-            // )));
-        }
-
-        let second_position_token = &data
-            [TOKEN_IN_TX_STARTS_AT_POSSIBLE_POSITION_2..TOKEN_IN_TX_ENDS_AT_POSSIBLE_POSITION_2];
-
-        if let Some((token, index)) =
-            get_token_to_buy(&Address::from_slice(second_position_token), 0)
-        {
-            if !token.liq_will_be_added_via_pcs {
-                return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length));
-            }
-            unsafe {
-                BUY_IS_IN_PROGRESS = true;
-            }
-
-            let token = unsafe { TOKENS_TO_BUY.swap_remove(index) };
-
-            return Ok(TxDecodingResult::Buy(BuyTokenInfo::new(
-                token, gas_price, hash,
-            )));
-        }
-    }
-
-    if data.starts_with(&[0xf3, 0x05, 0xd7, 0x19]) {
-        let first_position_token = &data[TOKEN_IN_TX_STARTS_AT..TOKEN_IN_TX_ENDS_AT];
-        if let Some((token, index)) =
-            get_token_to_buy(&Address::from_slice(first_position_token), 0)
-        {
-            if !token.liq_will_be_added_via_pcs {
-                return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length));
-            }
-            unsafe {
-                BUY_IS_IN_PROGRESS = true;
-            }
-
-            let token = unsafe { TOKENS_TO_BUY.swap_remove(index) };
-
-            return Ok(TxDecodingResult::Buy(BuyTokenInfo::new(
-                token, gas_price, hash,
-            )));
-        }
-    }
-
-    Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length))
-}
-
-fn handle_pcs_v3(
-    tx_metadata: Header,
-    payload_view: &mut &[u8],
+fn handle_triggers(
+    mut payload_view: &[u8],
+    raw_tx: &[u8],
     hash: H256,
+    nonce: u64,
     gas_price: u64,
-) -> Result<TxDecodingResult, DecodeTxError> {
-    let _skip_decoding_value = HeaderInfo::skip_next_item(payload_view);
-    let data = Bytes::decode(payload_view)?;
-
-    if data.starts_with(&[0x88, 0x31, 0x64, 0x56]) {
-        let first_position_token = &data[TOKEN_IN_TX_STARTS_AT..TOKEN_IN_TX_ENDS_AT];
-        if let Some((token, index)) =
-            get_token_to_buy(&Address::from_slice(first_position_token), 0)
-        {
-            if !token.liq_will_be_added_via_pcs {
-                return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length));
-            }
-            unsafe {
-                BUY_IS_IN_PROGRESS = true;
-            }
-
-            let token = unsafe { TOKENS_TO_BUY.swap_remove(index) };
-
-            return Ok(TxDecodingResult::Buy(BuyTokenInfo::new(
-                token, gas_price, hash,
-            )));
-        }
-
-        let second_position_token = &data
-            [TOKEN_IN_TX_STARTS_AT_POSSIBLE_POSITION_2..TOKEN_IN_TX_ENDS_AT_POSSIBLE_POSITION_2];
-
-        if let Some((token, index)) =
-            get_token_to_buy(&Address::from_slice(second_position_token), 0)
-        {
-            if !token.liq_will_be_added_via_pcs {
-                return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length));
-            }
-            unsafe {
-                BUY_IS_IN_PROGRESS = true;
-            }
-
-            let token = unsafe { TOKENS_TO_BUY.swap_remove(index) };
-
-            return Ok(TxDecodingResult::Buy(BuyTokenInfo::new(
-                token, gas_price, hash,
-            )));
-        }
-    }
-
-    if data.starts_with(&[0xac, 0x96, 0x50, 0xd8]) {
-        if !contains(&data, &[0x13, 0xea, 0xd5, 0x62]) {
-            return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length));
-        }
+    recipient: &ethers::types::H160,
+) -> Result<Option<BuyTokenInfo>, DecodeTxError> {
+    let _skip_decoding_value = HeaderInfo::skip_next_item(&mut payload_view);
+    let data = Bytes::decode(&mut payload_view)?;
 
-        if !contains(&data, &[0x88, 0x31, 0x64, 0x56]) {
-            return Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length));
-        }
-
-        unsafe {
-            for (i, t) in TOKENS_TO_BUY.iter().enumerate() {
-                if !t.liq_will_be_added_via_pcs {
-                    continue;
-                }
-
-                if contains(&data, t.buy_token_address.as_bytes()) {
-                    BUY_IS_IN_PROGRESS = true;
-                    let token = TOKENS_TO_BUY.swap_remove(i);
-                    return Ok(TxDecodingResult::Buy(BuyTokenInfo::new(
-                        token, gas_price, hash,
-                    )));
-                }
-            }
-        }
-    }
-
-    Ok(TxDecodingResult::NoBuy(tx_metadata.payload_length))
-}
-
-fn recipient_is_to_pcs(recipient: &ethers::types::H160) -> bool {
-    *recipient == *PCS_V2_ROUTER || *recipient == *PCS_V3_LIQ_CONTRACT
-}
+    let token = match get_triggered_token(recipient, nonce, &data, raw_tx) {
+        Some(token) => token,
+        None => return Ok(None),
+    };
 
-fn contains(bytes: &Bytes, slice: &[u8]) -> bool {
-    bytes.windows(slice.len()).any(|window| window == slice)
+    Ok(Some(BuyTokenInfo::new(token, gas_price, hash)))
 }
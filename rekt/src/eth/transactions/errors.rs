@@ -8,4 +8,6 @@ pub enum DecodeTxError {
     ContractCreation,
     #[error("TX is of unknown type")]
     UnknownTxType,
+    #[error("TX signature is invalid")]
+    InvalidSignature,
 }
@@ -1,4 +1,7 @@
 pub mod cache;
+pub mod confirmations;
 pub mod decoder;
 pub mod errors;
+pub mod our_pool;
+pub mod transaction;
 pub mod types;
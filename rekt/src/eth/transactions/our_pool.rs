@@ -0,0 +1,395 @@
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use dashmap::DashMap;
+use ethers::utils::keccak256;
+use once_cell::sync::{Lazy, OnceCell};
+use open_fastrlp::{Decodable, DecodeError, Encodable, Header, RlpDecodable, RlpEncodable};
+
+use crate::eth::eth_message::EthMessage;
+use crate::eth::messages::new_block::decode_block_txs;
+use crate::eth::types::protocol::EthProtocol;
+use crate::p2p::protocol::ProtocolVersion;
+use crate::types::hash::H256;
+
+use super::cache;
+
+/// Peers ask for announced txs right away, after this our txs are either mined or useless
+const POOL_TTL: Duration = Duration::from_secs(120);
+
+static OUR_POOL: Lazy<DashMap<H256, PooledTx>> = Lazy::new(DashMap::new);
+
+/// Protocol versions of peers which get hash announcements instead of full txs
+static ANNOUNCE_TO: OnceCell<Vec<ProtocolVersion>> = OnceCell::new();
+
+/*
+* Our own signed txs, kept so they can be announced by hash instead of pushed as full
+* `TransactionsMsg` payloads.
+* Announcement is a tiny message (eth/68: types, sizes and hashes, eth/66-67: hashes only), peer
+* which doesn't have the tx asks for it with `GetPooledTransactionsMsg`, and it is served from here.
+* It costs peer a round trip, so it is enabled only for protocol versions given in
+* `--announce_txs_to`, every other peer still gets full txs.
+*
+* eth/65 requests have no request id, so eth/65 peers always get full txs.
+*
+* Buy txs are hashed and their announcement is built when they are prepared (`PreparedTxs`), on
+* the buy itself they are only put to the pool and sent.
+* */
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PooledTx {
+    /// Tx as it is hashed: rlp list for legacy txs, `type || rlp` for typed ones
+    raw: Bytes,
+    added_at: Instant,
+}
+
+/// Legacy txs are rlp lists, typed ones start with their type
+fn tx_type(raw: &[u8]) -> u8 {
+    match raw.first() {
+        Some(b) if *b < 0xc0 => *b,
+        _ => 0,
+    }
+}
+
+impl PooledTx {
+    /// In `PooledTransactionsMsg` typed txs are wrapped into rlp string
+    fn encode_for_network(&self, out: &mut BytesMut) {
+        if tx_type(&self.raw) != 0 {
+            Header {
+                list: false,
+                payload_length: self.raw.len(),
+            }
+            .encode(out);
+        }
+        out.extend_from_slice(&self.raw);
+    }
+
+    fn network_len(&self) -> usize {
+        let mut out = BytesMut::new();
+        self.encode_for_network(&mut out);
+        out.len()
+    }
+}
+
+/// `NewPooledTransactionHashesMsg` of eth/68
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+struct Eth68Announcement {
+    types: Bytes,
+    sizes: Vec<u32>,
+    hashes: Vec<H256>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, RlpDecodable)]
+struct PooledTransactionsRequest {
+    request_id: u64,
+    hashes: Vec<H256>,
+}
+
+/// Announcement of our txs, built once and sent to many peers
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxAnnouncement {
+    eth68: EthMessage,
+    eth66: EthMessage,
+}
+
+impl TxAnnouncement {
+    pub fn message_for(&self, protocol_version: ProtocolVersion) -> &EthMessage {
+        if protocol_version >= ProtocolVersion::Eth68 {
+            &self.eth68
+        } else {
+            &self.eth66
+        }
+    }
+}
+
+pub fn init_tx_announcements(versions: &[usize]) {
+    let versions = versions
+        .iter()
+        .map(|v| ProtocolVersion::from(*v))
+        .filter(|v| *v >= ProtocolVersion::Eth66)
+        .collect::<Vec<_>>();
+    if !versions.is_empty() {
+        println!("Announcing our txs by hash to: {:?}", versions);
+    }
+    let _ = ANNOUNCE_TO.set(versions);
+}
+
+pub fn announcements_enabled() -> bool {
+    ANNOUNCE_TO
+        .get()
+        .is_some_and(|versions| !versions.is_empty())
+}
+
+pub fn announces_to(protocol_version: ProtocolVersion) -> bool {
+    ANNOUNCE_TO
+        .get()
+        .is_some_and(|versions| versions.contains(&protocol_version))
+}
+
+fn forget_old_txs(now: Instant) {
+    OUR_POOL.retain(|_, tx| now.saturating_duration_since(tx.added_at) < POOL_TTL);
+}
+
+/// Our `TransactionsMsg` with the announcement of its txs, if announcements are enabled
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedTxs {
+    pub message: EthMessage,
+    announced: Option<AnnouncedTxs>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AnnouncedTxs {
+    txs: Vec<(H256, Bytes)>,
+    announcement: TxAnnouncement,
+}
+
+impl PreparedTxs {
+    pub fn new(message: EthMessage) -> Self {
+        if !announcements_enabled() {
+            return Self {
+                message,
+                announced: None,
+            };
+        }
+
+        let announced = match decode_tx_message(&message) {
+            Ok(txs) => Some(announce_raw_txs(txs)),
+            Err(e) => {
+                println!("Could not pool our txs, they will be sent in full: {}", e);
+                None
+            }
+        };
+        Self { message, announced }
+    }
+
+    /// Adds txs to the pool, so they can be served once announced
+    pub fn add_to_pool(&self) -> Option<&TxAnnouncement> {
+        let announced = self.announced.as_ref()?;
+        add_to_pool(&announced.txs);
+        Some(&announced.announcement)
+    }
+}
+
+fn announce_raw_txs(txs: Vec<Bytes>) -> AnnouncedTxs {
+    let mut announcement = Eth68Announcement {
+        types: Bytes::new(),
+        sizes: Vec::with_capacity(txs.len()),
+        hashes: Vec::with_capacity(txs.len()),
+    };
+    let mut types = Vec::with_capacity(txs.len());
+    let txs = txs
+        .into_iter()
+        .map(|raw| {
+            let hash = H256(keccak256(&raw));
+            types.push(tx_type(&raw));
+            // size as in eth/68 spec: `type || rlp` for typed txs, without the string header
+            announcement.sizes.push(raw.len() as u32);
+            announcement.hashes.push(hash);
+            (hash, raw)
+        })
+        .collect();
+    announcement.types = Bytes::from(types);
+
+    let mut eth68 = BytesMut::new();
+    announcement.encode(&mut eth68);
+    let mut eth66 = BytesMut::new();
+    announcement.hashes.encode(&mut eth66);
+
+    AnnouncedTxs {
+        txs,
+        announcement: TxAnnouncement {
+            eth68: EthMessage::new_compressed_message(
+                EthProtocol::NewPooledTransactionHashesMsg,
+                &eth68,
+            ),
+            eth66: EthMessage::new_compressed_message(
+                EthProtocol::NewPooledTransactionHashesMsg,
+                &eth66,
+            ),
+        },
+    }
+}
+
+fn add_to_pool(txs: &[(H256, Bytes)]) {
+    let now = Instant::now();
+    forget_old_txs(now);
+
+    for (hash, raw) in txs {
+        // peers announce our txs back to us, there is no point in requesting them
+        cache::mark_as_fetched(hash);
+        OUR_POOL.insert(
+            *hash,
+            PooledTx {
+                raw: raw.clone(),
+                added_at: now,
+            },
+        );
+    }
+}
+
+/// Adds raw txs to the pool and builds their announcement
+pub fn add_raw_txs(txs: &[Bytes]) -> TxAnnouncement {
+    let announced = announce_raw_txs(txs.to_vec());
+    add_to_pool(&announced.txs);
+    announced.announcement
+}
+
+fn decode_tx_message(msg: &EthMessage) -> Result<Vec<Bytes>, DecodeError> {
+    let rlp = msg.decompressed_rlp()?;
+    decode_block_txs(&mut &rlp[..])
+}
+
+/// Answers `GetPooledTransactionsMsg` with txs we have, unknown hashes are skipped
+pub fn pooled_txs_response(request: &EthMessage) -> Result<EthMessage, DecodeError> {
+    let request = PooledTransactionsRequest::decode(&mut &request.data[..])?;
+
+    let txs = request
+        .hashes
+        .iter()
+        .filter_map(|hash| OUR_POOL.get(hash).map(|tx| tx.value().clone()))
+        .collect::<Vec<_>>();
+
+    let txs_len = txs.iter().map(PooledTx::network_len).sum::<usize>();
+    let mut txs_rlp = BytesMut::with_capacity(txs_len + 8);
+    Header {
+        list: true,
+        payload_length: txs_len,
+    }
+    .encode(&mut txs_rlp);
+    txs.iter()
+        .for_each(|tx| tx.encode_for_network(&mut txs_rlp));
+
+    let mut payload = BytesMut::new();
+    request.request_id.encode(&mut payload);
+    payload.extend_from_slice(&txs_rlp);
+
+    let mut response = BytesMut::with_capacity(payload.len() + 8);
+    Header {
+        list: true,
+        payload_length: payload.len(),
+    }
+    .encode(&mut response);
+    response.extend_from_slice(&payload);
+
+    Ok(EthMessage::new(
+        EthProtocol::PooledTransactionsMsg,
+        response.freeze(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn legacy_tx() -> Bytes {
+        // [nonce, gas price, gas, to, value, data, v, r, s] with dummy signature
+        let mut fields = BytesMut::new();
+        7u64.encode(&mut fields);
+        3_000_000_000u64.encode(&mut fields);
+        21_000u64.encode(&mut fields);
+        Bytes::from_static(&[0xb2; 20]).encode(&mut fields);
+        0u64.encode(&mut fields);
+        Bytes::new().encode(&mut fields);
+        27u64.encode(&mut fields);
+        1u64.encode(&mut fields);
+        1u64.encode(&mut fields);
+
+        let mut raw = BytesMut::new();
+        Header {
+            list: true,
+            payload_length: fields.len(),
+        }
+        .encode(&mut raw);
+        raw.extend_from_slice(&fields);
+        raw.freeze()
+    }
+
+    fn typed_tx() -> Bytes {
+        Bytes::from_static(&[0x02, 0xc3, 0xb2, 0xb2, 0xb2])
+    }
+
+    #[test]
+    fn announces_types_sizes_and_hashes() {
+        let (legacy, typed) = (legacy_tx(), typed_tx());
+        let announcement = add_raw_txs(&[legacy.clone(), typed.clone()]);
+
+        let eth68 = announcement.message_for(ProtocolVersion::Eth68);
+        assert_eq!(eth68.id, EthProtocol::NewPooledTransactionHashesMsg);
+        let decoded =
+            Eth68Announcement::decode(&mut &eth68.decompressed_rlp().unwrap()[..]).unwrap();
+        assert_eq!(&decoded.types[..], &[0, 2]);
+        assert_eq!(decoded.sizes, vec![legacy.len() as u32, typed.len() as u32]);
+        assert_eq!(
+            decoded.hashes,
+            vec![H256(keccak256(&legacy)), H256(keccak256(&typed))]
+        );
+
+        let eth66 = announcement.message_for(ProtocolVersion::Eth66);
+        let hashes = Vec::<H256>::decode(&mut &eth66.decompressed_rlp().unwrap()[..]).unwrap();
+        assert_eq!(hashes, decoded.hashes);
+    }
+
+    #[test]
+    fn serves_pooled_txs() {
+        let (legacy, typed) = (legacy_tx(), typed_tx());
+        add_raw_txs(std::slice::from_ref(&typed));
+        let announcement = add_raw_txs(std::slice::from_ref(&legacy));
+        assert!(announcement
+            .message_for(ProtocolVersion::Eth67)
+            .is_compressed());
+
+        #[derive(RlpEncodable)]
+        struct Request {
+            request_id: u64,
+            hashes: Vec<H256>,
+        }
+        let mut request = BytesMut::new();
+        Request {
+            request_id: 42,
+            hashes: vec![
+                H256(keccak256(&legacy)),
+                H256::repeat_byte(0xb2),
+                H256(keccak256(&typed)),
+            ],
+        }
+        .encode(&mut request);
+
+        let response = pooled_txs_response(&EthMessage::new(
+            EthProtocol::GetPooledTransactionsMsg,
+            request.freeze(),
+        ))
+        .unwrap();
+        assert_eq!(response.id, EthProtocol::PooledTransactionsMsg);
+
+        let buf = &mut &response.data[..];
+        Header::decode(buf).unwrap();
+        assert_eq!(u64::decode(buf).unwrap(), 42);
+        let txs = decode_block_txs(&mut &buf[..]).unwrap();
+        assert_eq!(txs, vec![legacy, typed]);
+    }
+
+    #[test]
+    fn prepared_txs_are_pooled_when_sent() {
+        init_tx_announcements(&[68]);
+        let mut tx = legacy_tx().to_vec();
+        // other nonce than the other tests, so the tx isn't pooled already
+        tx[1] = 8;
+        let tx = Bytes::from(tx);
+        let hash = H256(keccak256(&tx));
+
+        let mut msg = BytesMut::new();
+        Header {
+            list: true,
+            payload_length: tx.len(),
+        }
+        .encode(&mut msg);
+        msg.extend_from_slice(&tx);
+        let prepared = PreparedTxs::new(EthMessage::new_tx_message(msg.freeze()));
+        assert!(!OUR_POOL.contains_key(&hash));
+
+        let announcement = prepared.add_to_pool().unwrap();
+        assert!(OUR_POOL.contains_key(&hash));
+        let eth66 = announcement.message_for(ProtocolVersion::Eth66);
+        let hashes = Vec::<H256>::decode(&mut &eth66.decompressed_rlp().unwrap()[..]).unwrap();
+        assert_eq!(hashes, vec![hash]);
+    }
+}
@@ -0,0 +1,509 @@
+use bytes::{Buf, Bytes};
+use ethers::types::{Address, U256};
+use open_fastrlp::{
+    Decodable, DecodeError, Encodable, Header, HeaderInfo, RlpDecodable, RlpEncodable,
+};
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message, SECP256K1,
+};
+use sha3::{Digest, Keccak256};
+
+use super::{errors::DecodeTxError, types::TxType};
+use crate::types::hash::H256;
+
+/*
+* Full transaction decoder.
+*
+* Unlike the decoder in `decoder.rs` (which is on the hot path and decodes only what is needed to
+* decide whether to buy), this one decodes every field of every tx type, and recovers the sender.
+* It is slow compared to the hot path one (sender recovery is the most expensive part), so it
+* should be used only once we know the tx is interesting.
+* */
+
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<ethers::types::H256>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxSignature {
+    /// For legacy txs this is `v` as it was in the tx (27/28 or EIP-155 value),
+    /// for typed txs this is `y_parity`
+    pub v: u64,
+    pub r: U256,
+    pub s: U256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub tx_type: TxType,
+    pub hash: H256,
+    /// `None` only for pre EIP-155 legacy txs
+    pub chain_id: Option<u64>,
+    pub nonce: u64,
+    /// Set for legacy and access list txs
+    pub gas_price: Option<U256>,
+    /// Set for dynamic fee and blob txs
+    pub max_priority_fee_per_gas: Option<U256>,
+    /// Set for dynamic fee and blob txs
+    pub max_fee_per_gas: Option<U256>,
+    pub gas_limit: u64,
+    /// `None` when tx is contract creation
+    pub to: Option<Address>,
+    pub value: U256,
+    pub input: Bytes,
+    pub access_list: Vec<AccessListItem>,
+    /// Set only for blob txs
+    pub max_fee_per_blob_gas: Option<U256>,
+    pub blob_versioned_hashes: Vec<ethers::types::H256>,
+    pub signature: TxSignature,
+    pub from: Address,
+}
+
+impl Transaction {
+    /// Decodes tx as it is encoded in eth messages (Transactions, PooledTransactions, block bodies):
+    /// legacy tx is RLP list, typed tx is RLP string with EIP-2718 envelope inside
+    pub fn decode(buf: &mut &[u8]) -> Result<Self, DecodeTxError> {
+        let tx_metadata = HeaderInfo::decode(buf)?;
+        if buf.len() < tx_metadata.total_len {
+            return Err(DecodeTxError::from(DecodeError::InputTooShort));
+        }
+
+        if tx_metadata.list {
+            let raw = &buf[..tx_metadata.total_len];
+            buf.advance(tx_metadata.total_len);
+            return Self::decode_raw(raw);
+        }
+
+        let tx_metadata = Header::decode_from_info(buf, tx_metadata)?;
+        let raw = &buf[..tx_metadata.payload_length];
+        buf.advance(tx_metadata.payload_length);
+        Self::decode_raw(raw)
+    }
+
+    /// Decodes tx from EIP-2718 envelope (`tx_type || rlp(fields)` or legacy RLP list),
+    /// which is the format of signed raw txs (e.g. what `eth_sendRawTransaction` accepts)
+    pub fn decode_raw(raw: &[u8]) -> Result<Self, DecodeTxError> {
+        if raw.is_empty() {
+            return Err(DecodeTxError::from(DecodeError::InputTooShort));
+        }
+
+        if raw[0] >= 0xc0 {
+            return decode_legacy(raw);
+        }
+
+        let tx_type = TxType::try_from(raw[0])?;
+        match tx_type {
+            TxType::Legacy => Err(DecodeTxError::UnknownTxType),
+            TxType::Blob => decode_typed(tx_type, unwrap_blob_network_form(&raw[1..])?),
+            _ => decode_typed(tx_type, &raw[1..]),
+        }
+    }
+
+    pub fn selector(&self) -> Option<&[u8]> {
+        self.input.get(..4)
+    }
+}
+
+/// Decodes the list of txs, e.g. payload of Transactions message
+pub fn decode_tx_list(buf: &mut &[u8]) -> Result<Vec<Transaction>, DecodeTxError> {
+    let metadata = Header::decode(buf)?;
+    if !metadata.list {
+        return Err(DecodeTxError::from(DecodeError::UnexpectedString));
+    }
+
+    let payload_view = &mut &buf[..metadata.payload_length];
+    let mut txs = Vec::new();
+    while !payload_view.is_empty() {
+        txs.push(Transaction::decode(payload_view)?);
+    }
+    buf.advance(metadata.payload_length);
+
+    Ok(txs)
+}
+
+/// Recovers the address that signed `signing_hash`
+pub fn recover_sender(
+    signing_hash: &[u8; 32],
+    r: U256,
+    s: U256,
+    recovery_id: u64,
+) -> Result<Address, DecodeTxError> {
+    let recovery_id =
+        RecoveryId::from_i32(recovery_id as i32).map_err(|_| DecodeTxError::InvalidSignature)?;
+
+    let mut compact = [0u8; 64];
+    r.to_big_endian(&mut compact[..32]);
+    s.to_big_endian(&mut compact[32..]);
+    let signature = RecoverableSignature::from_compact(&compact, recovery_id)
+        .map_err(|_| DecodeTxError::InvalidSignature)?;
+
+    let message = Message::from_slice(signing_hash).map_err(|_| DecodeTxError::InvalidSignature)?;
+    let public_key = SECP256K1
+        .recover_ecdsa(&message, &signature)
+        .map_err(|_| DecodeTxError::InvalidSignature)?;
+
+    let hash = Keccak256::digest(&public_key.serialize_uncompressed()[1..]);
+    Ok(Address::from_slice(&hash[12..]))
+}
+
+fn decode_legacy(raw: &[u8]) -> Result<Transaction, DecodeTxError> {
+    let buf = &mut &raw[..];
+    let tx_metadata = Header::decode(buf)?;
+    if !tx_metadata.list {
+        return Err(DecodeTxError::from(DecodeError::UnexpectedString));
+    }
+    if buf.len() < tx_metadata.payload_length {
+        return Err(DecodeTxError::from(DecodeError::InputTooShort));
+    }
+    let tx_len = raw.len() - buf.len() + tx_metadata.payload_length;
+
+    let payload = &buf[..tx_metadata.payload_length];
+    let payload_view = &mut &payload[..];
+
+    let nonce = u64::decode(payload_view)?;
+    let gas_price = U256::decode(payload_view)?;
+    let gas_limit = u64::decode(payload_view)?;
+    let to = decode_to(payload_view)?;
+    let value = U256::decode(payload_view)?;
+    let input = Bytes::decode(payload_view)?;
+    let unsigned_fields = &payload[..payload.len() - payload_view.len()];
+
+    let v = u64::decode(payload_view)?;
+    let r = U256::decode(payload_view)?;
+    let s = U256::decode(payload_view)?;
+
+    // EIP-155: v = chain_id * 2 + 35 + recovery_id
+    let (chain_id, recovery_id) = match v {
+        27 | 28 => (None, v - 27),
+        v if v >= 35 => (Some((v - 35) / 2), (v - 35) % 2),
+        _ => return Err(DecodeTxError::InvalidSignature),
+    };
+
+    let mut signing_payload = Vec::with_capacity(unsigned_fields.len() + 16);
+    match chain_id {
+        Some(chain_id) => {
+            Header {
+                list: true,
+                payload_length: unsigned_fields.len() + chain_id.length() + 2,
+            }
+            .encode(&mut signing_payload);
+            signing_payload.extend_from_slice(unsigned_fields);
+            chain_id.encode(&mut signing_payload);
+            signing_payload.extend_from_slice(&[0x80, 0x80]);
+        }
+        None => {
+            Header {
+                list: true,
+                payload_length: unsigned_fields.len(),
+            }
+            .encode(&mut signing_payload);
+            signing_payload.extend_from_slice(unsigned_fields);
+        }
+    }
+    let signing_hash: [u8; 32] = Keccak256::digest(&signing_payload).into();
+
+    Ok(Transaction {
+        tx_type: TxType::Legacy,
+        hash: H256::from_slice(&Keccak256::digest(&raw[..tx_len])),
+        chain_id,
+        nonce,
+        gas_price: Some(gas_price),
+        max_priority_fee_per_gas: None,
+        max_fee_per_gas: None,
+        gas_limit,
+        to,
+        value,
+        input,
+        access_list: Vec::new(),
+        max_fee_per_blob_gas: None,
+        blob_versioned_hashes: Vec::new(),
+        signature: TxSignature { v, r, s },
+        from: recover_sender(&signing_hash, r, s, recovery_id)?,
+    })
+}
+
+/// `body` is RLP list of tx fields, i.e. envelope without the tx type byte
+fn decode_typed(tx_type: TxType, body: &[u8]) -> Result<Transaction, DecodeTxError> {
+    let buf = &mut &body[..];
+    let tx_metadata = Header::decode(buf)?;
+    if !tx_metadata.list {
+        return Err(DecodeTxError::from(DecodeError::UnexpectedString));
+    }
+    if buf.len() < tx_metadata.payload_length {
+        return Err(DecodeTxError::from(DecodeError::InputTooShort));
+    }
+    let tx_len = body.len() - buf.len() + tx_metadata.payload_length;
+
+    let payload = &buf[..tx_metadata.payload_length];
+    let payload_view = &mut &payload[..];
+
+    let chain_id = u64::decode(payload_view)?;
+    let nonce = u64::decode(payload_view)?;
+
+    let (gas_price, max_priority_fee_per_gas, max_fee_per_gas) = match tx_type {
+        TxType::AccessList => (Some(U256::decode(payload_view)?), None, None),
+        _ => (
+            None,
+            Some(U256::decode(payload_view)?),
+            Some(U256::decode(payload_view)?),
+        ),
+    };
+
+    let gas_limit = u64::decode(payload_view)?;
+    let to = decode_to(payload_view)?;
+    let value = U256::decode(payload_view)?;
+    let input = Bytes::decode(payload_view)?;
+    let access_list = Vec::<AccessListItem>::decode(payload_view)?;
+
+    let (max_fee_per_blob_gas, blob_versioned_hashes) = match tx_type {
+        TxType::Blob => (
+            Some(U256::decode(payload_view)?),
+            Vec::<ethers::types::H256>::decode(payload_view)?,
+        ),
+        _ => (None, Vec::new()),
+    };
+    let unsigned_fields = &payload[..payload.len() - payload_view.len()];
+
+    let y_parity = u64::decode(payload_view)?;
+    let r = U256::decode(payload_view)?;
+    let s = U256::decode(payload_view)?;
+
+    let mut hasher = Keccak256::new();
+    hasher.update([tx_type as u8]);
+    let mut header = Vec::with_capacity(9);
+    Header {
+        list: true,
+        payload_length: unsigned_fields.len(),
+    }
+    .encode(&mut header);
+    hasher.update(&header);
+    hasher.update(unsigned_fields);
+    let signing_hash: [u8; 32] = hasher.finalize().into();
+
+    let mut hasher = Keccak256::new();
+    hasher.update([tx_type as u8]);
+    hasher.update(&body[..tx_len]);
+
+    Ok(Transaction {
+        tx_type,
+        hash: H256::from_slice(&hasher.finalize()),
+        chain_id: Some(chain_id),
+        nonce,
+        gas_price,
+        max_priority_fee_per_gas,
+        max_fee_per_gas,
+        gas_limit,
+        to,
+        value,
+        input,
+        access_list,
+        max_fee_per_blob_gas,
+        blob_versioned_hashes,
+        signature: TxSignature { v: y_parity, r, s },
+        from: recover_sender(&signing_hash, r, s, y_parity)?,
+    })
+}
+
+/// Blob txs in PooledTransactions are sent as `[tx_fields, blobs, commitments, proofs]`,
+/// while in blocks (and in hash) only `tx_fields` are used
+fn unwrap_blob_network_form(body: &[u8]) -> Result<&[u8], DecodeTxError> {
+    let buf = &mut &body[..];
+    let _outer = Header::decode(buf)?;
+    let first_item = HeaderInfo::decode(buf)?;
+    if !first_item.list {
+        return Ok(body);
+    }
+
+    if buf.len() < first_item.total_len {
+        return Err(DecodeTxError::from(DecodeError::InputTooShort));
+    }
+    Ok(&buf[..first_item.total_len])
+}
+
+fn decode_to(buf: &mut &[u8]) -> Result<Option<Address>, DecodeTxError> {
+    match buf.first() {
+        None => Err(DecodeTxError::from(DecodeError::InputTooShort)),
+        Some(0x80) => {
+            buf.advance(1);
+            Ok(None)
+        }
+        Some(_) => Ok(Some(Address::decode(buf)?)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::{
+            transaction::eip2718::TypedTransaction, transaction::eip2930::AccessList,
+            Eip1559TransactionRequest, Eip2930TransactionRequest, TransactionRequest,
+        },
+    };
+    use secp256k1::SecretKey;
+
+    use super::*;
+
+    const CHAIN_ID: u64 = 56;
+
+    fn wallet() -> LocalWallet {
+        "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(CHAIN_ID)
+    }
+
+    fn to() -> Address {
+        "0x10ED43C718714eb63d5aA57B78B54704E256024E"
+            .parse()
+            .unwrap()
+    }
+
+    fn sign(tx: TypedTransaction) -> (Vec<u8>, ethers::types::H256) {
+        let signature = wallet().sign_transaction_sync(&tx).unwrap();
+        (tx.rlp_signed(&signature).to_vec(), tx.hash(&signature))
+    }
+
+    #[test]
+    fn decode_legacy_tx() {
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to(to())
+            .nonce(7)
+            .gas(210_000)
+            .gas_price(3_000_000_000u64)
+            .value(1_000)
+            .data(vec![0xe8, 0xe3, 0x37, 0x00, 0x01])
+            .chain_id(CHAIN_ID)
+            .into();
+        let (raw, hash) = sign(tx);
+
+        let decoded = Transaction::decode(&mut &raw[..]).unwrap();
+        assert_eq!(decoded.tx_type, TxType::Legacy);
+        assert_eq!(decoded.hash.as_bytes(), hash.as_bytes());
+        assert_eq!(decoded.chain_id, Some(CHAIN_ID));
+        assert_eq!(decoded.nonce, 7);
+        assert_eq!(decoded.gas_limit, 210_000);
+        assert_eq!(decoded.gas_price, Some(U256::from(3_000_000_000u64)));
+        assert_eq!(decoded.to, Some(to()));
+        assert_eq!(decoded.value, U256::from(1_000));
+        assert_eq!(decoded.selector(), Some(&[0xe8, 0xe3, 0x37, 0x00][..]));
+        assert_eq!(decoded.from, wallet().address());
+    }
+
+    #[test]
+    fn decode_access_list_tx() {
+        let access_list =
+            AccessList::from(vec![ethers::types::transaction::eip2930::AccessListItem {
+                address: to(),
+                storage_keys: vec![ethers::types::H256::repeat_byte(1)],
+            }]);
+        let request = TransactionRequest::new()
+            .to(to())
+            .nonce(1)
+            .gas(100_000)
+            .gas_price(5)
+            .chain_id(CHAIN_ID);
+        let tx: TypedTransaction = Eip2930TransactionRequest::new(request, access_list).into();
+        let (raw, hash) = sign(tx);
+
+        let decoded = Transaction::decode_raw(&raw).unwrap();
+        assert_eq!(decoded.tx_type, TxType::AccessList);
+        assert_eq!(decoded.hash.as_bytes(), hash.as_bytes());
+        assert_eq!(decoded.gas_price, Some(U256::from(5)));
+        assert_eq!(
+            decoded.access_list,
+            vec![AccessListItem {
+                address: to(),
+                storage_keys: vec![ethers::types::H256::repeat_byte(1)],
+            }]
+        );
+        assert_eq!(decoded.from, wallet().address());
+    }
+
+    #[test]
+    fn decode_dynamic_fee_tx_from_network_encoding() {
+        let tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(to())
+            .nonce(2)
+            .gas(100_000)
+            .max_priority_fee_per_gas(1)
+            .max_fee_per_gas(10)
+            .chain_id(CHAIN_ID)
+            .into();
+        let (raw, hash) = sign(tx);
+
+        // in eth messages typed txs are wrapped into RLP string
+        let mut network = Vec::new();
+        Header {
+            list: false,
+            payload_length: raw.len(),
+        }
+        .encode(&mut network);
+        network.extend_from_slice(&raw);
+
+        let decoded = Transaction::decode(&mut &network[..]).unwrap();
+        assert_eq!(decoded.tx_type, TxType::DynamicFee);
+        assert_eq!(decoded.hash.as_bytes(), hash.as_bytes());
+        assert_eq!(decoded.max_priority_fee_per_gas, Some(U256::from(1)));
+        assert_eq!(decoded.max_fee_per_gas, Some(U256::from(10)));
+        assert_eq!(decoded.gas_price, None);
+        assert_eq!(decoded.from, wallet().address());
+    }
+
+    #[test]
+    fn decode_blob_tx() {
+        let secret_key = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let public_key = secret_key.public_key(SECP256K1).serialize_uncompressed();
+        let sender = Address::from_slice(&Keccak256::digest(&public_key[1..])[12..]);
+
+        let mut fields = Vec::new();
+        CHAIN_ID.encode(&mut fields);
+        3u64.encode(&mut fields);
+        U256::from(1).encode(&mut fields);
+        U256::from(10).encode(&mut fields);
+        100_000u64.encode(&mut fields);
+        to().encode(&mut fields);
+        U256::zero().encode(&mut fields);
+        Bytes::from_static(&[0xde, 0xad]).encode(&mut fields);
+        Vec::<AccessListItem>::new().encode(&mut fields);
+        U256::from(7).encode(&mut fields);
+        vec![ethers::types::H256::repeat_byte(2)].encode(&mut fields);
+
+        let mut unsigned = vec![TxType::Blob as u8];
+        Header {
+            list: true,
+            payload_length: fields.len(),
+        }
+        .encode(&mut unsigned);
+        unsigned.extend_from_slice(&fields);
+        let message = Message::from_slice(&Keccak256::digest(&unsigned)).unwrap();
+        let (recovery_id, signature) = SECP256K1
+            .sign_ecdsa_recoverable(&message, &secret_key)
+            .serialize_compact();
+
+        (recovery_id.to_i32() as u64).encode(&mut fields);
+        U256::from_big_endian(&signature[..32]).encode(&mut fields);
+        U256::from_big_endian(&signature[32..]).encode(&mut fields);
+        let mut raw = vec![TxType::Blob as u8];
+        Header {
+            list: true,
+            payload_length: fields.len(),
+        }
+        .encode(&mut raw);
+        raw.extend_from_slice(&fields);
+
+        let decoded = Transaction::decode_raw(&raw).unwrap();
+        assert_eq!(decoded.tx_type, TxType::Blob);
+        assert_eq!(decoded.hash, H256::from_slice(&Keccak256::digest(&raw)));
+        assert_eq!(decoded.max_fee_per_blob_gas, Some(U256::from(7)));
+        assert_eq!(
+            decoded.blob_versioned_hashes,
+            vec![ethers::types::H256::repeat_byte(2)]
+        );
+        assert_eq!(decoded.input, Bytes::from_static(&[0xde, 0xad]));
+        assert_eq!(decoded.from, sender);
+    }
+}
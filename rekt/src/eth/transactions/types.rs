@@ -3,7 +3,7 @@ use derive_more::Display;
 use super::errors::DecodeTxError;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
-pub(super) enum TxType {
+pub enum TxType {
     Legacy,
     AccessList,
     DynamicFee,
@@ -22,6 +22,8 @@ pub enum EthProtocol {
     PooledTransactionsMsg = 0x0a,
     // Protocol messages overloaded in eth/67
     UpgradeStatusMsg = 0x0b,
+    // Added in eth/69
+    BlockRangeUpdateMsg = 0x11,
     DevP2PPing = 0xfe,
     Unknown = 0xff,
 }
@@ -45,6 +47,7 @@ impl From<u8> for EthProtocol {
             0x09 => Self::GetPooledTransactionsMsg,
             0x0a => Self::PooledTransactionsMsg,
             0x0b => Self::UpgradeStatusMsg,
+            0x11 => Self::BlockRangeUpdateMsg,
             _ => Self::Unknown,
         }
     }
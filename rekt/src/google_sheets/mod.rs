@@ -11,7 +11,9 @@ use google_sheets4::{
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::{cli::Cli, eth::transactions::decoder::BuyTokenInfo, p2p::Peer, utils::helpers};
+use crate::{
+    cli::Cli, eth::transactions::decoder::BuyTokenInfo, p2p::peer_info::PeerInfo, utils::helpers,
+};
 
 use self::ip_api_helper::get_ip_location_info;
 
@@ -49,7 +51,7 @@ pub struct LogToSheets {
 }
 
 impl LogToSheets {
-    pub async fn new(cli: &Cli, peer: &Peer, buy_info: &BuyTokenInfo) -> Self {
+    pub async fn new(cli: &Cli, peer: &PeerInfo, buy_info: &BuyTokenInfo) -> Self {
         let start_wallet = match cli.first_wallet {
             Some(wallet) => format!("{:#x}", wallet),
             None => "N/A".into(),
@@ -59,9 +61,7 @@ impl LogToSheets {
             None => "N/A".into(),
         };
 
-        let peer_location_info = get_ip_location_info(&peer.node_record.ip)
-            .await
-            .unwrap_or_default();
+        let peer_location_info = get_ip_location_info(&peer.ip).await.unwrap_or_default();
 
         Self {
             token_address: format!("{:#x}", buy_info.token.buy_token_address),
@@ -75,8 +75,8 @@ impl LogToSheets {
             our_country: cli.country.clone(),
             our_city: cli.city.clone(),
             our_td: 0,
-            peer_td: peer.td,
-            peer_enode: peer.node_record.str.clone(),
+            peer_td: u64::try_from(peer.td).unwrap_or(u64::MAX),
+            peer_enode: peer.enode.clone(),
             peer_info: peer.info.clone(),
             bsc_scan_token_url: helpers::get_bsc_token_url(buy_info.token.buy_token_address),
             bsc_scan_liquidity_url: helpers::get_bsc_tx_url(buy_info.hash),
@@ -1,4 +1,5 @@
 pub mod blockchain;
+pub mod bsc;
 pub mod cli;
 pub mod config;
 pub mod constants;
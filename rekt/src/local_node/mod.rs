@@ -1,11 +1,15 @@
+pub mod node_key;
+
 use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, RwLock};
 
-use bytes::BytesMut;
-use enr::{Enr, EnrBuilder};
+use bytes::{Bytes, BytesMut};
+use enr::{Enr, EnrBuilder, EnrError};
 use open_fastrlp::Encodable;
 use secp256k1::{PublicKey, SecretKey};
 
 use crate::blockchain::fork::ForkId;
+use crate::blockchain::head_tracker::current_fork_id;
 use crate::constants::DEFAULT_PORT;
 use crate::types::hash::H512;
 use crate::types::node_record::NodeRecord;
@@ -16,38 +20,43 @@ pub struct LocalNode {
     pub private_key: secp256k1::SecretKey,
     pub public_key: PublicKey,
     pub public_ip_retrieved: bool,
-    pub enr: Enr<SecretKey>,
+    /// Shared by clones, unless one of them changes its port
+    enr: Arc<RwLock<LocalEnr>>,
+}
+
+/// Our ENR and the fork id in its `eth` entry
+#[derive(Debug, Clone)]
+struct LocalEnr {
+    fork_id: ForkId,
+    enr: Enr<SecretKey>,
+}
+
+fn fork_id_rlp_encoded(fork_id: ForkId) -> Bytes {
+    #[derive(open_fastrlp::RlpEncodable)]
+    struct ForkIdRlpHelper {
+        fork_id: ForkId,
+    }
+
+    let mut buf = BytesMut::new();
+    ForkIdRlpHelper { fork_id }.encode(&mut buf);
+    buf.freeze()
 }
 
 impl LocalNode {
-    pub fn new(ip: Option<IpAddr>) -> Self {
-        let private_key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+    /// `private_key` is the persistent node key (see `node_key::resolve_node_key`)
+    pub fn new(ip: Option<IpAddr>, private_key: SecretKey) -> Self {
         let public_key = PublicKey::from_secret_key(&secp256k1::Secp256k1::new(), &private_key);
         let (ip, public_ip_retrieved) = match ip {
             Some(ip) => (ip, true),
             None => (IpAddr::V4(Ipv4Addr::UNSPECIFIED), false),
         };
 
-        let fork_id_rlp_encoded = {
-            #[derive(open_fastrlp::RlpEncodable)]
-            struct ForkIdRlpHelper {
-                fork_id: ForkId,
-            }
-
-            let mut buf = BytesMut::new();
-            let fork_id = *crate::blockchain::bsc_chain_spec::BSC_MAINNET_FORK_ID;
-
-            let fork_id_rlp_helper = ForkIdRlpHelper { fork_id };
-            fork_id_rlp_helper.encode(&mut buf);
-
-            buf.freeze()
-        };
-
+        let fork_id = current_fork_id();
         let local_enr = EnrBuilder::new("v4")
             .ip(ip)
             .udp4(DEFAULT_PORT)
             .tcp4(DEFAULT_PORT)
-            .add_value_rlp("eth", fork_id_rlp_encoded)
+            .add_value_rlp("eth", fork_id_rlp_encoded(fork_id))
             .build(&private_key)
             .unwrap();
 
@@ -55,8 +64,83 @@ impl LocalNode {
             private_key,
             public_key,
             public_ip_retrieved,
-            enr: local_enr,
+            enr: Arc::new(RwLock::new(LocalEnr {
+                fork_id,
+                enr: local_enr,
+            })),
             node_record: NodeRecord::new(ip, DEFAULT_PORT, DEFAULT_PORT, public_key),
         }
     }
+
+    /// Record with its own discovery port, other clones keep advertising theirs
+    pub fn set_udp4(&mut self, port: u16) -> Result<(), EnrError> {
+        let mut local = self.enr.read().unwrap().clone();
+        local.enr.set_udp4(port, &self.private_key)?;
+        self.enr = Arc::new(RwLock::new(local));
+        Ok(())
+    }
+
+    /// Our ENR, with the fork id the head tracker is currently at
+    pub fn enr(&self) -> Enr<SecretKey> {
+        self.enr_for(current_fork_id())
+    }
+
+    /*
+     * Fork id moves with the head (see `head_tracker`), so the record is checked every time it's
+     * sent. Changing the `eth` entry re-signs the record and bumps its seq, peers see the new seq
+     * in our pings and ask for the record again.
+     * */
+    fn enr_for(&self, fork_id: ForkId) -> Enr<SecretKey> {
+        {
+            let local = self.enr.read().unwrap();
+            if local.fork_id == fork_id {
+                return local.enr.clone();
+            }
+        }
+
+        let mut local = self.enr.write().unwrap();
+        if local.fork_id != fork_id {
+            match local
+                .enr
+                .insert_raw_rlp("eth", fork_id_rlp_encoded(fork_id), &self.private_key)
+            {
+                Ok(_) => local.fork_id = fork_id,
+                Err(e) => println!("Failed to update fork id of local ENR: {:?}", e),
+            }
+        }
+        local.enr.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::blockchain::fork::ForkHash;
+
+    use super::*;
+
+    #[test]
+    fn enr_follows_fork_id() {
+        let node = LocalNode::new(None, SecretKey::new(&mut secp256k1::rand::thread_rng()));
+        let enr = node.enr();
+        let fork_id = current_fork_id();
+        assert_eq!(
+            enr.get_raw_rlp("eth"),
+            Some(fork_id_rlp_encoded(fork_id).as_ref())
+        );
+
+        let next = ForkId {
+            hash: ForkHash([1, 2, 3, 4]),
+            next: 0,
+        };
+        let updated = node.enr_for(next);
+        assert_eq!(updated.seq(), enr.seq() + 1);
+        assert_eq!(
+            updated.get_raw_rlp("eth"),
+            Some(fork_id_rlp_encoded(next).as_ref())
+        );
+        assert!(updated.verify());
+
+        // clones share the record, same fork id doesn't bump seq again
+        assert_eq!(node.clone().enr_for(next), updated);
+    }
 }
@@ -0,0 +1,121 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use secp256k1::SecretKey;
+use thiserror::Error;
+
+/// Same file name geth uses in its data dir
+pub const DEFAULT_NODE_KEY_FILE: &str = "nodekey";
+
+#[derive(Debug, Error)]
+pub enum NodeKeyError {
+    #[error("Could not read or write node key file {path}: {source}")]
+    Io { path: String, source: io::Error },
+    #[error("Node key is not valid hex")]
+    InvalidHex,
+    #[error("Node key is not a valid secp256k1 secret key")]
+    InvalidKey,
+}
+
+/*
+* Node key is our identity in both discovery and RLPx, enode id is derived from it.
+* With a new key on every start peers which have us as static/trusted node can't find us anymore,
+* and whatever reputation we had in discovery tables is gone, so the key is generated once and
+* stored as hex (like geth's `nodekey` file), then reused.
+*
+* Key given on the command line (`--nodekey_hex`) wins, then the key file from the command line
+* (`--nodekey`), then the one from config (`node_key_file`), then `DEFAULT_NODE_KEY_FILE`.
+* */
+pub fn resolve_node_key(
+    key_hex: Option<&str>,
+    key_file: Option<&str>,
+) -> Result<SecretKey, NodeKeyError> {
+    match key_hex {
+        Some(key_hex) => parse_node_key(key_hex),
+        None => load_or_generate_node_key(Path::new(key_file.unwrap_or(DEFAULT_NODE_KEY_FILE))),
+    }
+}
+
+pub fn parse_node_key(key_hex: &str) -> Result<SecretKey, NodeKeyError> {
+    let key_hex = key_hex.trim();
+    let bytes = hex::decode(key_hex.strip_prefix("0x").unwrap_or(key_hex))
+        .map_err(|_| NodeKeyError::InvalidHex)?;
+    SecretKey::from_slice(&bytes).map_err(|_| NodeKeyError::InvalidKey)
+}
+
+pub fn load_or_generate_node_key(path: &Path) -> Result<SecretKey, NodeKeyError> {
+    let io_err = |source| NodeKeyError::Io {
+        path: path.display().to_string(),
+        source,
+    };
+
+    match fs::read_to_string(path) {
+        Ok(key_hex) => parse_node_key(&key_hex),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let key = SecretKey::new(&mut secp256k1::rand::thread_rng());
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir).map_err(io_err)?;
+            }
+            write_key_file(path, &key).map_err(io_err)?;
+            println!("Generated new node key: {}", path.display());
+            Ok(key)
+        }
+        Err(e) => Err(io_err(e)),
+    }
+}
+
+/// Key file is readable only by us, anyone with the key can impersonate our node
+fn write_key_file(path: &Path, key: &SecretKey) -> io::Result<()> {
+    let key_hex = hex::encode(key.secret_bytes());
+
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(key_hex.as_bytes())
+    }
+
+    #[cfg(not(unix))]
+    fs::write(path, key_hex)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_is_generated_once_and_reused() {
+        let dir = std::env::temp_dir().join(format!("rekt-nodekey-{}", std::process::id()));
+        let path = dir.join("nested").join(DEFAULT_NODE_KEY_FILE);
+        let _ = fs::remove_dir_all(&dir);
+
+        let generated = load_or_generate_node_key(&path).unwrap();
+        let loaded = load_or_generate_node_key(&path).unwrap();
+        assert_eq!(generated, loaded);
+        assert_eq!(resolve_node_key(None, path.to_str()).unwrap(), generated);
+
+        // key from the command line wins over the file
+        let hex_key = "0x".to_string() + &"11".repeat(32);
+        let given = resolve_node_key(Some(&hex_key), path.to_str()).unwrap();
+        assert_eq!(given.secret_bytes(), [0x11; 32]);
+
+        fs::write(&path, "not a key").unwrap();
+        assert!(matches!(
+            load_or_generate_node_key(&path),
+            Err(NodeKeyError::InvalidHex)
+        ));
+        assert!(matches!(
+            parse_node_key(&"00".repeat(32)),
+            Err(NodeKeyError::InvalidKey)
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -7,11 +7,18 @@ use warp::{filters::path::end, reject::Reject, Filter};
 
 use crate::{
     discover::server::Server,
-    eth::eth_message::EthMessage,
+    eth::{eth_message::EthMessage, transactions::confirmations::all_our_txs},
     mev,
-    p2p::{peer::PeerType, peer_info::PeerInfo},
+    p2p::{
+        fan_out::{self, fan_out},
+        peer::PeerType,
+        peer_info::PeerInfo,
+    },
     server::{inbound_connections::InboundConnections, peers::PEERS},
-    token::tokens_to_buy::{get_token_by_address, remove_all_tokens_to_buy},
+    token::{
+        lifecycle::all_token_states,
+        tokens_to_buy::{get_token_by_address, remove_all_tokens_to_buy},
+    },
     utils::wei_gwei_converter::MIN_GAS_PRICE,
     wallets::local_wallets::{generate_rlp_prep_tx, generate_rlp_snappy_prep_tx},
 };
@@ -19,7 +26,6 @@ use crate::{
 pub fn run_local_server(
     disc_server: Option<Arc<Server>>,
     incoming_listener: Arc<InboundConnections>,
-    tx_sender: tokio::sync::broadcast::Sender<EthMessage>,
 ) {
     let disc_server_toggler = disc_server.clone();
     let disc_server_enodes = disc_server.clone();
@@ -27,8 +33,6 @@ pub fn run_local_server(
         //TODO: extract this into at least separate function (and maybe even file)
         let prep = warp::path!("prep" / String).and_then({
             move |token_address: String| {
-                let tx_sender = tx_sender.clone();
-
                 async move {
                     let token_address = match Address::from_str(&token_address) {
                         Ok(t_a) => t_a,
@@ -45,9 +49,9 @@ pub fn run_local_server(
                     }
                     let token = token.unwrap();
                     let prep_tx = EthMessage::new_compressed_tx_message(
-                        generate_rlp_snappy_prep_tx(token, MIN_GAS_PRICE).await,
+                        generate_rlp_snappy_prep_tx(&token, MIN_GAS_PRICE).await,
                     );
-                    let _ = tx_sender.send(prep_tx);
+                    let reports = fan_out(prep_tx, &[]).await;
                     // let prep_tx = generate_rlp_prep_tx(token, MIN_GAS_PRICE).await.0;
                     // match mev::puissant::send_mev(prep_tx, 1, 60).await {
                     //     Ok(resp) => {
@@ -80,10 +84,9 @@ pub fn run_local_server(
                     //     }
                     // }
 
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
                     cprintln!(
-                        "<yellow>[{}]Prep sent successfully: {}</>",
-                        PEERS.len(),
+                        "<yellow>Prep {}: {}</>",
+                        fan_out::summarize(&reports),
                         token.buy_token_address
                     );
                     return Ok(format!(
@@ -153,6 +156,14 @@ pub fn run_local_server(
             PeerInfo::slice_to_json(&peers).unwrap()
         });
 
+        let token_states = warp::path!("tokenstates")
+            .and(end())
+            .map(|| serde_json::to_string(&all_token_states()).unwrap());
+
+        let our_txs = warp::path!("ourtxs")
+            .and(end())
+            .map(|| serde_json::to_string(&all_our_txs()).unwrap());
+
         let get_enodes = warp::path("enodes").and(end()).map(move || {
             if let Some(disc) = &disc_server_enodes {
                 let enodes = disc.get_bsc_node_enodes();
@@ -171,6 +182,8 @@ pub fn run_local_server(
             .or(refresh_tokens)
             .or(disc)
             .or(peer_infos)
+            .or(token_states)
+            .or(our_txs)
             .or(get_enodes);
         warp::serve(routes).run(([0, 0, 0, 0], 6060)).await;
     });
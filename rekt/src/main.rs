@@ -1,22 +1,33 @@
 use std::fs::File;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
+use rekt::blockchain::{chain_spec, init_chain_spec};
 use rekt::cli::Cli;
 use rekt::config::get_config;
-use rekt::constants::BOOTSTRAP_NODES;
+use rekt::constants::DEFAULT_PORT;
+use rekt::discover::dns::resolver::{DnsResolver, DEFAULT_DNS_SERVER};
+use rekt::discover::dns::{resolve_enrtrees, RESOLVE_TIMEOUT};
+use rekt::discover::node_db::{NodeDb, DEFAULT_NODE_DB_FILE};
+use rekt::discover::server::Server;
+use rekt::discover::v5::service::Discv5;
+use rekt::local_node::node_key::resolve_node_key;
 use rekt::local_node::LocalNode;
 use rekt::local_server::run_local_server;
 use rekt::mev;
 use rekt::public_nodes::nodes::init_connection_to_public_nodes;
+use rekt::server::errors::ConnectionTaskError;
 use rekt::server::inbound_connections::InboundConnections;
 use rekt::server::outbound_connections::OutboundConnections;
 
+use bytes::Bytes;
 use clap::Parser;
 use mimalloc::MiMalloc;
 use rekt::server::peers::BLACKLIST_PEERS_BY_ID;
 use rekt::token::tokens_to_buy::import_tokens_to_buy;
 use rekt::types::node_record::NodeRecord;
 use rekt::wallets::local_wallets::init_local_wallets;
+use tokio::sync::mpsc::UnboundedSender;
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
@@ -33,9 +44,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     mev::puissant::get_score().await;
 
     let mut config = get_config()?;
+    init_chain_spec(config.chain_spec()?)?;
+
+    let mut enrtrees = chain_spec().enrtrees.clone();
+    enrtrees.append(&mut config.enrtrees);
+    let resolver = DnsResolver::new(config.dns_server.unwrap_or(DEFAULT_DNS_SERVER));
+    let dns_nodes = resolve_enrtrees(resolver, &enrtrees, RESOLVE_TIMEOUT).await;
+    config.nodes.extend(dns_nodes.into_iter().map(|n| n.str));
+
     let all_nodes = get_all_nodes(&mut config.nodes);
 
-    rekt::eth::transactions::cache::init_cache();
+    rekt::eth::transactions::cache::init_cache(args.tx_cache_size);
+    rekt::server::peer_slots::init_peer_budget(args.max_inbound_peers, args.max_outbound_peers);
+    rekt::eth::transactions::our_pool::init_tx_announcements(&args.announce_txs_to);
 
     let file = File::create("log.txt")?;
     let subscriber = FmtSubscriber::builder()
@@ -46,7 +67,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     tracing::subscriber::set_global_default(subscriber).expect("Could not init tracing");
 
-    let our_node = LocalNode::new(public_ip::addr().await);
+    let node_key = resolve_node_key(
+        args.node_key_hex.as_deref(),
+        args.node_key_file
+            .as_deref()
+            .or(config.node_key_file.as_deref()),
+    )?;
+    let our_node = LocalNode::new(public_ip::addr().await, node_key);
     println!("{:?}", our_node.node_record.str);
 
     init_connection_to_public_nodes().await;
@@ -57,7 +84,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("{}", args);
 
     let (conn_tx, conn_rx) = tokio::sync::mpsc::unbounded_channel();
-    let (tx_sender, _) = tokio::sync::broadcast::channel(2);
     let outbound_connections = OutboundConnections::new(
         our_node.private_key,
         our_node.public_key,
@@ -65,7 +91,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         conn_rx,
         conn_tx.clone(),
         args.clone(),
-        tx_sender.clone(),
     );
 
     BLACKLIST_PEERS_BY_ID.insert(our_node.node_record.id);
@@ -77,20 +102,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             rekt::discover::server::Server::new(
                 our_node.clone(),
                 all_nodes,
-                conn_tx,
-                udp_tx,
+                conn_tx.clone(),
+                udp_tx.clone(),
                 args.clone(),
+                NodeDb::new(
+                    args.node_db_file
+                        .as_deref()
+                        .or(config.node_db_file.as_deref())
+                        .unwrap_or(DEFAULT_NODE_DB_FILE),
+                ),
             )
             .await?,
         );
         rekt::discover::server::Server::start(discover_server.clone(), udp_rx);
+
+        if args.discv5 {
+            start_discv5(
+                &discover_server,
+                our_node.clone(),
+                udp_tx,
+                conn_tx,
+                args.clone(),
+                &config.discv5_bootnodes,
+            )
+            .await?;
+        }
+
         Some(discover_server)
     } else {
         println!("Failed to retrieve public ip, discovery server not started");
         None
     };
 
-    let incoming_listener = Arc::new(InboundConnections::new(our_node, args, tx_sender.clone()));
+    let incoming_listener = Arc::new(InboundConnections::new(our_node, args));
     let listener = incoming_listener.clone();
     tokio::spawn(async move {
         if let Err(e) = listener.run().await {
@@ -98,19 +142,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
-    run_local_server(disc_server, incoming_listener, tx_sender);
+    run_local_server(disc_server.clone(), incoming_listener);
 
     let _ = tokio::signal::ctrl_c().await;
 
+    if let Some(disc_server) = disc_server {
+        disc_server.save_node_db();
+    }
+
+    Ok(())
+}
+
+/// Discovery v5 shares the discovery v4 socket unless it has its own port
+async fn start_discv5(
+    discover_server: &Arc<Server>,
+    mut our_node: LocalNode,
+    udp_tx: UnboundedSender<(SocketAddr, Bytes)>,
+    conn_tx: UnboundedSender<ConnectionTaskError>,
+    args: Cli,
+    bootnodes: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    match args.discv5_port.filter(|port| *port != DEFAULT_PORT) {
+        None => {
+            let discv5 = Arc::new(Discv5::new(our_node, udp_tx, conn_tx, args, bootnodes));
+            discover_server.attach_discv5(discv5.clone(), true);
+            Discv5::start(discv5, None);
+        }
+        Some(port) => {
+            let socket = Discv5::bind(port).await?;
+            our_node.set_udp4(port).map_err(|e| e.to_string())?;
+
+            let (udp_tx, udp_rx) = tokio::sync::mpsc::unbounded_channel();
+            let discv5 = Arc::new(Discv5::new(our_node, udp_tx, conn_tx, args, bootnodes));
+            discover_server.attach_discv5(discv5.clone(), false);
+            Discv5::start(discv5, Some((socket, udp_rx)));
+        }
+    }
+
     Ok(())
 }
 
 fn get_all_nodes(static_nodes: &mut Vec<String>) -> Vec<String> {
-    let mut nodes = BOOTSTRAP_NODES
-        .iter()
-        .copied()
-        .map(ToString::to_string)
-        .collect::<Vec<String>>();
+    let mut nodes = chain_spec().bootnodes.clone();
 
     nodes.append(static_nodes);
     nodes.sort_unstable();
@@ -16,6 +16,10 @@ pub enum P2PError {
     SnappyCompressError,
     #[error("Too many messages queued")]
     TooManyMessagesQueued,
+    #[error("Send timed out")]
+    SendTimeout,
+    #[error("Capability is not shared with the peer")]
+    CapabilityNotShared,
     #[error("RLPX error")]
     RlpxError,
     #[error("Disconnect requested: {0}")]
@@ -34,4 +38,6 @@ pub enum P2PError {
     AlreadyConnected,
     #[error("Already connected to the same ip")]
     AlreadyConnectedToSameIp,
+    #[error("Too many peers")]
+    TooManyPeers,
 }
@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+
+use crate::eth::eth_message::EthMessage;
+use crate::eth::transactions::our_pool::{self, PreparedTxs};
+use crate::types::hash::H512;
+
+use super::errors::P2PError;
+use super::p2p_wire::WireWriter;
+use super::protocol::ProtocolVersion;
+
+/// Peer which doesn't take the message in this time is reported as failed,
+/// so one stuck connection can't hold the buy
+const SEND_TIMEOUT: Duration = Duration::from_millis(500);
+
+static PEER_WRITERS: Lazy<DashMap<H512, (WireWriter, ProtocolVersion)>> = Lazy::new(DashMap::new);
+
+/*
+* Fan-out writes one pre-built message (buy/sell txs) to writers of all connected peers.
+* Every send is spawned as its own task, in the given order (peers not in the order after them).
+* Spawning only queues the tasks in that order, with several runtime threads they run in parallel,
+* so the order is a head start rather than a guarantee. `FuturesUnordered` just collects the
+* reports as sends complete, in no particular order.
+* Unlike a broadcast channel, nothing is dropped when some peer lags behind.
+*
+* Our txs go through `fan_out_txs`, which sends hash announcements instead of full txs to peers
+* whose protocol version is set up for it (see `our_pool`), announcements are built beforehand.
+* */
+#[derive(Debug)]
+pub struct SendReport {
+    pub peer: H512,
+    pub result: Result<(), P2PError>,
+    pub elapsed: Duration,
+}
+
+pub fn register_peer_writer(id: H512, writer: WireWriter, protocol_version: ProtocolVersion) {
+    PEER_WRITERS.insert(id, (writer, protocol_version));
+}
+
+/// Removes the writer unless it was replaced by a newer connection with the same peer
+pub fn unregister_peer_writer(id: &H512, writer: &WireWriter) {
+    PEER_WRITERS.remove_if(id, |_, (w, _)| w.is_same(writer));
+}
+
+/// Writers of all peers, ones from `order` first (in that order), the rest after them
+fn writers_in_order(order: &[H512]) -> Vec<(H512, (WireWriter, ProtocolVersion))> {
+    let mut writers = Vec::with_capacity(PEER_WRITERS.len());
+    for id in order {
+        if let Some(writer) = PEER_WRITERS.get(id) {
+            writers.push((*id, writer.clone()));
+        }
+    }
+    let ordered = order.iter().collect::<HashSet<_>>();
+    for entry in PEER_WRITERS.iter() {
+        if !ordered.contains(entry.key()) {
+            writers.push((*entry.key(), entry.value().clone()));
+        }
+    }
+    writers
+}
+
+/// Sends the message to all peers and returns report of every send, in order of completion
+pub async fn fan_out(msg: EthMessage, order: &[H512]) -> Vec<SendReport> {
+    fan_out_by_version(order, |_| msg.clone()).await
+}
+
+/// Sends our txs to all peers, as full txs or as hash announcement depending on peer's version
+pub async fn fan_out_txs(txs: &PreparedTxs, order: &[H512]) -> Vec<SendReport> {
+    let announcement = match txs.add_to_pool() {
+        Some(announcement) => announcement,
+        None => return fan_out(txs.message.clone(), order).await,
+    };
+    fan_out_by_version(order, |protocol_version| {
+        if our_pool::announces_to(protocol_version) {
+            announcement.message_for(protocol_version).clone()
+        } else {
+            txs.message.clone()
+        }
+    })
+    .await
+}
+
+async fn fan_out_by_version(
+    order: &[H512],
+    msg_for: impl Fn(ProtocolVersion) -> EthMessage,
+) -> Vec<SendReport> {
+    let started = Instant::now();
+    let mut sends = writers_in_order(order)
+        .into_iter()
+        .map(|(peer, (writer, protocol_version))| {
+            let msg = msg_for(protocol_version);
+            tokio::spawn(async move {
+                let result = match tokio::time::timeout(SEND_TIMEOUT, writer.send(msg)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(P2PError::SendTimeout),
+                };
+                SendReport {
+                    peer,
+                    result,
+                    elapsed: started.elapsed(),
+                }
+            })
+        })
+        .collect::<FuturesUnordered<_>>();
+
+    let mut reports = Vec::with_capacity(sends.len());
+    while let Some(report) = sends.next().await {
+        // send task panicked, its peer is left out of the report
+        if let Ok(report) = report {
+            reports.push(report);
+        }
+    }
+    reports
+}
+
+/// Short summary of fan-out for logs
+pub fn summarize(reports: &[SendReport]) -> String {
+    let sent = reports.iter().filter(|r| r.result.is_ok()).count();
+    let slowest = reports
+        .iter()
+        .filter(|r| r.result.is_ok())
+        .map(|r| r.elapsed)
+        .max()
+        .unwrap_or_default();
+    format!("sent to {}/{} peers in {:?}", sent, reports.len(), slowest)
+}
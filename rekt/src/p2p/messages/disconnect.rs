@@ -48,6 +48,26 @@ impl From<u8> for DisconnectReason {
     }
 }
 
+impl From<DisconnectReason> for u8 {
+    fn from(reason: DisconnectReason) -> Self {
+        match reason {
+            DisconnectReason::DisconnectRequested => 0x00,
+            DisconnectReason::TcpSubsystemError => 0x01,
+            DisconnectReason::ProtocolBreach => 0x02,
+            DisconnectReason::UselessPeer => 0x03,
+            DisconnectReason::TooManyPeers => 0x04,
+            DisconnectReason::AlreadyConnected => 0x05,
+            DisconnectReason::IncompatibleP2PProtocolVersion => 0x06,
+            DisconnectReason::NullNodeIdentity => 0x07,
+            DisconnectReason::ClientQuitting => 0x08,
+            DisconnectReason::UnexpectedHandshakeIdentity => 0x09,
+            DisconnectReason::ConnectedToSelf => 0x0a,
+            DisconnectReason::PingTimeout => 0x0b,
+            DisconnectReason::SubprotocolSpecific | DisconnectReason::Unknown => 0x10,
+        }
+    }
+}
+
 impl Decodable for DisconnectReason {
     //NOTE: the message ID is already parsed, this parses the message body
     fn decode(buf: &mut &[u8]) -> Result<Self, DecodeError> {
@@ -126,4 +146,21 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_encode_disconnect() {
+        use open_fastrlp::Encodable;
+
+        for reason in [
+            DisconnectReason::TooManyPeers,
+            DisconnectReason::DisconnectRequested,
+        ] {
+            let mut buf = Vec::new();
+            P2PMessage::Disconnect(reason).encode(&mut buf);
+            assert_eq!(buf[0], crate::p2p::P2PMessageID::Disconnect as u8);
+
+            let payload = snap::raw::Decoder::new().decompress_vec(&buf[1..]).unwrap();
+            assert_eq!(DisconnectReason::decode(&mut &payload[..]).unwrap(), reason);
+        }
+    }
 }
@@ -55,7 +55,17 @@ impl Encodable for P2PMessage {
                 P2PMessageID::Hello.encode(out);
                 m.encode(out);
             }
-            P2PMessage::Disconnect(_r) => todo!(),
+            P2PMessage::Disconnect(reason) => {
+                P2PMessageID::Disconnect.encode(out);
+                // payload is snappy encoded [reason] (snappy length, literal tag, rlp list)
+                out.put_u8(0x02);
+                out.put_u8(0x04);
+                out.put_u8(0xc1);
+                match u8::from(*reason) {
+                    0 => out.put_u8(open_fastrlp::EMPTY_STRING_CODE),
+                    reason => out.put_u8(reason),
+                }
+            }
             P2PMessage::Ping => {
                 P2PMessageID::Ping.encode(out);
                 out.put_u8(0x01);
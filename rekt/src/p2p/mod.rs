@@ -1,10 +1,12 @@
 pub mod errors;
+pub mod fan_out;
 pub mod messages;
 pub mod p2p_wire;
 pub mod p2p_wire_message;
 pub mod peer;
 pub mod peer_info;
 pub mod protocol;
+pub mod tx_latency;
 
 pub use messages::*;
 pub use peer::Peer;
@@ -1,11 +1,16 @@
 use std::collections::VecDeque;
+use std::sync::Arc;
 use std::task::{ready, Poll};
 
 use bytes::{Bytes, BytesMut};
+use futures::stream::{SplitSink, SplitStream};
 use futures::{Sink, SinkExt, Stream, StreamExt};
 use num_traits::FromPrimitive;
 use open_fastrlp::{Decodable, DecodeError, Encodable};
+use tokio::sync::Mutex;
 
+use crate::bsc::msg_handler::handle_bsc_message;
+use crate::bsc::protocol::{BscMessage, BscProtocol};
 use crate::eth::eth_message::EthMessage;
 use crate::eth::types::protocol::{EthProtocol, ETH_PROTOCOL_OFFSET};
 use crate::p2p::P2PMessage;
@@ -13,24 +18,38 @@ use crate::rlpx::TcpWire;
 
 use super::errors::P2PError;
 use super::p2p_wire_message::{MessageKind, P2pWireMessage};
-use super::peer::is_buy_in_progress;
+use super::protocol::SharedCapabilities;
 use super::{DisconnectReason, P2PMessageID};
 
 const MAX_WRITER_QUEUE_SIZE: usize = 50; // how many messages are we queuing for write
 
-#[pin_project::pin_project]
 #[derive(Debug)]
 pub struct P2PWire {
-    #[pin]
-    inner: TcpWire,
-    writer_queue: VecDeque<Bytes>,
+    reader: SplitStream<TcpWire>,
+    writer: WireWriter,
     snappy_decoder: snap::raw::Decoder,
-    snappy_encoder: snap::raw::Encoder,
-    established_on: tokio::time::Instant,
+    capabilities: SharedCapabilities,
 }
 
 unsafe impl Send for P2PWire {}
 
+/// Write half of the `P2PWire`, it is shared so messages can be written to the peer from other
+/// tasks (eg. buy txs are written to all peers straight from the task which decoded the trigger),
+/// without waiting for the peer's own task to be scheduled.
+/// Whoever holds the lock is the only one flushing the inner sink.
+#[derive(Debug, Clone)]
+pub struct WireWriter(Arc<Mutex<WireWriterInner>>);
+
+#[derive(Debug)]
+pub struct WireWriterInner {
+    inner: SplitSink<TcpWire, Bytes>,
+    writer_queue: VecDeque<Bytes>,
+    snappy_encoder: snap::raw::Encoder,
+    capabilities: SharedCapabilities,
+}
+
+unsafe impl Send for WireWriterInner {}
+
 /*
 * These are the facts about the "system" we are building:
 * Only P2P messages we care for are:
@@ -64,20 +83,43 @@ unsafe impl Send for P2PWire {}
 * The `P2PWire` takes care of  messages in way described above (reacts to Pings/Disconnects, and
 * filters out all other P2P messages). If the message is not P2P and is valid ETH message it is
 * passed "forward".
+* Messages of other shared capabilities (`bsc`) are handed to their own handler right here, they
+* never reach the peer's task.
 *
 * */
 
 impl P2PWire {
-    pub fn new(rlpx_wire: TcpWire) -> Self {
+    pub fn new(rlpx_wire: TcpWire, capabilities: SharedCapabilities) -> Self {
+        let (writer, reader) = rlpx_wire.split();
         Self {
-            inner: rlpx_wire,
-            established_on: tokio::time::Instant::now(),
-            writer_queue: VecDeque::with_capacity(MAX_WRITER_QUEUE_SIZE + 1),
+            reader,
+            writer: WireWriter(Arc::new(Mutex::new(WireWriterInner {
+                inner: writer,
+                writer_queue: VecDeque::with_capacity(MAX_WRITER_QUEUE_SIZE + 1),
+                snappy_encoder: snap::raw::Encoder::new(),
+                capabilities,
+            }))),
             snappy_decoder: snap::raw::Decoder::default(),
-            snappy_encoder: snap::raw::Encoder::new(),
+            capabilities,
         }
     }
 
+    pub fn writer(&self) -> WireWriter {
+        self.writer.clone()
+    }
+
+    pub async fn send(&self, msg: EthMessage) -> Result<(), P2PError> {
+        self.writer.send(msg).await
+    }
+
+    pub async fn disconnect(&self, reason: DisconnectReason) -> Result<(), P2PError> {
+        self.writer.disconnect(reason).await
+    }
+
+    pub async fn send_bsc(&self, msg: BscMessage) -> Result<(), P2PError> {
+        self.writer.send_bsc(msg).await
+    }
+
     fn handle_p2p_msg(
         &mut self,
         msg: P2pWireMessage,
@@ -93,27 +135,27 @@ impl P2PWire {
                 DisconnectReason::decode(&mut &msg.data[..])?,
             )),
             P2PMessageID::Ping => {
-                let no_need_to_send_ping_if_there_are_messages_queued =
-                    !self.writer_queue.is_empty();
-                if no_need_to_send_ping_if_there_are_messages_queued {
+                // writer is locked while some other task is writing, we can't await the lock
+                // while polling, so the Pong is queued from its own task
+                let mut writer = match self.writer.0.try_lock() {
+                    Ok(writer) => writer,
+                    Err(_) => {
+                        let writer = self.writer.clone();
+                        tokio::spawn(async move { writer.pong().await });
+                        return Ok(());
+                    }
+                };
+
+                if !writer.queue_pong() {
                     return Ok(());
                 }
 
-                let mut buf = BytesMut::new();
-                P2PMessage::Pong.encode(&mut buf);
-
-                self.writer_queue.push_back(buf.freeze());
-
                 // Flushes (writes) sink (maybe writes our Pong message)
                 // To explain "maybe writes" our Pong message:
-                // If inner sink is busy our Ping message won't be written at this time
-                // But this is ok, it just means that we were just "now" in process of sending
-                // "some" message to a peer. And as already mentioned we Really don't have to reply
-                // to Pings with Pong, we just need to keep connection alive by sending proper
-                // p2p/eth msgs.
-                // This is why it is ok to use poll_flush_unpin here and not poll again, because
-                // "we don't care"
-                let _ = self.poll_flush_unpin(cx);
+                // If inner sink is busy our Pong message won't be written at this time, but it
+                // stays queued and is written with the next flush of whoever writes to the peer.
+                // This is why it is ok to use poll_flush_unpin here and not poll again.
+                let _ = writer.poll_flush_unpin(cx);
                 Ok(())
             }
         }
@@ -129,29 +171,31 @@ impl Stream for P2PWire {
     ) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
 
-        // in case buy is in progress we don't want to read any messages
-        // till we are done with buying
-        if is_buy_in_progress() {
-            return Poll::Pending;
-        }
-
-        while let Poll::Ready(bytes_r) = this.inner.poll_next_unpin(cx) {
+        while let Poll::Ready(bytes_r) = this.reader.poll_next_unpin(cx) {
             let bytes = match bytes_r {
                 None => return Poll::Ready(None),
                 Some(Err(_)) => return Poll::Ready(Some(Err(P2PError::RlpxError))),
                 Some(Ok(bytes)) => bytes,
             };
-            let mut msg = P2pWireMessage::new(bytes)?;
-            if !P2pWireMessage::message_is_of_interest(msg.id) {
+            let mut msg = P2pWireMessage::new(bytes, &this.capabilities)?;
+            if !msg.message_is_of_interest() {
                 continue;
             }
 
-            if msg.kind == MessageKind::P2P {
-                msg.snappy_decompress(&mut this.snappy_decoder)?;
-                if let Err(e) = this.handle_p2p_msg(msg, cx) {
-                    return Poll::Ready(Some(Err(e)));
+            match msg.kind {
+                MessageKind::P2P => {
+                    msg.snappy_decompress(&mut this.snappy_decoder)?;
+                    if let Err(e) = this.handle_p2p_msg(msg, cx) {
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    continue;
                 }
-                continue;
+                MessageKind::BSC => {
+                    msg.snappy_decompress(&mut this.snappy_decoder)?;
+                    handle_bsc_message(BscMessage::new(BscProtocol::from(msg.id), msg.data))?;
+                    continue;
+                }
+                MessageKind::ETH => {}
             }
 
             let mut msg = EthMessage::from(msg);
@@ -162,7 +206,87 @@ impl Stream for P2PWire {
     }
 }
 
-impl Sink<EthMessage> for P2PWire {
+impl WireWriter {
+    /// Both are writers of the same connection
+    pub fn is_same(&self, other: &WireWriter) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub async fn send(&self, msg: EthMessage) -> Result<(), P2PError> {
+        self.0.lock().await.send(msg).await
+    }
+
+    /// Drops queued messages, peer is going away anyway
+    pub async fn disconnect(&self, reason: DisconnectReason) -> Result<(), P2PError> {
+        let mut writer = self.0.lock().await;
+        let mut buf = BytesMut::new();
+        P2PMessage::Disconnect(reason).encode(&mut buf);
+
+        writer.writer_queue.clear();
+        writer.writer_queue.push_back(buf.freeze());
+        SinkExt::<EthMessage>::flush(&mut *writer).await
+    }
+
+    async fn pong(&self) -> Result<(), P2PError> {
+        let mut writer = self.0.lock().await;
+        if !writer.queue_pong() {
+            return Ok(());
+        }
+        SinkExt::<EthMessage>::flush(&mut *writer).await
+    }
+
+    pub async fn send_bsc(&self, msg: BscMessage) -> Result<(), P2PError> {
+        let mut writer = self.0.lock().await;
+        let bsc_offset = writer
+            .capabilities
+            .bsc_offset
+            .ok_or(P2PError::CapabilityNotShared)?;
+
+        let compressed = writer.compress(msg.id as u8 + bsc_offset, &msg.data)?;
+        writer.writer_queue.push_back(compressed);
+        SinkExt::<EthMessage>::flush(&mut *writer).await
+    }
+}
+
+impl WireWriterInner {
+    /// Pong is queued only if there is nothing else queued, any message keeps connection alive
+    fn queue_pong(&mut self) -> bool {
+        if !self.writer_queue.is_empty() {
+            return false;
+        }
+
+        let mut buf = BytesMut::new();
+        P2PMessage::Pong.encode(&mut buf);
+        self.writer_queue.push_back(buf.freeze());
+        true
+    }
+
+    /// Message id followed by snappy compressed rlp
+    fn compress(&mut self, id: u8, rlp: &[u8]) -> Result<Bytes, P2PError> {
+        let mut compressed = BytesMut::zeroed(1 + snap::raw::max_compress_len(rlp.len()));
+        let compressed_size = self
+            .snappy_encoder
+            .compress(rlp, &mut compressed[1..])
+            .map_err(|_err| P2PError::SnappyCompressError)?;
+
+        compressed[0] = id;
+        compressed.truncate(compressed_size + 1);
+        Ok(compressed.freeze())
+    }
+
+    /// Pre-compressed messages are built with default `eth` offset
+    fn with_eth_offset(&self, data: Bytes) -> Bytes {
+        if self.capabilities.eth_offset == ETH_PROTOCOL_OFFSET || data.is_empty() {
+            return data;
+        }
+
+        let mut data = BytesMut::from(&data[..]);
+        data[0] = data[0] - ETH_PROTOCOL_OFFSET + self.capabilities.eth_offset;
+        data.freeze()
+    }
+}
+
+impl Sink<EthMessage> for WireWriterInner {
     type Error = P2PError;
 
     fn poll_ready(
@@ -216,17 +340,9 @@ impl Sink<EthMessage> for P2PWire {
         if item.is_compressed() {
             // if message is already compressed this is "high-priority" message
             // remove all queued messages and send this one
+            let data = self.with_eth_offset(item.data);
             self.writer_queue.clear();
-            self.writer_queue.push_back(item.data);
-            return Ok(());
-        }
-
-        // note check !item.is_compressed() is not needed, because of lines above
-        // but in case we move code around or change logic, I think it is better to have it here
-        let we_should_not_send_any_unimportant_messages_during_buy =
-            is_buy_in_progress() && !item.is_compressed();
-
-        if we_should_not_send_any_unimportant_messages_during_buy {
+            self.writer_queue.push_back(data);
             return Ok(());
         }
 
@@ -234,16 +350,9 @@ impl Sink<EthMessage> for P2PWire {
             return Err(P2PError::TooManyMessagesQueued);
         }
 
-        let mut compressed = BytesMut::zeroed(1 + snap::raw::max_compress_len(item.data.len()));
-        let compressed_size = self
-            .snappy_encoder
-            .compress(&item.data, &mut compressed[1..])
-            .map_err(|_err| P2PError::SnappyCompressError)?;
-
-        compressed[0] = item.id as u8 + ETH_PROTOCOL_OFFSET;
-        compressed.truncate(compressed_size + 1);
-
-        self.writer_queue.push_back(compressed.freeze());
+        let id = self.capabilities.eth_message_id(item.id);
+        let compressed = self.compress(id, &item.data)?;
+        self.writer_queue.push_back(compressed);
         Ok(())
     }
 
@@ -251,15 +360,15 @@ impl Sink<EthMessage> for P2PWire {
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        let mut this = self.project();
+        let this = self.get_mut();
         // while there are messages in the queue and inner sink is able to send them
         // send the one by one
         loop {
-            match ready!(this.inner.as_mut().poll_flush(cx)) {
+            match ready!(this.inner.poll_flush_unpin(cx)) {
                 Err(_) => return Poll::Ready(Err(P2PError::RlpxError)),
                 Ok(()) => {
                     if let Some(message) = this.writer_queue.pop_front() {
-                        if this.inner.as_mut().start_send(message).is_err() {
+                        if this.inner.start_send_unpin(message).is_err() {
                             return Poll::Ready(Err(P2PError::RlpxError));
                         }
                     } else {
@@ -2,30 +2,38 @@ use bytes::{Buf, Bytes, BytesMut};
 use derive_more::Display;
 use open_fastrlp::{Decodable, DecodeError};
 
-use crate::eth::types::protocol::{ETH_PROTOCOL_OFFSET, MAX_ETH_PROTOCOL_LEN};
+use crate::eth::types::protocol::EthProtocol;
+
+use super::protocol::SharedCapabilities;
 
 // when we receive message data, the first byte will be the message id
 // and the rest will be actual data
 const POSITION_OF_MSG_ID_IN_BYTE_BUFFER: usize = 1;
-const MAX_SUPPORTED_MESSAGE_ID: u8 = ETH_PROTOCOL_OFFSET + MAX_ETH_PROTOCOL_LEN;
 
-#[derive(Debug, Display, Clone, Eq, PartialEq)]
+#[derive(Debug, Display, Clone, Copy, Eq, PartialEq)]
 pub enum MessageKind {
     P2P,
     ETH,
+    BSC,
 }
 
 #[derive(Debug, Clone)]
 pub struct P2pWireMessage {
     pub kind: MessageKind,
+    /// Id within the message's capability (offset of the capability is subtracted)
     pub id: u8,
     pub data: Bytes,
 }
 
 impl P2pWireMessage {
-    pub fn new(mut data: BytesMut) -> Result<P2pWireMessage, DecodeError> {
+    pub fn new(
+        mut data: BytesMut,
+        capabilities: &SharedCapabilities,
+    ) -> Result<P2pWireMessage, DecodeError> {
         let id = Self::decode_id(&mut &data[..])?;
-        let kind = Self::decode_kind(id)?;
+        let (kind, id) = capabilities
+            .resolve(id)
+            .ok_or(DecodeError::Custom("Decoded message id out of bounds"))?;
 
         // after we decoded id, the byte buffer has to move forwards for 1
         // because id was decoded, and we'll have to decode the rest of the message
@@ -37,22 +45,9 @@ impl P2pWireMessage {
         })
     }
 
-    fn decode_kind(id: u8) -> Result<MessageKind, DecodeError> {
-        match id {
-            0x0..=0x03 => Ok(MessageKind::P2P),
-            ETH_PROTOCOL_OFFSET..=MAX_SUPPORTED_MESSAGE_ID => Ok(MessageKind::ETH),
-            _ => Err(DecodeError::Custom("Decoded message id out of bounds")),
-        }
-    }
-
     fn decode_id(data: &mut &[u8]) -> Result<u8, DecodeError> {
-        let message_id = u8::decode(&mut &data[..POSITION_OF_MSG_ID_IN_BYTE_BUFFER])
-            .map_err(|_| DecodeError::Custom("Invalid message id"))?;
-
-        match message_id {
-            0x0..=MAX_SUPPORTED_MESSAGE_ID => Ok(message_id),
-            _ => Err(DecodeError::Custom("Decoded message id out of bounds")),
-        }
+        u8::decode(&mut &data[..POSITION_OF_MSG_ID_IN_BYTE_BUFFER])
+            .map_err(|_| DecodeError::Custom("Invalid message id"))
     }
 
     pub fn snappy_decompress(
@@ -60,11 +55,8 @@ impl P2pWireMessage {
         snappy_decoder: &mut snap::raw::Decoder,
     ) -> Result<(), DecodeError> {
         // we skip decompressing p2p messages, except Disconnect
-        match self.id {
-            0x00 => return Ok(()),
-            0x02 => return Ok(()),
-            0x03 => return Ok(()),
-            _ => {}
+        if self.kind == MessageKind::P2P && self.id != 0x01 {
+            return Ok(());
         }
 
         let decompressed_len = snap::raw::decompress_len(&self.data)
@@ -80,16 +72,26 @@ impl P2pWireMessage {
         Ok(())
     }
 
-    pub fn message_is_of_interest(msg_id: u8) -> bool {
-        match msg_id {
-            1 => true,  // P2P/Disconnect
-            2 => true,  // P2P/Ping
-            16 => true, // ETH/Status
-            27 => true, // ETH/UpgradeStatus
-            18 => true, // ETH/Transactions
-            26 => true, // ETH/PooledTransactions
-            24 => true, // ETH/NewPoolTransactionHashes
-            _ => false,
+    pub fn message_is_of_interest(&self) -> bool {
+        match self.kind {
+            MessageKind::P2P => matches!(self.id, 0x01 | 0x02), // Disconnect, Ping
+            MessageKind::BSC => true,
+            MessageKind::ETH => matches!(
+                EthProtocol::from(self.id),
+                EthProtocol::StatusMsg
+                    | EthProtocol::NewBlockHashesMsg
+                    | EthProtocol::TransactionsMsg
+                    | EthProtocol::GetBlockHeadersMsg
+                    | EthProtocol::GetBlockBodiesMsg
+                    | EthProtocol::NewBlockMsg
+                    | EthProtocol::NewPooledTransactionHashesMsg
+                    | EthProtocol::GetPooledTransactionsMsg
+                    | EthProtocol::PooledTransactionsMsg
+                    | EthProtocol::UpgradeStatusMsg
+                    | EthProtocol::GetNodeDataMsg
+                    | EthProtocol::GetReceiptsMsg
+                    | EthProtocol::BlockRangeUpdateMsg
+            ),
         }
     }
 }
@@ -2,33 +2,39 @@ use derive_more::Display;
 use ethers::types::U256;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
-use std::time::Duration;
 use tokio::select;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{mpsc, oneshot, Mutex};
 use tokio::time::interval;
 
 use color_print::cprintln;
-use futures::{SinkExt, StreamExt};
+use futures::StreamExt;
 
 use open_fastrlp::Decodable;
 use tracing::error;
 
 use super::errors::P2PError;
+use super::fan_out::{self, fan_out_txs, register_peer_writer, unregister_peer_writer};
 use super::peer_info::PeerInfo;
-use super::protocol::ProtocolVersion;
+use super::protocol::{ProtocolVersion, SharedCapabilities};
+use super::tx_latency::peers_by_score;
+use super::DisconnectReason;
+use crate::blockchain::head_tracker;
+use crate::bsc::protocol::BscMessage;
 use crate::cli::Cli;
 use crate::eth::eth_message::EthMessage;
 use crate::eth::msg_handler::EthMessageHandler;
-use crate::eth::status_message::{StatusMessage, UpgradeStatusMessage};
+use crate::eth::status_message::{StatusMessage, StatusMessage69, UpgradeStatusMessage};
+use crate::eth::transactions::confirmations::{self, OurTxKind};
 use crate::eth::transactions::decoder::BuyTokenInfo;
+use crate::eth::transactions::our_pool::PreparedTxs;
 use crate::eth::types::protocol::EthProtocol;
 use crate::google_sheets::LogToSheets;
 use crate::p2p::p2p_wire::P2PWire;
 use crate::rlpx::TcpWire;
-use crate::server::peers::{
-    blacklist_peer, check_if_already_connected_to_peer, PEERS, PEERS_BY_IP,
-};
-use crate::token::tokens_to_buy::{mark_token_as_bought, remove_all_tokens_to_buy};
+use crate::server::peer_slots::{release_peer_slot, take_peer_slot};
+use crate::server::peers::{blacklist_peer, check_if_already_connected_to_peer, PEERS_BY_IP};
+use crate::token::lifecycle::{set_token_state, TokenState};
+use crate::token::tokens_to_buy::{mark_token_as_bought, prepare_buy_txs_for_pending_tokens};
 use crate::types::hash::H512;
 use crate::{eth, google_sheets, mev};
 
@@ -39,22 +45,9 @@ use crate::wallets::local_wallets::{
     MEV_WALLET,
 };
 
-pub static mut BUY_IS_IN_PROGRESS: bool = false;
-pub static mut SELL_IS_IN_PROGRESS: bool = false;
-
-pub fn is_buy_in_progress() -> bool {
-    unsafe { BUY_IS_IN_PROGRESS }
-}
-
-pub fn is_sell_in_progress() -> bool {
-    unsafe { SELL_IS_IN_PROGRESS }
-}
-
-pub fn is_buy_or_sell_in_progress() -> bool {
-    is_buy_in_progress() || is_sell_in_progress()
-}
-
-const BLOCK_DURATION_IN_MILLIS: u64 = 3000;
+/// All sell txs are signed by the same sell wallet, so tokens are sold one after another
+/// (sell nonce is bumped manually between sells), while other tokens are still watched and bought
+static SELL_LOCK: Mutex<()> = Mutex::const_new(());
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Display, Serialize, Deserialize)]
 pub enum PeerType {
@@ -68,13 +61,12 @@ pub struct Peer {
     pub(crate) node_record: NodeRecord,
     pub(crate) info: String,
     pub(crate) peer_type: PeerType,
-    pub(crate) td: u64,
+    pub(crate) td: u128,
 
     pub(super) connection: P2PWire,
 
     pub(super) protocol_version: ProtocolVersion,
-
-    tx_sender: broadcast::Sender<EthMessage>,
+    capabilities: SharedCapabilities,
 
     cli: Cli,
 }
@@ -83,22 +75,21 @@ impl Peer {
     pub fn new(
         enode: NodeRecord,
         id: H512,
-        protocol: usize,
+        capabilities: SharedCapabilities,
         info: String,
         connection: TcpWire,
         peer_type: PeerType,
         cli: Cli,
-        tx_sender: broadcast::Sender<EthMessage>,
     ) -> Self {
         Self {
             id,
             cli,
-            connection: P2PWire::new(connection),
+            connection: P2PWire::new(connection, capabilities),
             info,
             peer_type,
-            tx_sender,
             node_record: enode,
-            protocol_version: ProtocolVersion::from(protocol),
+            protocol_version: capabilities.eth_version,
+            capabilities,
             td: 0,
         }
     }
@@ -123,73 +114,59 @@ impl Peer {
         }
         check_if_already_connected_to_peer(&self.node_record)?;
 
-        PEERS.insert(self.node_record.id, PeerInfo::from(self as &Peer));
+        let info = PeerInfo::from(self as &Peer);
+        let (session, evicted) = match take_peer_slot(self.node_record.id, info) {
+            Ok(slot) => slot,
+            Err(e) => {
+                let reason = match e {
+                    P2PError::AlreadyConnected => DisconnectReason::AlreadyConnected,
+                    _ => DisconnectReason::TooManyPeers,
+                };
+                let _ = self.connection.disconnect(reason).await;
+                return Err(e);
+            }
+        };
         PEERS_BY_IP.insert(self.node_record.ip.clone());
+        let writer = self.connection.writer();
+        register_peer_writer(self.node_record.id, writer.clone(), self.protocol_version);
 
+        let result = self.run_session(evicted).await;
+        unregister_peer_writer(&self.node_record.id, &writer);
+        release_peer_slot(&self.node_record.id, session);
+        result
+    }
+
+    async fn run_session(
+        &mut self,
+        mut evicted: oneshot::Receiver<DisconnectReason>,
+    ) -> Result<(), P2PError> {
         let (ping_send, mut ping_recv) = tokio::sync::mpsc::channel(1);
         Self::start_pinger(ping_send);
 
-        let mut tx_receiver = self.tx_sender.subscribe();
-
         loop {
             select! {
                 biased;
-                tx = tx_receiver.recv() => {
-                    if let Ok(tx) = tx {
-                        self.connection.send(tx).await?;
-                    }
+                reason = &mut evicted => {
+                    let reason = reason.unwrap_or(DisconnectReason::TooManyPeers);
+                    let _ = self.connection.disconnect(reason).await;
+                    return Err(P2PError::TooManyPeers);
                 },
-                msg = self.connection.next(), if !is_buy_in_progress() => {
+                msg = self.connection.next() => {
                     let msg = msg.ok_or(P2PError::NoMessage)??;
-                    if let Ok(handler_resp) = eth::msg_handler::handle_eth_message(msg, self.protocol_version) {
+                    if let Ok(handler_resp) = eth::msg_handler::handle_eth_message(msg, self.protocol_version, &self.node_record.id) {
                         match handler_resp {
                             EthMessageHandler::None => {},
                             EthMessageHandler::Response(msg) => {
                                 self.connection.send(msg).await?;
                             }
-                            EthMessageHandler::Buy(mut buy_info) => {
-                               let (buy_txs, mev_buy_tx) = buy_info.token.get_buy_txs(buy_info.gas_price);
-                               let buy_txs = match buy_txs {
-                                                Some(buy_txs) => buy_txs,
-                                                None => {
-                                                    println!("LIQ has gwei that we haven't prepared txs for, preparing now...");
-                                                    let tx = buy_info
-                                                             .token
-                                                             .prepare_buy_txs_for_gas_price(buy_info.gas_price)
-                                                             .await;
-                                                    tx
-                                                    }
-                                            };
-
-                                let _ = self.tx_sender.send(buy_txs);
-                                // we sleep so that buy txs are sent before we continue with the rest of the code
-                                tokio::time::sleep(Duration::from_millis(100)).await;
-                                let mev_buy_tx = match mev_buy_tx {
-                                                Some(mev_buy_tx) => mev_buy_tx,
-                                                None => {
-                                                     let mev_wallet = &mut MEV_WALLET.write().await;
-                                                     let mev_tx = generate_mev_buy_tx(mev_wallet, U256::from(buy_info.gas_price)).await;
-                                                     let mev_tx = hex::encode(&mev_tx);
-                                                     let mev_tx = format!("0x{}", mev_tx);
-                                                     mev_tx
-                                                    }
-                                            };
-                                let _mev_resp = mev::puissant::send_mev(1, 5, &buy_info, mev_buy_tx).await;
-
-                                self.sell(&buy_info).await;
-
-                                if let Err(e) = google_sheets::write_data_to_sheets(
-                                    LogToSheets::new(&self.cli, &self, &buy_info).await,
-                                )
-                                .await
-                                {
-                                    error!("Failed to write to sheets: {}", e);
-                                }
+                            EthMessageHandler::Buy(buy_info) => {
+                                // buy runs in the background, so this peer keeps reading while txs are written
+                                Self::spawn_buy(self.cli.clone(), PeerInfo::from(&*self), buy_info);
                             }
                         }
                     }
                 },
-                ping = ping_recv.recv(), if !is_buy_in_progress() => {
+                ping = ping_recv.recv() => {
                     if ping.is_some() {
                         self.connection.send(EthMessage::new_devp2p_ping_message()).await?;
                     }
@@ -199,6 +176,11 @@ impl Peer {
     }
 
     async fn handshake(&mut self) -> Result<(), P2PError> {
+        // bsc handshake runs next to the eth one, peer's answer is checked by the wire
+        if self.capabilities.bsc_offset.is_some() {
+            self.connection.send_bsc(BscMessage::cap()).await?;
+        }
+
         let msg = self.connection.next().await.ok_or(P2PError::NoMessage)??;
 
         if msg.id != EthProtocol::StatusMsg {
@@ -206,21 +188,48 @@ impl Peer {
             return Err(P2PError::ExpectedStatusMessage);
         }
 
-        let status_msg = StatusMessage::decode(&mut &msg.data[..])?;
+        if self.protocol_version >= ProtocolVersion::Eth69 {
+            self.handle_eth69_status_message(&msg).await?;
+        } else {
+            let status_msg = StatusMessage::decode(&mut &msg.data[..])?;
 
-        if StatusMessage::validate(&status_msg, &self.protocol_version).is_err() {
-            return Err(P2PError::CouldNotValidateStatusMessage);
-        }
+            if StatusMessage::validate(&status_msg, &self.protocol_version).is_err() {
+                return Err(P2PError::CouldNotValidateStatusMessage);
+            }
 
-        self.connection
-            .send(StatusMessage::get(&self.protocol_version))
-            .await?;
+            self.connection
+                .send(StatusMessage::get(&self.protocol_version))
+                .await?;
 
-        self.td = status_msg.total_difficulty;
+            self.td = status_msg.total_difficulty;
+            head_tracker::on_peer_status(
+                self.id,
+                status_msg.total_difficulty,
+                status_msg.blockhash,
+            );
+        }
 
         self.handle_upgrade_status_messages().await
     }
 
+    /// eth/69 peers have no total difficulty, their latest block moves our head instead
+    async fn handle_eth69_status_message(&mut self, msg: &EthMessage) -> Result<(), P2PError> {
+        let status_msg = StatusMessage69::decode(&mut &msg.data[..])?;
+
+        if StatusMessage69::validate(&status_msg).is_err() {
+            return Err(P2PError::CouldNotValidateStatusMessage);
+        }
+
+        self.connection.send(StatusMessage69::get()).await?;
+
+        head_tracker::on_block_hash(
+            self.id,
+            status_msg.latest_block,
+            status_msg.latest_block_hash,
+        );
+        Ok(())
+    }
+
     async fn handle_upgrade_status_messages(&mut self) -> Result<(), P2PError> {
         if self.protocol_version == ProtocolVersion::Eth66 {
             return Ok(());
@@ -235,7 +244,76 @@ impl Peer {
         Ok(())
     }
 
-    async fn sell(&self, buy_info: &BuyTokenInfo) {
+    fn spawn_buy(cli: Cli, peer: PeerInfo, mut buy_info: BuyTokenInfo) {
+        tokio::spawn(async move {
+            let (buy_txs, mev_buy_tx) = buy_info.token.get_buy_txs(buy_info.gas_price);
+            let buy_txs = match buy_txs {
+                Some(buy_txs) => buy_txs,
+                None => {
+                    println!("LIQ has gwei that we haven't prepared txs for, preparing now...");
+                    buy_info
+                        .token
+                        .prepare_buy_txs_for_gas_price(buy_info.gas_price)
+                        .await
+                }
+            };
+
+            // peers which deliver txs first are likely closest to validators
+            let reports = fan_out_txs(&buy_txs, &peers_by_score()).await;
+            mark_token_as_bought(buy_info.token.buy_token_address);
+            println!("Buy txs {}", fan_out::summarize(&reports));
+            confirmations::track_tx_message(
+                OurTxKind::Buy,
+                buy_info.token.buy_token_address,
+                Some(buy_info.hash),
+                &buy_txs.message,
+            );
+
+            // sell runs in its own task as well
+            Self::spawn_after_buy(cli, peer, buy_info, mev_buy_tx);
+        });
+    }
+
+    fn spawn_after_buy(
+        cli: Cli,
+        peer: PeerInfo,
+        buy_info: BuyTokenInfo,
+        mev_buy_tx: Option<String>,
+    ) {
+        tokio::spawn(async move {
+            let mev_buy_tx = match mev_buy_tx {
+                Some(mev_buy_tx) => mev_buy_tx,
+                None => {
+                    let mev_wallet = &mut MEV_WALLET.write().await;
+                    let mev_tx =
+                        generate_mev_buy_tx(mev_wallet, U256::from(buy_info.gas_price)).await;
+                    format!("0x{}", hex::encode(&mev_tx))
+                }
+            };
+            let token = buy_info.token.buy_token_address;
+            if let Some(mev_config) = &buy_info.token.mev_config {
+                confirmations::track_hex_tx(
+                    OurTxKind::MevBid,
+                    token,
+                    Some(buy_info.hash),
+                    &mev_config.bid_tx,
+                );
+            }
+            confirmations::track_hex_tx(OurTxKind::MevBuy, token, Some(buy_info.hash), &mev_buy_tx);
+            let _mev_resp = mev::puissant::send_mev(1, 5, &buy_info, mev_buy_tx).await;
+
+            Self::sell(&buy_info).await;
+
+            if let Err(e) =
+                google_sheets::write_data_to_sheets(LogToSheets::new(&cli, &peer, &buy_info).await)
+                    .await
+            {
+                error!("Failed to write to sheets: {}", e);
+            }
+        });
+    }
+
+    async fn sell(buy_info: &BuyTokenInfo) {
         //async fn sell(&self, buy_info: &BuyTokenInfo, mev_resp: anyhow::Result<ApiResponse>) {
         // let mev_id = match mev_resp {
         //     Ok(r) => {
@@ -252,11 +330,6 @@ impl Peer {
         //     }
         // };
         //TODO: handle transfer instead of selling scenario
-        mark_token_as_bought(buy_info.token.buy_token_address);
-        unsafe {
-            BUY_IS_IN_PROGRESS = false;
-            SELL_IS_IN_PROGRESS = true;
-        }
         cprintln!(
             "<b><green>[{}]Bought token: {}</></>\nliq TX: {} ",
             buy_info.time.format("%Y-%m-%d %H:%M:%S:%f"),
@@ -265,15 +338,25 @@ impl Peer {
         );
 
         let token = &buy_info.token;
+        let _sell_guard = SELL_LOCK.lock().await;
+        if let Err(e) = set_token_state(token.buy_token_address, TokenState::Selling) {
+            println!("{}", e);
+        }
         for i in 0..token.sell_config.sell_count {
             //this is because for the first sell the nonce is up to date with blockchain
             //only after first sell we need to "update it manually"
             let increment_sell_nonce_after_first_sell = i > 0;
-            let sell_tx = EthMessage::new_tx_message(
+            let sell_tx = PreparedTxs::new(EthMessage::new_tx_message(
                 generate_and_rlp_encode_sell_tx(increment_sell_nonce_after_first_sell).await,
-            );
+            ));
 
-            let _ = self.tx_sender.send(sell_tx);
+            let _ = fan_out_txs(&sell_tx, &[]).await;
+            confirmations::track_tx_message(
+                OurTxKind::Sell,
+                token.buy_token_address,
+                Some(buy_info.hash),
+                &sell_tx.message,
+            );
             cprintln!(
                 "<blue>[{}/{}]Selling token: {:#x}</>",
                 i + 1,
@@ -281,9 +364,8 @@ impl Peer {
                 token.buy_token_address
             );
 
-            // wait for sell tx to be mined before sending the next one
-            // we also wait bit more before sending new tx since our code is super fast 😅
-            tokio::time::sleep(Duration::from_millis(BLOCK_DURATION_IN_MILLIS + 500)).await;
+            // wait for sell tx to be mined (next block is announced) before sending the next one
+            head_tracker::wait_for_blocks(1).await;
         }
 
         cprintln!(
@@ -291,8 +373,8 @@ impl Peer {
             get_bsc_token_url(token.buy_token_address)
         );
 
-        unsafe {
-            SELL_IS_IN_PROGRESS = false;
+        if let Err(e) = set_token_state(token.buy_token_address, TokenState::Done) {
+            println!("{}", e);
         }
 
         // if let Some(id) = mev_id {
@@ -306,11 +388,12 @@ impl Peer {
         //     }
         // }
 
-        // this will refresh token list with proper nonces
-        // sleep for a while to make sure public nodes have latest nonces
-        tokio::time::sleep(Duration::from_millis(3 * BLOCK_DURATION_IN_MILLIS)).await;
+        // wait for a few blocks to make sure public nodes have latest nonces
+        head_tracker::wait_for_blocks(3).await;
         update_nonces_for_local_wallets().await;
-        remove_all_tokens_to_buy();
+        // buy txs of pending tokens were signed with nonces we just used, they are signed again
+        // while sell lock is held, so no sell changes nonces in the meantime
+        prepare_buy_txs_for_pending_tokens().await;
     }
 
     pub(crate) fn start_pinger(ping_sender: mpsc::Sender<()>) {
@@ -320,10 +403,6 @@ impl Peer {
             ));
 
             while let Some(_) = stream.next().await {
-                if is_buy_in_progress() {
-                    continue;
-                }
-
                 if let Err(_) = ping_sender.send(()).await {
                     return;
                 }
@@ -2,9 +2,10 @@ use std::fmt::{Display, Formatter};
 
 use serde::{Deserialize, Serialize};
 
+use crate::server::peer_slots::network_of;
 use crate::types::hash::H512;
 
-use super::{peer::PeerType, Peer};
+use super::{peer::PeerType, tx_latency::TxLatencyScore, Peer};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PeerInfo {
@@ -12,8 +13,17 @@ pub struct PeerInfo {
     pub info: String,
     pub enode: String,
     pub ip: String,
+    /// Network prefix of the ip, peers from the same one are redundant
+    pub network: String,
     pub peer_type: PeerType,
     pub protocol_version: usize,
+    pub td: u128,
+    /// Unix timestamps (secs), `last_tx_at` is 0 until the peer delivers a tx
+    pub connected_at: i64,
+    #[serde(default)]
+    pub last_tx_at: i64,
+    #[serde(default)]
+    pub tx_latency: TxLatencyScore,
 }
 
 impl From<&Peer> for PeerInfo {
@@ -23,8 +33,13 @@ impl From<&Peer> for PeerInfo {
             info: p.info.clone(),
             enode: p.node_record.str.clone(),
             ip: p.node_record.ip.clone(),
+            network: network_of(&p.node_record.address),
             peer_type: p.peer_type,
             protocol_version: p.protocol_version as usize,
+            td: p.td,
+            connected_at: chrono::Utc::now().timestamp(),
+            last_tx_at: 0,
+            tx_latency: TxLatencyScore::default(),
         }
     }
 }
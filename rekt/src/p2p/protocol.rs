@@ -4,6 +4,11 @@ use open_fastrlp::{RlpDecodable, RlpEncodable};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::bsc::protocol::{BSC_PROTOCOL, BSC_PROTOCOL_LENGTH, BSC_PROTOCOL_VERSION};
+use crate::eth::types::protocol::{EthProtocol, ETH_PROTOCOL_OFFSET, MAX_ETH_PROTOCOL_LEN};
+
+use super::p2p_wire_message::MessageKind;
+
 const ETH_PROTOCOL: &str = "eth";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, ToPrimitive, PartialOrd)]
@@ -14,6 +19,7 @@ pub enum ProtocolVersion {
     #[default]
     Eth67 = 67,
     Eth68 = 68,
+    Eth69 = 69,
 }
 
 #[derive(Error, Debug, Copy, Clone)]
@@ -32,6 +38,7 @@ impl From<usize> for ProtocolVersion {
             66 => Self::Eth66,
             67 => Self::Eth67,
             68 => Self::Eth68,
+            69 => Self::Eth69,
             _ => Self::Unknown,
         }
     }
@@ -50,28 +57,132 @@ impl Protocol {
 
     pub fn get_our_protocols() -> Vec<Protocol> {
         vec![
+            Protocol::new(ETH_PROTOCOL.to_string(), ProtocolVersion::Eth69 as usize),
             Protocol::new(ETH_PROTOCOL.to_string(), ProtocolVersion::Eth68 as usize),
             Protocol::new(ETH_PROTOCOL.to_string(), ProtocolVersion::Eth67 as usize),
             Protocol::new(ETH_PROTOCOL.to_string(), ProtocolVersion::Eth66 as usize),
             Protocol::new(ETH_PROTOCOL.to_string(), ProtocolVersion::Eth65 as usize),
+            Protocol::new(BSC_PROTOCOL.to_string(), BSC_PROTOCOL_VERSION),
         ]
     }
 
-    pub fn match_protocols(peer_protocols: &mut [Protocol]) -> Option<Protocol> {
-        peer_protocols.sort_unstable_by(|fst, snd| snd.version.cmp(&fst.version));
-        let proto = peer_protocols.first()?;
-        if proto.name != ETH_PROTOCOL {
-            return None;
+    /// Capabilities shared with the peer, `None` if the peer doesn't speak any `eth` we do
+    pub fn match_protocols(peer_protocols: &[Protocol]) -> Option<SharedCapabilities> {
+        let ours = Self::get_our_protocols();
+        let shared = |name: &str| {
+            peer_protocols
+                .iter()
+                .filter(|p| p.name == name && ours.contains(p))
+                .map(|p| p.version)
+                .max()
+        };
+
+        let eth_version = shared(ETH_PROTOCOL)?;
+        let bsc_version = shared(BSC_PROTOCOL);
+
+        // shared capabilities take message ids after p2p ones, in alphabetical order
+        let mut offset = ETH_PROTOCOL_OFFSET;
+        let bsc_offset = bsc_version.map(|_| {
+            let bsc_offset = offset;
+            offset += BSC_PROTOCOL_LENGTH;
+            bsc_offset
+        });
+
+        Some(SharedCapabilities {
+            eth_version: ProtocolVersion::from(eth_version),
+            eth_offset: offset,
+            bsc_offset,
+        })
+    }
+}
+
+/*
+* After the hello exchange every shared capability gets its own range of message ids.
+* Ranges start right after p2p messages (0x10), capabilities are ordered by name and then each
+* takes as many ids as its protocol has messages, eg. with `bsc/1` shared:
+* bsc: 0x10..=0x11, eth: 0x12..=0x22
+* Without `bsc` the `eth` range starts at 0x10, which is what pre-built (pre-compressed) messages
+* assume, writer fixes message id of those for peers with different `eth` offset.
+* */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedCapabilities {
+    pub eth_version: ProtocolVersion,
+    pub eth_offset: u8,
+    pub bsc_offset: Option<u8>,
+}
+
+impl Default for SharedCapabilities {
+    fn default() -> Self {
+        Self {
+            eth_version: ProtocolVersion::default(),
+            eth_offset: ETH_PROTOCOL_OFFSET,
+            bsc_offset: None,
+        }
+    }
+}
+
+impl SharedCapabilities {
+    /// Kind of the message and its id within its capability
+    pub fn resolve(&self, id: u8) -> Option<(MessageKind, u8)> {
+        if id < ETH_PROTOCOL_OFFSET {
+            return Some((MessageKind::P2P, id));
+        }
+        if let Some(bsc_offset) = self.bsc_offset {
+            if (bsc_offset..bsc_offset + BSC_PROTOCOL_LENGTH).contains(&id) {
+                return Some((MessageKind::BSC, id - bsc_offset));
+            }
+        }
+        if (self.eth_offset..self.eth_offset + MAX_ETH_PROTOCOL_LEN).contains(&id) {
+            return Some((MessageKind::ETH, id - self.eth_offset));
         }
+        None
+    }
 
-        let p = match proto.version {
-            68 => proto.clone(),
-            67 => proto.clone(),
-            66 => proto.clone(),
-            65 => proto.clone(),
-            _ => return None,
-        };
+    pub fn eth_message_id(&self, id: EthProtocol) -> u8 {
+        id as u8 + self.eth_offset
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn protocols(protocols: &[(&str, usize)]) -> Vec<Protocol> {
+        protocols
+            .iter()
+            .map(|(name, version)| Protocol::new(name.to_string(), *version))
+            .collect()
+    }
+
+    #[test]
+    fn offsets_of_shared_capabilities() {
+        // bsc sorts before eth, snap is not ours so it takes no ids
+        let peer = protocols(&[("eth", 67), ("eth", 68), ("snap", 1), ("bsc", 1)]);
+        let caps = Protocol::match_protocols(&peer).unwrap();
+        assert_eq!(caps.eth_version, ProtocolVersion::Eth68);
+        assert_eq!(caps.bsc_offset, Some(0x10));
+        assert_eq!(caps.eth_offset, 0x12);
+        assert_eq!(caps.resolve(0x02), Some((MessageKind::P2P, 0x02)));
+        assert_eq!(caps.resolve(0x11), Some((MessageKind::BSC, 0x01)));
+        assert_eq!(caps.resolve(0x14), Some((MessageKind::ETH, 0x02)));
+        assert_eq!(caps.eth_message_id(EthProtocol::TransactionsMsg), 0x14);
+
+        let peer = protocols(&[("snap", 1), ("eth", 66)]);
+        let caps = Protocol::match_protocols(&peer).unwrap();
+        assert_eq!(caps.eth_version, ProtocolVersion::Eth66);
+        assert_eq!(
+            caps,
+            SharedCapabilities {
+                eth_version: ProtocolVersion::Eth66,
+                ..Default::default()
+            }
+        );
+        assert_eq!(caps.resolve(0x11), Some((MessageKind::ETH, 0x01)));
 
-        Some(p)
+        // bsc alone is not enough
+        assert_eq!(
+            Protocol::match_protocols(&protocols(&[("bsc", 1), ("eth", 64)])),
+            None
+        );
     }
 }
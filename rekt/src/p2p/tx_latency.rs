@@ -0,0 +1,247 @@
+use std::time::{Duration, Instant};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::server::peers::PEERS;
+use crate::types::hash::{H256, H512};
+
+/// Hashes older than this are forgotten, peer delivering tx this late is not "racing" anymore
+const FIRST_SEEN_WINDOW: Duration = Duration::from_secs(10);
+/// Once this many hashes are tracked, hashes out of the window are dropped. If that doesn't free
+/// at least half of them, the next hashes would scan the whole map again, so all of them are.
+const MAX_TRACKED_HASHES: usize = 200_000;
+/// Weight of the newest sample in rolling averages (~last 64 txs of the peer matter)
+const EWMA_WEIGHT: f64 = 1.0 / 64.0;
+
+static FIRST_SEEN: Lazy<DashMap<H256, FirstSeen>> =
+    Lazy::new(|| DashMap::with_capacity(MAX_TRACKED_HASHES));
+
+/*
+* For every tx hash we remember which peer delivered it (as full tx or as announcement) first.
+* When the same hash comes from another peer we know:
+* 1. how far behind the first peer the late one was (the late peer's delay)
+* 2. by how much the first peer beat the rest (first peer's lead, taken from the second delivery)
+*
+* Both are kept as rolling averages per peer in `PeerInfo`, score is lead minus delay, so peers
+* which deliver txs first (and by a wide margin) are on top, and our buy txs are sent to them first.
+* */
+#[derive(Debug, Clone, Copy)]
+struct FirstSeen {
+    peer: H512,
+    at: Instant,
+    lead_recorded: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TxLatencyScore {
+    /// Txs seen from this peer
+    pub seen: u64,
+    /// Txs this peer delivered before anyone else
+    pub first: u64,
+    /// Rolling average of how far behind the first peer this one was (0 when it was first)
+    pub avg_delay_micros: f64,
+    /// Rolling average of how much this peer beat the second one when it was first
+    pub avg_lead_micros: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Seen {
+    First,
+    Late {
+        first_peer: H512,
+        delay: Duration,
+        lead_of_first: bool,
+    },
+    Again,
+}
+
+impl TxLatencyScore {
+    pub fn score(&self) -> f64 {
+        self.avg_lead_micros - self.avg_delay_micros
+    }
+
+    fn on_first(&mut self) {
+        self.seen += 1;
+        self.first += 1;
+        self.avg_delay_micros = ewma(self.avg_delay_micros, 0.0);
+    }
+
+    fn on_late(&mut self, delay: Duration) {
+        self.seen += 1;
+        self.avg_delay_micros = ewma(self.avg_delay_micros, delay.as_micros() as f64);
+    }
+
+    fn on_lead(&mut self, lead: Duration) {
+        self.avg_lead_micros = ewma(self.avg_lead_micros, lead.as_micros() as f64);
+    }
+}
+
+fn ewma(avg: f64, sample: f64) -> f64 {
+    avg + EWMA_WEIGHT * (sample - avg)
+}
+
+fn mark_seen(first_seen: &DashMap<H256, FirstSeen>, hash: H256, peer: H512, now: Instant) -> Seen {
+    match first_seen.entry(hash) {
+        Entry::Vacant(entry) => {
+            entry.insert(FirstSeen {
+                peer,
+                at: now,
+                lead_recorded: false,
+            });
+            Seen::First
+        }
+        Entry::Occupied(mut entry) => {
+            let first = entry.get_mut();
+            if first.peer == peer {
+                return Seen::Again;
+            }
+            let lead_of_first = !first.lead_recorded;
+            first.lead_recorded = true;
+            Seen::Late {
+                first_peer: first.peer,
+                delay: now.saturating_duration_since(first.at),
+                lead_of_first,
+            }
+        }
+    }
+}
+
+fn forget_old_hashes(first_seen: &DashMap<H256, FirstSeen>, now: Instant, max_tracked: usize) {
+    if first_seen.len() < max_tracked {
+        return;
+    }
+    first_seen.retain(|_, seen| now.saturating_duration_since(seen.at) < FIRST_SEEN_WINDOW);
+    if first_seen.len() > max_tracked / 2 {
+        first_seen.clear();
+    }
+}
+
+/// Records tx hash delivered by the peer and updates scores of peers which raced for it
+pub fn on_tx_seen(hash: &H256, peer: &H512) {
+    let now = Instant::now();
+    match mark_seen(&FIRST_SEEN, *hash, *peer, now) {
+        Seen::Again => {}
+        Seen::First => {
+            forget_old_hashes(&FIRST_SEEN, now, MAX_TRACKED_HASHES);
+            if let Some(mut info) = PEERS.get_mut(peer) {
+                info.tx_latency.on_first();
+                info.last_tx_at = chrono::Utc::now().timestamp();
+            }
+        }
+        Seen::Late {
+            first_peer,
+            delay,
+            lead_of_first,
+        } => {
+            if let Some(mut info) = PEERS.get_mut(peer) {
+                info.tx_latency.on_late(delay);
+                info.last_tx_at = chrono::Utc::now().timestamp();
+            }
+            if lead_of_first {
+                if let Some(mut info) = PEERS.get_mut(&first_peer) {
+                    info.tx_latency.on_lead(delay);
+                }
+            }
+        }
+    }
+}
+
+/// Ids of peers which delivered at least one tx, best score first
+pub fn peers_by_score() -> Vec<H512> {
+    let mut scores = PEERS
+        .iter()
+        .filter(|p| p.value().tx_latency.seen > 0)
+        .map(|p| (*p.key(), p.value().tx_latency.score()))
+        .collect::<Vec<_>>();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scores.into_iter().map(|(id, _)| id).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn first_peer_gets_the_lead() {
+        let first_seen = DashMap::new();
+        let hash = H256::repeat_byte(1);
+        let (fast, slow, slowest) = (
+            H512::repeat_byte(1),
+            H512::repeat_byte(2),
+            H512::repeat_byte(3),
+        );
+        let start = Instant::now();
+
+        assert_eq!(mark_seen(&first_seen, hash, fast, start), Seen::First);
+        assert_eq!(
+            mark_seen(&first_seen, hash, fast, start + Duration::from_micros(10)),
+            Seen::Again
+        );
+        assert_eq!(
+            mark_seen(&first_seen, hash, slow, start + Duration::from_micros(300)),
+            Seen::Late {
+                first_peer: fast,
+                delay: Duration::from_micros(300),
+                lead_of_first: true
+            }
+        );
+        // lead is taken only from the second delivery
+        assert_eq!(
+            mark_seen(
+                &first_seen,
+                hash,
+                slowest,
+                start + Duration::from_micros(900)
+            ),
+            Seen::Late {
+                first_peer: fast,
+                delay: Duration::from_micros(900),
+                lead_of_first: false
+            }
+        );
+    }
+
+    #[test]
+    fn score_prefers_fast_peers() {
+        let mut fast = TxLatencyScore::default();
+        let mut slow = TxLatencyScore::default();
+        for _ in 0..100 {
+            fast.on_first();
+            fast.on_lead(Duration::from_micros(500));
+            slow.on_late(Duration::from_micros(500));
+        }
+
+        assert_eq!((fast.seen, fast.first), (100, 100));
+        assert_eq!((slow.seen, slow.first), (100, 0));
+        assert!(fast.score() > 0.0);
+        assert!(slow.score() < 0.0);
+        assert!(slow.avg_delay_micros > 350.0 && slow.avg_delay_micros < 500.0);
+    }
+
+    #[test]
+    fn full_map_is_cleared_unless_enough_is_freed() {
+        let first_seen = DashMap::new();
+        let peer = H512::repeat_byte(1);
+        let max_tracked = 1_000;
+        let start = Instant::now();
+        let recent = start + FIRST_SEEN_WINDOW;
+
+        // most hashes still in the window, scanning them on every new one doesn't pay off
+        for i in 0..max_tracked {
+            let at = if i < max_tracked / 4 { start } else { recent };
+            mark_seen(&first_seen, H256::random(), peer, at);
+        }
+        forget_old_hashes(&first_seen, recent, max_tracked);
+        assert!(first_seen.is_empty());
+
+        // enough hashes out of the window, recent ones are kept
+        for i in 0..max_tracked {
+            let at = if i < max_tracked / 2 { start } else { recent };
+            mark_seen(&first_seen, H256::random(), peer, at);
+        }
+        forget_old_hashes(&first_seen, recent, max_tracked);
+        assert_eq!(first_seen.len(), max_tracked / 2);
+    }
+}
@@ -11,6 +11,7 @@ use tokio::sync::RwLock;
 use tokio_stream::StreamExt;
 use url::Url;
 
+use crate::blockchain::chain_spec;
 use crate::wallets::wallet_with_nonce::WalletWithNonce;
 
 const DEFAULT_RETRY_COUNT: u8 = 2;
@@ -28,7 +29,7 @@ pub const PUBLIC_NODE_URLS: [&str; 6] = [
 static PUBLIC_NODES: Lazy<RwLock<Vec<RetryClient<Http>>>> = Lazy::new(|| RwLock::new(Vec::new()));
 
 pub async fn init_connection_to_public_nodes() {
-    for rpc_url in PUBLIC_NODE_URLS.iter() {
+    for rpc_url in chain_spec().public_nodes.iter() {
         let mut public_nodes = PUBLIC_NODES.write().await;
         if let Ok(p) = get_retry_provider(rpc_url) {
             match JsonRpcClient::request::<_, U256>(&p, "eth_blockNumber", ()).await {
@@ -5,16 +5,16 @@ use std::time::Duration;
 use futures::{SinkExt, TryStreamExt};
 use secp256k1::PublicKey;
 use tokio::net::TcpStream;
-use tokio::sync::broadcast::Sender;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::time::timeout;
 use tokio_util::codec::{Decoder, Framed};
 use tracing::error;
 
-use crate::eth::eth_message::EthMessage;
+use crate::discover::node_db::{record_connection_outcome, ConnectionOutcome};
 use crate::p2p::errors::P2PError;
 use crate::p2p::p2p_wire_message::P2pWireMessage;
-use crate::p2p::peer::{is_buy_or_sell_in_progress, PeerType};
+use crate::p2p::peer::PeerType;
+use crate::p2p::protocol::SharedCapabilities;
 use crate::p2p::{self, HelloMessage, Peer, Protocol};
 use crate::p2p::{P2PMessage, P2PMessageID};
 use crate::rlpx::codec::{RLPXMsg, RLPXMsgOut};
@@ -23,13 +23,13 @@ use crate::rlpx::TcpWire;
 use crate::rlpx::{utils::pk2id, Connection};
 use crate::server::connection_task::ConnectionTask;
 use crate::server::errors::ConnectionTaskError;
-use crate::server::peers::{check_if_already_connected_to_peer, PEERS, PEERS_BY_IP};
+use crate::server::peers::{check_if_already_connected_to_peer, PEERS_BY_IP};
+use crate::types::node_record::NodeRecord;
 
 pub async fn connect_to_node(
     conn_task: ConnectionTask,
     tx: UnboundedSender<ConnectionTaskError>,
     conn_permit: Arc<tokio::sync::Semaphore>,
-    tx_sender: Sender<EthMessage>,
 ) {
     let permit = conn_permit.clone().acquire_owned().await.unwrap();
     tokio::spawn(async move {
@@ -38,10 +38,9 @@ pub async fn connect_to_node(
                 match $e {
                     Ok(v) => v,
                     Err(e) => {
-                        let _ = tx.send(ConnectionTaskError::new(
-                            conn_task.next_attempt(),
-                            RLPXSessionError::from(e),
-                        ));
+                        let err = RLPXSessionError::from(e);
+                        record_dial_failure(&conn_task.node, &err);
+                        let _ = tx.send(ConnectionTaskError::new(conn_task.next_attempt(), err));
                         return;
                     }
                 }
@@ -83,15 +82,15 @@ pub async fn connect_to_node(
         let mut transport = rlpx_connection.framed(stream);
         map_err!(handle_auth(&mut transport).await);
 
-        let (hello_msg, protocol_v) =
+        let (hello_msg, capabilities) =
             map_err!(
                 match handle_hello_msg(&conn_task.our_pk, &mut transport).await {
-                    Ok(mut hello_msg) => {
-                        let matched_protocol =
-                            map_err!(Protocol::match_protocols(&mut hello_msg.protocols)
+                    Ok(hello_msg) => {
+                        let capabilities =
+                            map_err!(Protocol::match_protocols(&hello_msg.protocols)
                                 .ok_or(RLPXSessionError::NoMatchingProtocols));
 
-                        Ok((hello_msg, matched_protocol.version))
+                        Ok((hello_msg, capabilities))
                     }
                     Err(e) => Err(e),
                 }
@@ -99,27 +98,19 @@ pub async fn connect_to_node(
 
         //conn attempt succeeded, so we can release the permit
         drop(permit);
+        record_connection_outcome(node.id, ConnectionOutcome::Connected);
 
         let mut p = Peer::new(
             node.clone(),
             hello_msg.id,
-            protocol_v,
+            capabilities,
             hello_msg.client_version,
             TcpWire::new(transport),
             PeerType::Outbound,
             conn_task.server_info.clone(),
-            tx_sender,
         );
 
         let task_result = p.run().await;
-        if is_buy_or_sell_in_progress() {
-            //NOTE: don't disconnect peers immediately to avoid UB (like nil ptr)
-
-            while is_buy_or_sell_in_progress() {
-                tokio::time::sleep(Duration::from_secs(120)).await;
-            }
-        }
-        PEERS.remove(&node.id);
 
         // In case we got already connected to same ip error we do not remove the IP from the set
         // of already connected ips
@@ -128,10 +119,28 @@ pub async fn connect_to_node(
             PEERS_BY_IP.remove(&node.ip);
         }
 
-        map_err!(task_result);
+        // session ended after a successful dial, that is not a failed connection attempt
+        if let Err(e) = task_result {
+            let _ = tx.send(ConnectionTaskError::new(
+                conn_task.next_attempt(),
+                RLPXSessionError::from(e),
+            ));
+        }
     });
 }
 
+/// Dial outcome goes to node db, being already connected to the node is not a failure
+fn record_dial_failure(node: &NodeRecord, err: &RLPXSessionError) {
+    if matches!(
+        err,
+        RLPXSessionError::P2PError(P2PError::AlreadyConnected | P2PError::AlreadyConnectedToSameIp)
+    ) {
+        return;
+    }
+
+    record_connection_outcome(node.id, ConnectionOutcome::Failed(err.to_string()));
+}
+
 async fn handle_auth(
     transport: &mut Framed<TcpStream, Connection>,
 ) -> Result<(), RLPXSessionError> {
@@ -169,7 +178,8 @@ pub(super) async fn handle_hello_msg(
         .ok_or(RLPXError::InvalidMsgData)?;
 
     if let RLPXMsg::Message(rlpx_msg) = rlpx_msg {
-        let msg = P2pWireMessage::new(rlpx_msg)?;
+        // capabilities are not negotiated yet, only p2p messages are expected
+        let msg = P2pWireMessage::new(rlpx_msg, &SharedCapabilities::default())?;
         let p2p_msg = P2PMessage::decode(msg.id, &mut &msg.data[..])?;
 
         match p2p_msg {
@@ -7,32 +7,21 @@ use std::{
 
 use futures::{SinkExt, TryStreamExt};
 use secp256k1::PublicKey;
-use tokio::{
-    net::{TcpSocket, TcpStream},
-    sync::broadcast,
-};
+use tokio::net::{TcpSocket, TcpStream};
 use tokio_util::codec::{Decoder, Framed};
 use tracing::error;
 
 use crate::{
     cli::Cli,
     constants::DEFAULT_PORT,
-    eth::eth_message::EthMessage,
     local_node::LocalNode,
-    p2p::{
-        errors::P2PError,
-        peer::{is_buy_or_sell_in_progress, PeerType},
-        Peer, Protocol,
-    },
+    p2p::{errors::P2PError, peer::PeerType, Peer, Protocol},
     rlpx::{Connection, RLPXError, RLPXMsg, RLPXSessionError, TcpWire},
     server::peers::BLACKLIST_PEERS_BY_IP,
     types::node_record::NodeRecord,
 };
 
-use super::{
-    active_peer_session::handle_hello_msg,
-    peers::{PEERS, PEERS_BY_IP},
-};
+use super::{active_peer_session::handle_hello_msg, peers::PEERS_BY_IP};
 
 pub struct InboundConnections {
     our_private_key: secp256k1::SecretKey,
@@ -40,16 +29,14 @@ pub struct InboundConnections {
     is_paused: AtomicBool,
 
     cli: crate::cli::Cli,
-    tx_sender: tokio::sync::broadcast::Sender<crate::eth::eth_message::EthMessage>,
 }
 
 impl InboundConnections {
-    pub fn new(local_node: LocalNode, cli: Cli, tx_sender: broadcast::Sender<EthMessage>) -> Self {
+    pub fn new(local_node: LocalNode, cli: Cli) -> Self {
         Self {
             our_private_key: local_node.private_key,
             is_paused: AtomicBool::new(false),
             cli,
-            tx_sender,
         }
     }
 
@@ -94,12 +81,10 @@ impl InboundConnections {
             }
 
             let cli = self.cli.clone();
-            let tx_sender = self.tx_sender.clone();
             tokio::spawn(async move {
                 let rlpx_connection = Connection::new_in(our_secret_key);
                 let transport = rlpx_connection.framed(stream);
-                let _ =
-                    new_connection_handler(src, transport, our_secret_key, cli, tx_sender).await;
+                let _ = new_connection_handler(src, transport, our_secret_key, cli).await;
             });
         }
     }
@@ -110,7 +95,6 @@ async fn new_connection_handler(
     mut transport: Framed<TcpStream, Connection>,
     secret_key: secp256k1::SecretKey,
     cli: Cli,
-    tx_sender: broadcast::Sender<EthMessage>,
 ) -> Result<(), RLPXSessionError> {
     let external_node_pub_key = handle_auth(&mut transport).await?;
 
@@ -122,12 +106,12 @@ async fn new_connection_handler(
     );
     let pub_key = PublicKey::from_secret_key(&secp256k1::Secp256k1::new(), &secret_key);
 
-    let (hello_msg, protocol_v) = match handle_hello_msg(&pub_key, &mut transport).await {
-        Ok(mut hello_msg) => {
-            let matched_protocol = Protocol::match_protocols(&mut hello_msg.protocols)
+    let (hello_msg, capabilities) = match handle_hello_msg(&pub_key, &mut transport).await {
+        Ok(hello_msg) => {
+            let capabilities = Protocol::match_protocols(&hello_msg.protocols)
                 .ok_or(RLPXSessionError::NoMatchingProtocols)?;
 
-            (hello_msg, matched_protocol.version)
+            (hello_msg, capabilities)
         }
         Err(e) => {
             return Err(e);
@@ -137,23 +121,14 @@ async fn new_connection_handler(
     let mut p = Peer::new(
         node.clone(),
         hello_msg.id,
-        protocol_v,
+        capabilities,
         hello_msg.client_version,
         TcpWire::new(transport),
         PeerType::Inbound,
         cli,
-        tx_sender,
     );
 
     let task_result = p.run().await;
-    if is_buy_or_sell_in_progress() {
-        //NOTE: don't disconnect peers immediately to avoid UB (like nil ptr)
-
-        while is_buy_or_sell_in_progress() {
-            tokio::time::sleep(Duration::from_secs(120)).await;
-        }
-    }
-    PEERS.remove(&node.id);
 
     // In case we got already connected to same ip error we do not remove the IP from the set
     // of already connected ips
@@ -3,4 +3,5 @@ pub mod connection_task;
 pub mod errors;
 pub mod inbound_connections;
 pub mod outbound_connections;
+pub mod peer_slots;
 pub mod peers;
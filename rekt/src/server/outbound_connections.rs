@@ -4,7 +4,6 @@ use std::time::{Duration, Instant};
 use secp256k1::{PublicKey, SecretKey};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
-use crate::p2p::peer::is_buy_in_progress;
 use crate::rlpx::RLPXSessionError;
 
 use super::active_peer_session::connect_to_node;
@@ -24,7 +23,6 @@ pub struct OutboundConnections {
 
     cli: crate::cli::Cli,
     concurrent_conn_attempts: Arc<tokio::sync::Semaphore>,
-    tx_sender: tokio::sync::broadcast::Sender<crate::eth::eth_message::EthMessage>,
 }
 
 impl OutboundConnections {
@@ -35,7 +33,6 @@ impl OutboundConnections {
         conn_rx: UnboundedReceiver<ConnectionTaskError>,
         conn_tx: UnboundedSender<ConnectionTaskError>,
         cli: crate::cli::Cli,
-        tx_sender: tokio::sync::broadcast::Sender<crate::eth::eth_message::EthMessage>,
     ) -> Self {
         Self {
             nodes,
@@ -44,7 +41,6 @@ impl OutboundConnections {
             conn_rx,
             conn_tx,
             cli,
-            tx_sender,
             concurrent_conn_attempts: Arc::new(tokio::sync::Semaphore::new(256)),
         }
     }
@@ -63,16 +59,11 @@ impl OutboundConnections {
                     task,
                     self.conn_tx.clone(),
                     self.concurrent_conn_attempts.clone(),
-                    self.tx_sender.clone(),
                 )
                 .await;
             }
             loop {
                 if let Some(task) = self.conn_rx.recv().await {
-                    if is_buy_in_progress() {
-                        tokio::time::sleep(Duration::from_secs(90)).await;
-                    }
-
                     if let Some(err) = task.err {
                         match err {
                             RLPXSessionError::ConnectionClosed | RLPXSessionError::TcpError(_) => {
@@ -110,7 +101,6 @@ impl OutboundConnections {
                         task,
                         self.conn_tx.clone(),
                         self.concurrent_conn_attempts.clone(),
-                        self.tx_sender.clone(),
                     )
                     .await;
                 }
@@ -0,0 +1,265 @@
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+use once_cell::sync::{Lazy, OnceCell};
+use tokio::sync::oneshot;
+
+use crate::p2p::errors::P2PError;
+use crate::p2p::peer::PeerType;
+use crate::p2p::peer_info::PeerInfo;
+use crate::p2p::DisconnectReason;
+use crate::types::hash::H512;
+
+use super::peers::PEERS;
+
+pub const DEFAULT_MAX_INBOUND_PEERS: usize = 1_000;
+pub const DEFAULT_MAX_OUTBOUND_PEERS: usize = 1_500;
+
+/// New peer can't be judged by its txs yet, so it is never evicted before this
+const MIN_PEER_AGE_SECS: i64 = 5 * 60;
+/// Peer which didn't deliver any tx for this long is considered silent
+const SILENCE_SECS: i64 = 2 * 60;
+
+static PEER_BUDGET: OnceCell<PeerBudget> = OnceCell::new();
+
+/// Senders used to tell peer's task that it was evicted, with the session which took the slot
+static EVICTIONS: Lazy<DashMap<H512, (u64, oneshot::Sender<DisconnectReason>)>> =
+    Lazy::new(DashMap::new);
+
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(0);
+
+/// Counting peers and inserting new one has to be atomic, otherwise concurrent handshakes
+/// overshoot the budget
+static SLOTS_LOCK: Mutex<()> = Mutex::new(());
+
+/*
+* Inbound and outbound peers have separate budgets.
+* When budget of the new peer's type is full, the worst peer of the same type is evicted to make
+* room for it, in this order:
+* 1. silent peers (no txs for `SILENCE_SECS`)
+* 2. peers from the network which is already covered by another peer of the same type
+* 3. peers with negative tx latency score (new peer starts at 0, so it can only be better)
+* Peers younger than `MIN_PEER_AGE_SECS` are never evicted. If there is nobody to evict, the new
+* peer gets `TooManyPeers`.
+*
+* We don't have ASN database at hand, so the network is IPv4 /16 (IPv6 /32) prefix,
+* which is close enough to "same hosting provider/location".
+* */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerBudget {
+    pub inbound: usize,
+    pub outbound: usize,
+}
+
+impl Default for PeerBudget {
+    fn default() -> Self {
+        Self {
+            inbound: DEFAULT_MAX_INBOUND_PEERS,
+            outbound: DEFAULT_MAX_OUTBOUND_PEERS,
+        }
+    }
+}
+
+impl PeerBudget {
+    fn for_type(&self, peer_type: PeerType) -> usize {
+        match peer_type {
+            PeerType::Inbound => self.inbound,
+            PeerType::Outbound => self.outbound,
+        }
+    }
+}
+
+pub fn init_peer_budget(inbound: usize, outbound: usize) {
+    let budget = PEER_BUDGET.get_or_init(|| PeerBudget { inbound, outbound });
+    println!(
+        "Max peers, inbound: {}, outbound: {}",
+        budget.inbound, budget.outbound
+    );
+}
+
+fn peer_budget() -> PeerBudget {
+    PEER_BUDGET.get().copied().unwrap_or_default()
+}
+
+pub fn network_of(address: &IpAddr) -> String {
+    match address {
+        IpAddr::V4(ip) => {
+            let o = ip.octets();
+            format!("{}.{}.0.0/16", o[0], o[1])
+        }
+        IpAddr::V6(ip) => {
+            let s = ip.segments();
+            format!("{:x}:{:x}::/32", s[0], s[1])
+        }
+    }
+}
+
+fn is_silent(peer: &PeerInfo, now: i64) -> bool {
+    let last_activity = peer.last_tx_at.max(peer.connected_at);
+    now - last_activity > SILENCE_SECS
+}
+
+/// Worst peer which is worse than a fresh one, `peers` are all peers of the same type
+fn pick_peer_to_evict(peers: &[PeerInfo], now: i64) -> Option<H512> {
+    let is_redundant = |peer: &PeerInfo| {
+        peers
+            .iter()
+            .any(|p| p.id != peer.id && p.network == peer.network)
+    };
+
+    peers
+        .iter()
+        .filter(|p| now - p.connected_at >= MIN_PEER_AGE_SECS)
+        .map(|p| {
+            let silent = is_silent(p, now);
+            let redundant = is_redundant(p);
+            (p, silent, redundant)
+        })
+        .filter(|(p, silent, redundant)| *silent || *redundant || p.tx_latency.score() < 0.0)
+        .min_by(|a, b| {
+            // `false < true`, so silent and redundant peers go first
+            (!a.1, !a.2)
+                .cmp(&(!b.1, !b.2))
+                .then(a.0.tx_latency.score().total_cmp(&b.0.tx_latency.score()))
+        })
+        .map(|(p, _, _)| p.id)
+}
+
+/// Adds the peer to `PEERS` if there is (or can be made) room for it.
+/// Returns the session which holds the slot, and the receiver which fires when the peer is
+/// evicted later on.
+pub fn take_peer_slot(
+    id: H512,
+    info: PeerInfo,
+) -> Result<(u64, oneshot::Receiver<DisconnectReason>), P2PError> {
+    let _guard = SLOTS_LOCK.lock().unwrap();
+
+    // checked before the handshake as well, but two handshakes with the same peer can finish
+    // at the same time
+    if PEERS.contains_key(&id) {
+        return Err(P2PError::AlreadyConnected);
+    }
+
+    let peer_type = info.peer_type;
+    let same_type = PEERS
+        .iter()
+        .filter(|p| p.value().peer_type == peer_type)
+        .map(|p| p.value().clone())
+        .collect::<Vec<_>>();
+
+    if same_type.len() >= peer_budget().for_type(peer_type) {
+        let now = chrono::Utc::now().timestamp();
+        let evicted = pick_peer_to_evict(&same_type, now).ok_or(P2PError::TooManyPeers)?;
+        evict_peer(&evicted, DisconnectReason::TooManyPeers);
+    }
+
+    let session = NEXT_SESSION.fetch_add(1, Ordering::Relaxed);
+    let (evict_tx, evict_rx) = oneshot::channel();
+    EVICTIONS.insert(id, (session, evict_tx));
+    PEERS.insert(id, info);
+    Ok((session, evict_rx))
+}
+
+/// Removes the peer from `PEERS` right away (so its slot is free), its task sends the
+/// disconnect and closes the connection
+pub fn evict_peer(id: &H512, reason: DisconnectReason) {
+    PEERS.remove(id);
+    if let Some((_, (_, evict_tx))) = EVICTIONS.remove(id) {
+        let _ = evict_tx.send(reason);
+    }
+}
+
+/// Frees the slot, unless `session` lost it already. Evicted peer's slot may be taken by a new
+/// session with the same peer before the old one is done.
+pub fn release_peer_slot(id: &H512, session: u64) {
+    let _guard = SLOTS_LOCK.lock().unwrap();
+    if EVICTIONS.remove_if(id, |_, (s, _)| *s == session).is_some() {
+        PEERS.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::p2p::tx_latency::TxLatencyScore;
+
+    fn peer(byte: u8, ip: &str, connected_at: i64, last_tx_at: i64, score: f64) -> PeerInfo {
+        PeerInfo {
+            id: H512::repeat_byte(byte),
+            info: String::new(),
+            enode: String::new(),
+            ip: ip.to_string(),
+            network: network_of(&ip.parse().unwrap()),
+            peer_type: PeerType::Inbound,
+            protocol_version: 68,
+            td: 0,
+            connected_at,
+            last_tx_at,
+            tx_latency: TxLatencyScore {
+                avg_lead_micros: score.max(0.0),
+                avg_delay_micros: (-score).max(0.0),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn evicts_worst_peer() {
+        let now = 10_000;
+        let old = now - MIN_PEER_AGE_SECS;
+
+        let good = peer(1, "1.1.1.1", old, now, 100.0);
+        let slow = peer(2, "2.2.2.2", old, now, -100.0);
+        let slower = peer(3, "3.3.3.3", old, now, -200.0);
+        let young_silent = peer(4, "4.4.4.4", now - 10, 0, -500.0);
+
+        let peers = vec![good.clone(), slow.clone(), slower.clone(), young_silent];
+        assert_eq!(pick_peer_to_evict(&peers, now), Some(slower.id));
+
+        // redundant network goes before score, silence before both
+        let same_network = peer(5, "1.1.200.1", old, now, 50.0);
+        let mut peers = vec![good.clone(), slower.clone(), same_network.clone()];
+        assert_eq!(pick_peer_to_evict(&peers, now), Some(same_network.id));
+
+        let silent = peer(6, "6.6.6.6", old, now - SILENCE_SECS - 1, 300.0);
+        peers.push(silent.clone());
+        assert_eq!(pick_peer_to_evict(&peers, now), Some(silent.id));
+
+        // nobody is worse than a fresh peer
+        assert_eq!(pick_peer_to_evict(&[good], now), None);
+    }
+
+    #[test]
+    fn slot_is_released_by_its_session_only() {
+        let info = peer(7, "7.7.7.7", 0, 0, 0.0);
+        let id = info.id;
+
+        let (first, mut first_evicted) = take_peer_slot(id, info.clone()).unwrap();
+        assert!(matches!(
+            take_peer_slot(id, info.clone()),
+            Err(P2PError::AlreadyConnected)
+        ));
+        assert!(first_evicted.try_recv().is_err());
+
+        // evicted peer reconnects before the old session is done
+        evict_peer(&id, DisconnectReason::TooManyPeers);
+        assert!(first_evicted.try_recv().is_ok());
+        let (second, _second_evicted) = take_peer_slot(id, info).unwrap();
+
+        release_peer_slot(&id, first);
+        assert!(PEERS.contains_key(&id) && EVICTIONS.contains_key(&id));
+        release_peer_slot(&id, second);
+        assert!(!PEERS.contains_key(&id) && !EVICTIONS.contains_key(&id));
+    }
+
+    #[test]
+    fn network_prefix() {
+        assert_eq!(network_of(&"10.20.30.40".parse().unwrap()), "10.20.0.0/16");
+        assert_eq!(
+            network_of(&"2a01:4f8:1:2::1".parse().unwrap()),
+            "2a01:4f8::/32"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use std::fmt::Display;
+
+use ethers::{
+    abi::{
+        decode,
+        token::{LenientTokenizer, Tokenizer},
+        AbiParser, ParamType, Token as AbiToken,
+    },
+    types::{I256, U256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::TX_SIGNATURE_LEN;
+
+/*
+* Check of the enable buy tx argument, with calldata decoded according to the function signature, eg.
+* {
+*   "function": "startTrade(address[] wallets, uint256 r)",
+*   "arg": 0,
+*   "predicate": "> 0"
+* }
+* Supported predicates are `==`, `!=`, `>`, `>=`, `<`, `<=` and `contains`.
+* For arrays, comparison operators are applied to the array length,
+* `contains` works for arrays (element), bytes and strings.
+* */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "AbiConditionConfig", into = "AbiConditionConfig")]
+pub struct AbiCondition {
+    config: AbiConditionConfig,
+    selector: [u8; 4],
+    params: Vec<ParamType>,
+    operator: Operator,
+    value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AbiConditionConfig {
+    /// Solidity function signature, eg. `enableTrading(bool,uint256)`
+    pub function: String,
+    /// Zero based index of the argument
+    pub arg: usize,
+    /// Operator and value, eg. `== true`
+    pub predicate: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Contains,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Token(AbiToken),
+    Length(U256),
+    Bytes(Vec<u8>),
+}
+
+impl AbiCondition {
+    #[inline(always)]
+    pub fn is_satisfied(&self, tx_data: &[u8]) -> bool {
+        if !tx_data.starts_with(&self.selector) {
+            return false;
+        }
+
+        let args = match decode(&self.params, &tx_data[TX_SIGNATURE_LEN..]) {
+            Ok(args) => args,
+            Err(_) => return false,
+        };
+
+        match args.get(self.config.arg) {
+            Some(arg) => self.check(arg),
+            None => false,
+        }
+    }
+
+    fn check(&self, arg: &AbiToken) -> bool {
+        match (&self.value, arg) {
+            (Value::Length(length), AbiToken::Array(items) | AbiToken::FixedArray(items)) => {
+                compare(self.operator, U256::from(items.len()), *length)
+            }
+            (Value::Token(value), AbiToken::Array(items) | AbiToken::FixedArray(items)) => {
+                items.contains(value)
+            }
+            (Value::Bytes(value), AbiToken::Bytes(bytes) | AbiToken::FixedBytes(bytes)) => {
+                contains(bytes, value)
+            }
+            (Value::Bytes(value), AbiToken::String(string)) => contains(string.as_bytes(), value),
+            (Value::Token(AbiToken::Uint(value)), AbiToken::Uint(arg)) => {
+                compare(self.operator, *arg, *value)
+            }
+            (Value::Token(AbiToken::Int(value)), AbiToken::Int(arg)) => {
+                compare(self.operator, I256::from_raw(*arg), I256::from_raw(*value))
+            }
+            (Value::Token(value), arg) => match self.operator {
+                Operator::Eq => arg == value,
+                Operator::Ne => arg != value,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for AbiCondition {
+    fn eq(&self, other: &Self) -> bool {
+        self.config == other.config
+    }
+}
+
+impl Eq for AbiCondition {}
+
+impl TryFrom<AbiConditionConfig> for AbiCondition {
+    type Error = String;
+
+    fn try_from(config: AbiConditionConfig) -> Result<Self, Self::Error> {
+        let function = AbiParser::default()
+            .parse_function(&config.function)
+            .map_err(|e| format!("Invalid function signature {}: {}", config.function, e))?;
+        let params: Vec<ParamType> = function.inputs.iter().map(|p| p.kind.clone()).collect();
+
+        let param = params
+            .get(config.arg)
+            .ok_or_else(|| format!("Function {} has no arg {}", config.function, config.arg))?;
+
+        let (operator, value) = Operator::split_predicate(&config.predicate)?;
+
+        let value = match (operator, param) {
+            (Operator::Contains, ParamType::Array(item) | ParamType::FixedArray(item, _)) => {
+                Value::Token(tokenize(item, value)?)
+            }
+            (
+                Operator::Contains,
+                ParamType::Bytes | ParamType::FixedBytes(_) | ParamType::String,
+            ) => Value::Bytes(match value.strip_prefix("0x") {
+                Some(hex_value) => hex::decode(hex_value).map_err(|e| e.to_string())?,
+                None => value.as_bytes().to_vec(),
+            }),
+            (Operator::Contains, _) => {
+                return Err(format!("`contains` is not supported for {}", param))
+            }
+            (_, ParamType::Array(_) | ParamType::FixedArray(_, _)) => Value::Length(
+                U256::from_dec_str(value).map_err(|e| format!("Invalid array length: {}", e))?,
+            ),
+            (Operator::Eq | Operator::Ne, _) => Value::Token(tokenize(param, value)?),
+            (_, ParamType::Uint(_) | ParamType::Int(_)) => Value::Token(tokenize(param, value)?),
+            (_, _) => return Err(format!("`{}` is not supported for {}", operator, param)),
+        };
+
+        Ok(Self {
+            selector: function.short_signature(),
+            params,
+            operator,
+            value,
+            config,
+        })
+    }
+}
+
+impl From<AbiCondition> for AbiConditionConfig {
+    fn from(condition: AbiCondition) -> Self {
+        condition.config
+    }
+}
+
+impl Operator {
+    /// Two char operators go before their one char prefixes, so `>=` isn't read as `>`
+    const ALL: [Operator; 7] = [
+        Operator::Eq,
+        Operator::Ne,
+        Operator::Ge,
+        Operator::Le,
+        Operator::Gt,
+        Operator::Lt,
+        Operator::Contains,
+    ];
+
+    /// Splits a predicate into the operator and the value, eg. `>0` and `> 0` both give `(Gt, "0")`
+    fn split_predicate(predicate: &str) -> Result<(Self, &str), String> {
+        let predicate = predicate.trim();
+        Self::ALL
+            .iter()
+            .find_map(|operator| {
+                predicate
+                    .strip_prefix(operator.as_str())
+                    .map(|value| (*operator, value.trim()))
+            })
+            .filter(|(_, value)| !value.is_empty())
+            .ok_or_else(|| format!("Invalid predicate: {}", predicate))
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Contains => "contains",
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+fn tokenize(param: &ParamType, value: &str) -> Result<AbiToken, String> {
+    LenientTokenizer::tokenize(param, value)
+        .map_err(|e| format!("Invalid value {} for {}: {}", value, param, e))
+}
+
+fn compare<T: Ord>(operator: Operator, arg: T, value: T) -> bool {
+    match operator {
+        Operator::Eq => arg == value,
+        Operator::Ne => arg != value,
+        Operator::Gt => arg > value,
+        Operator::Ge => arg >= value,
+        Operator::Lt => arg < value,
+        Operator::Le => arg <= value,
+        Operator::Contains => false,
+    }
+}
+
+fn contains(bytes: &[u8], slice: &[u8]) -> bool {
+    !slice.is_empty() && bytes.windows(slice.len()).any(|window| window == slice)
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::{
+        abi::{encode, short_signature},
+        types::Address,
+    };
+
+    use super::*;
+
+    fn condition(function: &str, arg: usize, predicate: &str) -> Result<AbiCondition, String> {
+        AbiCondition::try_from(AbiConditionConfig {
+            function: function.to_string(),
+            arg,
+            predicate: predicate.to_string(),
+        })
+    }
+
+    fn calldata(function: &str, params: &[ParamType], args: &[AbiToken]) -> Vec<u8> {
+        let mut data = short_signature(function, params).to_vec();
+        data.extend(encode(args));
+        data
+    }
+
+    #[test]
+    fn bool_and_uint_predicates() {
+        let params = [ParamType::Uint(8), ParamType::Bool];
+        let enabled = calldata(
+            "enableTrading",
+            &params,
+            &[AbiToken::Uint(U256::from(300)), AbiToken::Bool(true)],
+        );
+        let disabled = calldata(
+            "enableTrading",
+            &params,
+            &[AbiToken::Uint(U256::zero()), AbiToken::Bool(false)],
+        );
+
+        let is_true = condition("enableTrading(uint8,bool)", 1, "== true").unwrap();
+        assert!(is_true.is_satisfied(&enabled));
+        assert!(!is_true.is_satisfied(&disabled));
+
+        let bigger_than_0 = condition("enableTrading(uint8 x, bool y)", 0, "> 0").unwrap();
+        assert!(bigger_than_0.is_satisfied(&enabled));
+        assert!(!bigger_than_0.is_satisfied(&disabled));
+        let bigger_than_0 = condition("enableTrading(uint8,bool)", 0, ">0").unwrap();
+        assert!(bigger_than_0.is_satisfied(&enabled));
+        assert!(!bigger_than_0.is_satisfied(&disabled));
+
+        // other function with the same args
+        let other = condition("disableTrading(uint8,bool)", 1, "== true").unwrap();
+        assert!(!other.is_satisfied(&enabled));
+    }
+
+    #[test]
+    fn address_array_predicates() {
+        let wallet = Address::repeat_byte(0x11);
+        let params = [
+            ParamType::Array(Box::new(ParamType::Address)),
+            ParamType::Uint(256),
+        ];
+        let data = calldata(
+            "startTrade",
+            &params,
+            &[
+                AbiToken::Array(vec![
+                    AbiToken::Address(Address::repeat_byte(0x22)),
+                    AbiToken::Address(wallet),
+                ]),
+                AbiToken::Uint(U256::one()),
+            ],
+        );
+
+        let contains_wallet = condition(
+            "startTrade(address[],uint256)",
+            0,
+            &format!("contains {:?}", wallet),
+        )
+        .unwrap();
+        assert!(contains_wallet.is_satisfied(&data));
+
+        let contains_other = condition(
+            "startTrade(address[],uint256)",
+            0,
+            &format!("contains {:?}", Address::repeat_byte(0x33)),
+        )
+        .unwrap();
+        assert!(!contains_other.is_satisfied(&data));
+
+        let not_empty = condition("startTrade(address[],uint256)", 0, "> 0").unwrap();
+        assert!(not_empty.is_satisfied(&data));
+        let has_3 = condition("startTrade(address[],uint256)", 0, ">= 3").unwrap();
+        assert!(!has_3.is_satisfied(&data));
+        let has_3 = condition("startTrade(address[],uint256)", 0, ">=3").unwrap();
+        assert!(!has_3.is_satisfied(&data));
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        assert!(condition("enableTrading(bool)", 1, "== true").is_err());
+        assert!(condition("enableTrading(bool)", 0, "> true").is_err());
+        assert!(condition("enableTrading(bool)", 0, "contains true").is_err());
+        assert!(condition("enableTrading(uint256)", 0, "=> 1").is_err());
+        assert!(condition("enableTrading(uint256)", 0, "==").is_err());
+        assert!(condition("enableTrading(uint256", 0, "== 1").is_err());
+    }
+}
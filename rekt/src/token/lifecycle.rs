@@ -0,0 +1,130 @@
+use dashmap::{mapref::entry::Entry, DashMap};
+use derive_more::Display;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use thiserror::Error;
+
+use super::token::TokenAddress;
+
+/*
+* Every token to buy goes through: Pending -> Triggered -> Bought -> Selling -> Done
+* Pending:   token is in the list of tokens to buy and its trigger txs are watched
+* Triggered: liquidity/enable buy tx was seen, token is taken out of the list and buy txs are sent
+* Bought:    buy txs are sent
+* Selling:   sell txs are being sent
+* Done:      token is never imported again
+*
+* Every token has its own state, so other tokens are watched (and bought) while one is sold.
+* Transition is checked atomically, so a token is triggered only once even if the same tx is
+* decoded by many peers at the same time.
+* */
+static TOKEN_STATES: Lazy<DashMap<TokenAddress, TokenState>> = Lazy::new(DashMap::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+pub enum TokenState {
+    Pending,
+    Triggered,
+    Bought,
+    Selling,
+    Done,
+}
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("Token {token:#x} can't go from {from:?} to {to}")]
+pub struct InvalidTransition {
+    pub token: TokenAddress,
+    pub from: Option<TokenState>,
+    pub to: TokenState,
+}
+
+impl TokenState {
+    /// Whether `next` can follow this state, `None` is the state of unknown token
+    pub fn can_become(current: Option<TokenState>, next: TokenState) -> bool {
+        matches!(
+            (current, next),
+            (None, TokenState::Pending)
+                | (Some(TokenState::Pending), TokenState::Triggered)
+                | (Some(TokenState::Triggered), TokenState::Bought)
+                | (Some(TokenState::Bought), TokenState::Selling)
+                | (Some(TokenState::Bought), TokenState::Done)
+                | (Some(TokenState::Selling), TokenState::Done)
+        )
+    }
+}
+
+pub fn token_state(token: &TokenAddress) -> Option<TokenState> {
+    TOKEN_STATES.get(token).map(|state| *state)
+}
+
+pub fn set_token_state(token: TokenAddress, next: TokenState) -> Result<(), InvalidTransition> {
+    match TOKEN_STATES.entry(token) {
+        Entry::Occupied(mut entry) if TokenState::can_become(Some(*entry.get()), next) => {
+            entry.insert(next);
+            Ok(())
+        }
+        Entry::Vacant(entry) if TokenState::can_become(None, next) => {
+            entry.insert(next);
+            Ok(())
+        }
+        Entry::Occupied(entry) => Err(InvalidTransition {
+            token,
+            from: Some(*entry.get()),
+            to: next,
+        }),
+        Entry::Vacant(_) => Err(InvalidTransition {
+            token,
+            from: None,
+            to: next,
+        }),
+    }
+}
+
+/// Forgets pending token (eg. when the list is refreshed), tokens past pending are kept
+pub fn forget_pending_token(token: &TokenAddress) {
+    TOKEN_STATES.remove_if(token, |_, state| *state == TokenState::Pending);
+}
+
+pub fn all_token_states() -> Vec<(TokenAddress, TokenState)> {
+    TOKEN_STATES
+        .iter()
+        .map(|entry| (*entry.key(), *entry.value()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_lifecycle() {
+        let token = TokenAddress::repeat_byte(0xa1);
+        let other = TokenAddress::repeat_byte(0xa2);
+
+        assert!(set_token_state(token, TokenState::Triggered).is_err());
+        set_token_state(token, TokenState::Pending).unwrap();
+        set_token_state(other, TokenState::Pending).unwrap();
+
+        set_token_state(token, TokenState::Triggered).unwrap();
+        // the same tx decoded by another peer
+        assert_eq!(
+            set_token_state(token, TokenState::Triggered),
+            Err(InvalidTransition {
+                token,
+                from: Some(TokenState::Triggered),
+                to: TokenState::Triggered
+            })
+        );
+
+        set_token_state(token, TokenState::Bought).unwrap();
+        set_token_state(token, TokenState::Selling).unwrap();
+        assert_eq!(token_state(&other), Some(TokenState::Pending));
+
+        set_token_state(token, TokenState::Done).unwrap();
+        assert!(set_token_state(token, TokenState::Pending).is_err());
+
+        forget_pending_token(&token);
+        forget_pending_token(&other);
+        assert_eq!(token_state(&token), Some(TokenState::Done));
+        assert_eq!(token_state(&other), None);
+    }
+}
@@ -1,2 +1,7 @@
+pub mod abi_condition;
+pub mod lifecycle;
+pub mod multicall;
 pub mod token;
 pub mod tokens_to_buy;
+pub mod trigger;
+pub mod v3;
@@ -0,0 +1,214 @@
+use ethers::types::U256;
+
+use crate::constants::{TX_ARG_LEN, TX_SIGNATURE_LEN};
+
+/// `multicall(bytes[] data)`
+const MULTICALL: [u8; 4] = [0xac, 0x96, 0x50, 0xd8];
+/// `multicall(uint256 deadline, bytes[] data)`
+const MULTICALL_WITH_DEADLINE: [u8; 4] = [0x5a, 0xe4, 0x01, 0xdc];
+/// `multicall(bytes32 previousBlockhash, bytes[] data)`
+const MULTICALL_WITH_BLOCKHASH: [u8; 4] = [0x1f, 0x04, 0x64, 0xd1];
+
+/// Multicalls inside multicalls are allowed, but there is no reason to go deeper than this
+const MAX_DEPTH: usize = 4;
+
+/// Calls returned for one tx (the tx call included), whatever the nesting
+const MAX_CALLS: usize = 64;
+
+/*
+* Routers and position managers allow batching calls with `multicall`, where every inner call
+* is executed (delegatecall) by the same contract. So for trigger matching the inner calls are
+* the same as if they were sent directly to the contract.
+*
+* Inner calls are returned as slices into tx data, nothing is copied.
+* Every mempool tx goes through this, so items of a `bytes[]` must follow each other without
+* overlapping (as any ABI encoder lays them out), otherwise offsets pointing at the same data would
+* expand a small tx into a huge number of calls. On top of that there is a budget of `MAX_CALLS`,
+* calls collected before it is hit are returned.
+* */
+
+/// Returns the tx call itself followed by all (recursively) unwrapped inner calls, up to `MAX_CALLS`
+pub fn unwrap_calls(tx_data: &[u8]) -> Vec<&[u8]> {
+    let mut calls = vec![tx_data];
+    unwrap_into(tx_data, 0, &mut calls);
+    calls
+}
+
+fn unwrap_into<'a>(call: &'a [u8], depth: usize, calls: &mut Vec<&'a [u8]>) {
+    if depth == MAX_DEPTH || calls.len() >= MAX_CALLS {
+        return;
+    }
+
+    let bytes_array_arg_index = match call.get(..TX_SIGNATURE_LEN) {
+        Some(selector) if selector == MULTICALL => 0,
+        Some(selector) if selector == MULTICALL_WITH_DEADLINE => 1,
+        Some(selector) if selector == MULTICALL_WITH_BLOCKHASH => 1,
+        _ => return,
+    };
+
+    let inner_calls = match decode_bytes_array(&call[TX_SIGNATURE_LEN..], bytes_array_arg_index) {
+        Some(inner_calls) => inner_calls,
+        None => return,
+    };
+
+    for inner_call in inner_calls {
+        if calls.len() >= MAX_CALLS {
+            return;
+        }
+        calls.push(inner_call);
+        unwrap_into(inner_call, depth + 1, calls);
+    }
+}
+
+/// Decodes `bytes[]` argument at `arg_index` from ABI encoded args, every item has to start after
+/// the end of the previous one
+fn decode_bytes_array(args: &[u8], arg_index: usize) -> Option<Vec<&[u8]>> {
+    let array_offset = read_usize(args, arg_index * TX_ARG_LEN)?;
+    let len = read_usize(args, array_offset)?;
+    let items_head = array_offset + TX_ARG_LEN;
+    // every item has at least its offset in the head, so this guards against absurd lengths
+    if len > args.len().saturating_sub(items_head) / TX_ARG_LEN {
+        return None;
+    }
+
+    let mut items_end = items_head + len * TX_ARG_LEN;
+    (0..len)
+        .map(|i| {
+            let item_start = items_head + read_usize(args, items_head + i * TX_ARG_LEN)?;
+            if item_start < items_end {
+                return None;
+            }
+            let item_len = read_usize(args, item_start)?;
+            let data_start = item_start + TX_ARG_LEN;
+            let data_end = data_start.checked_add(item_len)?;
+            items_end = data_end;
+            args.get(data_start..data_end)
+        })
+        .collect()
+}
+
+fn read_usize(args: &[u8], at: usize) -> Option<usize> {
+    let word = args.get(at..at.checked_add(TX_ARG_LEN)?)?;
+    let value = U256::from_big_endian(word);
+    if value > U256::from(args.len()) {
+        return None;
+    }
+
+    Some(value.as_usize())
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::{
+        abi::{encode, short_signature, ParamType, Token},
+        types::Address,
+    };
+
+    use super::*;
+
+    fn bytes_array() -> ParamType {
+        ParamType::Array(Box::new(ParamType::Bytes))
+    }
+
+    fn call(selector: [u8; 4], args: &[Token]) -> Vec<u8> {
+        let mut data = selector.to_vec();
+        data.extend(encode(args));
+        data
+    }
+
+    #[test]
+    fn multicall_selectors() {
+        assert_eq!(short_signature("multicall", &[bytes_array()]), MULTICALL);
+        assert_eq!(
+            short_signature("multicall", &[ParamType::Uint(256), bytes_array()]),
+            MULTICALL_WITH_DEADLINE
+        );
+        assert_eq!(
+            short_signature("multicall", &[ParamType::FixedBytes(32), bytes_array()]),
+            MULTICALL_WITH_BLOCKHASH
+        );
+    }
+
+    #[test]
+    fn nested_multicalls_are_unwrapped() {
+        let mint = call(
+            [0x88, 0x31, 0x64, 0x56],
+            &[Token::Address(Address::repeat_byte(1))],
+        );
+        let refund = call([0x12, 0x21, 0x0e, 0x8a], &[]);
+        let inner = call(
+            MULTICALL,
+            &[Token::Array(vec![
+                Token::Bytes(mint.clone()),
+                Token::Bytes(refund.clone()),
+            ])],
+        );
+        let outer = call(
+            MULTICALL_WITH_DEADLINE,
+            &[
+                Token::Uint(U256::from(1_700_000_000)),
+                Token::Array(vec![Token::Bytes(inner.clone())]),
+            ],
+        );
+
+        assert_eq!(
+            unwrap_calls(&outer),
+            vec![&outer[..], &inner[..], &mint[..], &refund[..]]
+        );
+    }
+
+    #[test]
+    fn malformed_multicall_is_not_unwrapped() {
+        let mut data = call(
+            MULTICALL,
+            &[Token::Array(vec![Token::Bytes(vec![1, 2, 3, 4])])],
+        );
+        // point the array to the end of calldata
+        data[TX_SIGNATURE_LEN + TX_ARG_LEN - 1] = 0xff;
+
+        assert_eq!(unwrap_calls(&data), vec![&data[..]]);
+        assert_eq!(unwrap_calls(&MULTICALL), vec![&MULTICALL[..]]);
+    }
+
+    #[test]
+    fn aliased_items_are_not_unwrapped() {
+        let refund = call([0x12, 0x21, 0x0e, 0x8a], &[]);
+        let mut data = call(
+            MULTICALL,
+            &[Token::Array(vec![
+                Token::Bytes(refund.clone()),
+                Token::Bytes(refund.clone()),
+            ])],
+        );
+        assert_eq!(
+            unwrap_calls(&data),
+            vec![&data[..], &refund[..], &refund[..]]
+        );
+
+        // [array offset, len, offset of item 0, offset of item 1, ...], second item points at the first
+        let second_offset = TX_SIGNATURE_LEN + 3 * TX_ARG_LEN;
+        let first_offset = data[second_offset - TX_ARG_LEN..second_offset].to_vec();
+        data[second_offset..second_offset + TX_ARG_LEN].copy_from_slice(&first_offset);
+        assert_eq!(unwrap_calls(&data), vec![&data[..]]);
+
+        // pointing back into the head
+        data[second_offset + TX_ARG_LEN - 1] = 0;
+        assert_eq!(unwrap_calls(&data), vec![&data[..]]);
+    }
+
+    #[test]
+    fn calls_are_limited() {
+        let refund = call([0x12, 0x21, 0x0e, 0x8a], &[]);
+        let data = call(
+            MULTICALL,
+            &[Token::Array(vec![
+                Token::Bytes(refund.clone());
+                2 * MAX_CALLS
+            ])],
+        );
+
+        let calls = unwrap_calls(&data);
+        assert_eq!(calls.len(), MAX_CALLS);
+        assert!(calls[1..].iter().all(|c| *c == &refund[..]));
+    }
+}
@@ -4,7 +4,11 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     constants::{TX_ARG_LEN, TX_SIGNATURE_LEN},
-    eth::eth_message::EthMessage,
+    eth::{eth_message::EthMessage, transactions::our_pool::PreparedTxs},
+    token::{
+        abi_condition::AbiCondition,
+        trigger::{Trigger, PCS_TRIGGERS},
+    },
     utils::wei_gwei_converter::{
         gas_price_is_in_supported_precision, gas_price_is_in_supported_range, gas_price_to_index,
         get_default_gas_price_range, gwei_to_wei_with_decimals, DEFAULT_GWEI_DECIMAL_PRECISION,
@@ -61,7 +65,7 @@ pub struct Token {
     pub from: Option<FromConfig>,
 
     #[serde(skip)]
-    pub buy_txs: Option<Vec<EthMessage>>,
+    pub buy_txs: Option<Vec<PreparedTxs>>,
 
     #[serde(rename = "mev", default)]
     pub mev_config: Option<MevConfig>,
@@ -101,6 +105,17 @@ pub struct EnableBuyConfig {
 
     #[serde(rename = "size", default)]
     pub expected_tx_size: usize,
+
+    /// Check of the enable buy tx argument with calldata decoded according to ABI,
+    /// eg. `startTrade(address[],uint256)` with `address[] contains X`.
+    /// When set, it's used instead of `tradeStatusArg*` settings
+    #[serde(rename = "tradeStatusCheck", default)]
+    pub trade_status_check: Option<AbiCondition>,
+
+    /// Txs which should trigger the buy apart from the enable buy tx itself,
+    /// eg. liquidity added via some router (see [Trigger])
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -119,10 +134,15 @@ pub struct SellConfig {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FromConfig {
+    /// Expected sender (deployer/owner) of the enable buy tx
+    #[serde(default)]
+    pub address: Option<Address>,
+    /// Lowest accepted nonce of the enable buy tx, no lower bound if not set
     #[serde(rename = "minNonce", default)]
-    pub min_nonce: u64,
+    pub min_nonce: Option<u64>,
+    /// Highest accepted nonce of the enable buy tx, no upper bound if not set
     #[serde(rename = "maxNonce", default)]
-    pub max_nonce: u64,
+    pub max_nonce: Option<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -150,11 +170,53 @@ impl Token {
         self.enable_buy_config.tx_to
     }
 
-    pub async fn prepare_buy_txs_for_gas_price(&mut self, gas_price_in_wei: u64) -> EthMessage {
+    /// Triggers from config, plus PCS ones if liquidity will be added via PCS
+    pub fn triggers(&self) -> impl Iterator<Item = &Trigger> {
+        self.enable_buy_config
+            .triggers
+            .iter()
+            .chain(self.pcs_triggers())
+    }
+
+    pub fn pcs_triggers(&self) -> &[Trigger] {
+        if self.liq_will_be_added_via_pcs {
+            PCS_TRIGGERS.as_slice()
+        } else {
+            &[]
+        }
+    }
+
+    /// Tx with `nonce` matches one of the triggers. Nonce window is meant for the enable trading
+    /// tx, so it applies to triggers from config only, PCS liquidity txs match at any nonce
+    #[inline(always)]
+    pub fn is_triggered_by(&self, nonce: u64, matches: impl Fn(&Trigger) -> bool) -> bool {
+        (self.nonce_is_ok(nonce) && self.enable_buy_config.triggers.iter().any(&matches))
+            || self.pcs_triggers().iter().any(&matches)
+    }
+
+    /// Nonce window from `from` config, either bound can be left out
+    #[inline(always)]
+    pub fn nonce_is_ok(&self, nonce: u64) -> bool {
+        let (min, max) = self.nonce_window();
+        nonce >= min && nonce <= max
+    }
+
+    /// Inclusive nonce window, the whole range if `from` doesn't limit it
+    pub fn nonce_window(&self) -> (u64, u64) {
+        match &self.from {
+            Some(from) => (
+                from.min_nonce.unwrap_or(0),
+                from.max_nonce.unwrap_or(u64::MAX),
+            ),
+            None => (0, u64::MAX),
+        }
+    }
+
+    pub async fn prepare_buy_txs_for_gas_price(&mut self, gas_price_in_wei: u64) -> PreparedTxs {
         let txs =
             generate_and_rlp_encode_buy_txs_for_local_wallets(&self, U256::from(gas_price_in_wei))
                 .await;
-        EthMessage::new_compressed_tx_message(txs)
+        PreparedTxs::new(EthMessage::new_compressed_tx_message(txs))
     }
 
     pub async fn prepare_buy_txs_for_gas_price_range(&mut self) {
@@ -184,7 +246,7 @@ impl Token {
         for gwei in gas_price_range {
             let wei = gwei_to_wei_with_decimals(gwei, DEFAULT_GWEI_DECIMAL_PRECISION);
             let txs = generate_and_rlp_encode_buy_txs_for_local_wallets(&self, wei).await;
-            buy_txs.push(EthMessage::new_compressed_tx_message(txs));
+            buy_txs.push(PreparedTxs::new(EthMessage::new_compressed_tx_message(txs)));
 
             if self.mev_config.is_none() {
                 continue;
@@ -203,7 +265,7 @@ impl Token {
         }
     }
 
-    pub fn get_buy_txs(&mut self, gas_price_in_wei: u64) -> (Option<EthMessage>, Option<String>) {
+    pub fn get_buy_txs(&mut self, gas_price_in_wei: u64) -> (Option<PreparedTxs>, Option<String>) {
         let gas_price_in_wei = U256::from(gas_price_in_wei);
         if !gas_price_is_in_supported_range(gas_price_in_wei) {
             color_print::cprintln!(
@@ -229,6 +291,10 @@ impl Token {
 
     #[inline(always)]
     pub fn trade_status_is_enable(&self, tx_data: &[u8]) -> bool {
+        if let Some(check) = &self.enable_buy_config.trade_status_check {
+            return check.is_satisfied(tx_data);
+        }
+
         if self.enable_buy_config.trade_status_arg_position == 0 {
             return true;
         }
@@ -332,6 +398,8 @@ mod test {
                     trade_status_arg_value: 1,
                     trade_status_arg_value_any_bigger_than_0: false,
                     expected_tx_size: 0,
+                    triggers: vec![],
+                    trade_status_check: None,
                 },
                 sell_config: SellConfig {
                     sell_count: 2,
@@ -368,6 +436,8 @@ mod test {
                 trade_status_arg_value: 1,
                 trade_status_arg_value_any_bigger_than_0: false,
                 expected_tx_size: 0,
+                triggers: vec![],
+                trade_status_check: None,
             },
             sell_config: SellConfig {
                 sell_count: 2,
@@ -398,6 +468,8 @@ mod test {
                 trade_status_arg_value: 1,
                 trade_status_arg_value_any_bigger_than_0: false,
                 expected_tx_size: 0,
+                triggers: vec![],
+                trade_status_check: None,
             },
             ..token
         };
@@ -412,6 +484,8 @@ mod test {
                 trade_status_arg_value: 4,
                 trade_status_arg_value_any_bigger_than_0: false,
                 expected_tx_size: 0,
+                triggers: vec![],
+                trade_status_check: None,
             },
             ..token
         };
@@ -426,6 +500,8 @@ mod test {
                 trade_status_arg_value: 4,
                 trade_status_arg_value_any_bigger_than_0: true,
                 expected_tx_size: 0,
+                triggers: vec![],
+                trade_status_check: None,
             },
             ..token
         };
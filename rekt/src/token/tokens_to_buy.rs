@@ -1,66 +1,69 @@
-use dashmap::DashSet;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+use ethers::types::Address;
 use futures::StreamExt;
 use once_cell::sync::Lazy;
 use tokio::time::interval;
 
-use crate::{p2p::peer::BUY_IS_IN_PROGRESS, utils::helpers::get_bsc_token_url};
+use crate::{eth::transactions::transaction::Transaction, utils::helpers::get_bsc_token_url};
 
-use super::token::{Token, TokenAddress};
+use super::{
+    lifecycle::{forget_pending_token, set_token_state, token_state, TokenState},
+    multicall::unwrap_calls,
+    token::{Token, TokenAddress},
+};
 
 const TOKENS_TO_BUY_FILE_PATH: &str = "tokens_to_buy.json";
 const REFRESH_TOKENS_INTERVAL: u64 = 10;
 
-pub static mut TOKENS_TO_BUY: Vec<Token> = Vec::new();
-pub static mut MIN_NONCE: u64 = 0;
-pub static mut MAX_NONCE: u64 = 0;
-pub static mut MIN_SIZE: usize = 0;
-pub static mut MAX_SIZE: usize = 0;
-pub static mut PCS_LIQ: bool = false;
+/*
+* Tokens to buy are read by every peer decoding txs and written by the importer, by buys (taking
+* triggered token out) and by re-signing buy txs after a buy. Lock is never held across await,
+* tokens are prepared outside of it and swapped in by address.
+* */
+static TOKENS_TO_BUY: Lazy<RwLock<TokensToBuy>> = Lazy::new(|| RwLock::new(TokensToBuy::default()));
+
+/// Union of nonce windows of all tokens to buy, txs outside of it are skipped early
+static MIN_NONCE: AtomicU64 = AtomicU64::new(0);
+static MAX_NONCE: AtomicU64 = AtomicU64::new(u64::MAX);
+/// Expected size of enable buy txs, 0 if any size is fine
+static MIN_SIZE: AtomicUsize = AtomicUsize::new(0);
+static MAX_SIZE: AtomicUsize = AtomicUsize::new(0);
 
-pub static BOUGHT_TOKENS: Lazy<DashSet<TokenAddress>> = Lazy::new(|| DashSet::new());
+#[derive(Default)]
+struct TokensToBuy {
+    tokens: Vec<Token>,
+    /// Recipients of all triggers of tokens to buy, so the decoder can cheaply skip txs
+    /// which can't match any trigger
+    trigger_recipients: Vec<TokenAddress>,
+}
 
 pub fn import_tokens_to_buy() {
-    unsafe {
-        TOKENS_TO_BUY.reserve(10);
-    }
     tokio::task::spawn(async move {
         let mut read_tokens_ticker = tokio_stream::wrappers::IntervalStream::new(interval(
             std::time::Duration::from_secs(REFRESH_TOKENS_INTERVAL),
         ));
 
         while read_tokens_ticker.next().await.is_some() {
-            unsafe {
-                if BUY_IS_IN_PROGRESS {
-                    continue;
-                }
-            }
-
             if let Ok(tokens) = read_tokens_to_buy_from_file().await {
                 for mut token in tokens {
-                    if BOUGHT_TOKENS.contains(&token.buy_token_address) {
+                    // token which was already triggered is never bought again
+                    if token_state(&token.buy_token_address)
+                        .is_some_and(|state| state != TokenState::Pending)
+                    {
+                        continue;
+                    }
+                    if !is_newer_than_listed(&token) {
                         continue;
                     }
-                    unsafe {
-                        // If a newer version of the token is in the file,
-                        // update the in-memory list by removing and re-adding the token.
-                        if let Some(token_index) = TOKENS_TO_BUY
-                            .iter()
-                            .position(|t| t.buy_token_address == token.buy_token_address)
-                        {
-                            if TOKENS_TO_BUY[token_index].version < token.version {
-                                TOKENS_TO_BUY.swap_remove(token_index);
-                            } else {
-                                continue;
-                            }
-                        }
-
-                        token.prepare_buy_txs_for_gas_price_range().await;
+
+                    token.prepare_buy_txs_for_gas_price_range().await;
+                    if add_or_update_token(token.clone()) {
                         println!(
                             "Added token to buy: {}",
                             get_bsc_token_url(token.buy_token_address)
                         );
-                        TOKENS_TO_BUY.push(token);
-                        update_global_liq_setting();
                     }
                 }
             } else {
@@ -70,123 +73,435 @@ pub fn import_tokens_to_buy() {
     });
 }
 
+/// True if the token is not listed yet or the listed one has older version
+fn is_newer_than_listed(token: &Token) -> bool {
+    TOKENS_TO_BUY
+        .read()
+        .unwrap()
+        .tokens
+        .iter()
+        .find(|t| t.buy_token_address == token.buy_token_address)
+        .is_none_or(|listed| listed.version < token.version)
+}
+
+/// Lists the token, replacing its older version. Token triggered while its buy txs were
+/// prepared is not listed again
+fn add_or_update_token(token: Token) -> bool {
+    let mut list = TOKENS_TO_BUY.write().unwrap();
+    let tokens = &mut list.tokens;
+    if token_state(&token.buy_token_address).is_some_and(|state| state != TokenState::Pending) {
+        return false;
+    }
+
+    match tokens
+        .iter()
+        .position(|t| t.buy_token_address == token.buy_token_address)
+    {
+        Some(index) if tokens[index].version < token.version => tokens[index] = token,
+        Some(_) => return false,
+        None => {
+            let _ = set_token_state(token.buy_token_address, TokenState::Pending);
+            tokens.push(token);
+        }
+    }
+
+    update_global_liq_setting(&mut list);
+    true
+}
+
 #[inline(always)]
 pub fn there_are_no_tokens_to_buy() -> bool {
-    unsafe { TOKENS_TO_BUY.is_empty() }
+    TOKENS_TO_BUY.read().unwrap().tokens.is_empty()
 }
 
 pub fn mark_token_as_bought(buy_token_address: TokenAddress) {
-    BOUGHT_TOKENS.insert(buy_token_address);
-    unsafe {
-        if let Some(index) = TOKENS_TO_BUY
-            .iter()
-            .position(|v| v.buy_token_address == buy_token_address)
+    if let Err(e) = set_token_state(buy_token_address, TokenState::Bought) {
+        println!("{}", e);
+    }
+}
+
+/// Buy txs of all tokens are signed by the same local wallets, so after a buy the txs prepared
+/// for the rest of pending tokens have stale nonces and have to be signed again
+pub async fn prepare_buy_txs_for_pending_tokens() {
+    let pending_tokens = TOKENS_TO_BUY.read().unwrap().tokens.clone();
+    for mut token in pending_tokens {
+        token.prepare_buy_txs_for_gas_price_range().await;
+
+        // token could be triggered or updated in the meantime
+        let mut list = TOKENS_TO_BUY.write().unwrap();
+        if let Some(listed) = list
+            .tokens
+            .iter_mut()
+            .find(|t| t.buy_token_address == token.buy_token_address && t.version == token.version)
         {
-            TOKENS_TO_BUY.swap_remove(index);
+            *listed = token;
         }
     }
-    update_global_liq_setting();
 }
 
+/// Token is taken out of the list only by the first peer which decoded its trigger tx, the
+/// Pending -> Triggered transition is the compare-and-swap deciding which one it is
 #[inline(always)]
-pub fn get_token_to_buy(address: &TokenAddress, nonce: u64) -> Option<(&Token, usize)> {
-    unsafe {
-        let idx = TOKENS_TO_BUY
-            .iter()
-            .position(|v| &v.enable_buy_config.tx_to == address)?;
-
-        let token = &TOKENS_TO_BUY[idx];
-        if let Some(from) = &token.from {
-            if nonce < from.min_nonce || nonce > from.max_nonce {
-                return None;
-            }
-        }
+fn take_triggered_token(buy_token_address: &TokenAddress) -> Option<Token> {
+    let mut list = TOKENS_TO_BUY.write().unwrap();
+    let index = list
+        .tokens
+        .iter()
+        .position(|t| &t.buy_token_address == buy_token_address)?;
+    set_token_state(*buy_token_address, TokenState::Triggered).ok()?;
 
-        Some((&TOKENS_TO_BUY[idx], idx))
-    }
+    let token = list.tokens.swap_remove(index);
+    update_global_liq_setting(&mut list);
+    Some(token)
 }
 
+/// Cheap check done before the tx data is decoded
 #[inline(always)]
-pub fn get_token(address: &TokenAddress) -> Option<&Token> {
-    unsafe {
-        TOKENS_TO_BUY
-            .iter()
-            .find(|v| &v.enable_buy_config.tx_to == address)
-    }
+pub fn is_token_to_buy(recipient: &TokenAddress, nonce: u64) -> bool {
+    TOKENS_TO_BUY
+        .read()
+        .unwrap()
+        .tokens
+        .iter()
+        .any(|t| &t.enable_buy_config.tx_to == recipient && t.nonce_is_ok(nonce))
 }
 
 #[inline(always)]
-pub fn get_token_by_address(address: &TokenAddress) -> Option<&Token> {
-    unsafe {
-        TOKENS_TO_BUY
-            .iter()
-            .find(|v| &v.buy_token_address == address)
-    }
+pub fn recipient_has_triggers(recipient: &TokenAddress) -> bool {
+    TOKENS_TO_BUY
+        .read()
+        .unwrap()
+        .trigger_recipients
+        .contains(recipient)
+}
+
+/// Finds the first token to buy which has a trigger matching the tx or any of its multicall
+/// inner calls. Candidates are picked under the lock, the sender is checked after it's released.
+#[inline(always)]
+pub fn get_triggered_token(
+    recipient: &TokenAddress,
+    nonce: u64,
+    tx_data: &[u8],
+    raw_tx: &[u8],
+) -> Option<Token> {
+    let calls = unwrap_calls(tx_data);
+    let candidates = TOKENS_TO_BUY
+        .read()
+        .unwrap()
+        .tokens
+        .iter()
+        .filter(|token| {
+            token.is_triggered_by(nonce, |t| {
+                calls.iter().any(|call| {
+                    t.matches(
+                        recipient,
+                        call,
+                        &token.buy_token_address,
+                        &token.liquidity_token_address,
+                    )
+                })
+            })
+        })
+        .map(|token| (token.buy_token_address, expected_sender(token)))
+        .collect::<Vec<_>>();
+
+    take_triggered_token(&first_sent_by_expected_sender(candidates, raw_tx)?)
+}
+
+/// Copy of the listed token, buy txs included
+pub fn get_token_by_address(address: &TokenAddress) -> Option<Token> {
+    TOKENS_TO_BUY
+        .read()
+        .unwrap()
+        .tokens
+        .iter()
+        .find(|v| &v.buy_token_address == address)
+        .cloned()
 }
 
+/// Takes out the token enabled by this tx, if the tx is to its enable buy contract
 #[inline(always)]
 pub fn tx_is_enable_buy(
-    token: &Token,
-    index_of_token_in_buy_list: usize,
+    recipient: &TokenAddress,
+    nonce: u64,
     tx_data: &[u8],
+    raw_tx: &[u8],
 ) -> Option<Token> {
-    if !tx_data.starts_with(token.enable_buy_config.enable_buy_tx_hash.as_ref()) {
-        return None;
-    }
+    let candidates = TOKENS_TO_BUY
+        .read()
+        .unwrap()
+        .tokens
+        .iter()
+        .filter(|token| {
+            &token.enable_buy_config.tx_to == recipient
+                && token.nonce_is_ok(nonce)
+                && tx_data.starts_with(token.enable_buy_config.enable_buy_tx_hash.as_ref())
+                && token.trade_status_is_enable(tx_data)
+        })
+        .map(|token| (token.buy_token_address, expected_sender(token)))
+        .collect::<Vec<_>>();
 
-    if !token.trade_status_is_enable(tx_data) {
-        return None;
-    }
+    take_triggered_token(&first_sent_by_expected_sender(candidates, raw_tx)?)
+}
+
+/// Sender of the trigger tx from token config, if it's set
+fn expected_sender(token: &Token) -> Option<Address> {
+    token.from.as_ref().and_then(|from| from.address)
+}
 
-    unsafe { Some(TOKENS_TO_BUY.swap_remove(index_of_token_in_buy_list)) }
+/// First candidate token whose expected sender (if set) sent the tx.
+/// Sender recovery is expensive compared to the rest of the decoding, so it's done at most once
+/// per tx, and only if some candidate needs it. Called without the tokens lock held.
+fn first_sent_by_expected_sender(
+    candidates: Vec<(TokenAddress, Option<Address>)>,
+    raw_tx: &[u8],
+) -> Option<TokenAddress> {
+    let mut sender = None;
+    candidates
+        .into_iter()
+        .find(|(_, expected)| match expected {
+            None => true,
+            Some(expected) => {
+                let sender = sender
+                    .get_or_insert_with(|| Transaction::decode_raw(raw_tx).ok().map(|tx| tx.from));
+                sender.as_ref() == Some(expected)
+            }
+        })
+        .map(|(buy_token_address, _)| buy_token_address)
 }
 
 #[inline(always)]
 pub fn tx_nonce_is_ok(nonce: u64) -> bool {
-    unsafe {
-        if MAX_NONCE == 0 {
-            return true;
-        }
-        nonce >= MIN_NONCE && nonce <= MAX_NONCE
-    }
+    nonce >= MIN_NONCE.load(Ordering::Relaxed) && nonce <= MAX_NONCE.load(Ordering::Relaxed)
+}
+
+#[inline(always)]
+pub fn tx_size_is_ok(size: usize) -> bool {
+    let (min, max) = (
+        MIN_SIZE.load(Ordering::Relaxed),
+        MAX_SIZE.load(Ordering::Relaxed),
+    );
+    (min == 0 && max == 0) || (size >= min && size <= max)
 }
 
+/// Removes pending tokens, so they are imported again from the file.
+/// Tokens which were already triggered are not affected
 pub fn remove_all_tokens_to_buy() {
-    unsafe {
-        TOKENS_TO_BUY.clear();
-        update_global_liq_setting();
+    let mut list = TOKENS_TO_BUY.write().unwrap();
+    for token in list.tokens.iter() {
+        forget_pending_token(&token.buy_token_address);
     }
+    list.tokens.clear();
+    update_global_liq_setting(&mut list);
 }
 
-fn update_global_liq_setting() {
-    unsafe {
-        MIN_NONCE = 0;
-        MAX_NONCE = 0;
-        PCS_LIQ = false;
-        MIN_SIZE = 0;
-        MAX_SIZE = 0;
-        for token in TOKENS_TO_BUY.iter() {
-            if token.liq_will_be_added_via_pcs {
-                PCS_LIQ = true;
-            }
-            if let Some(from) = &token.from {
-                if MIN_NONCE > from.min_nonce {
-                    MIN_NONCE = from.min_nonce;
-                }
-                if MAX_NONCE < from.max_nonce {
-                    MAX_NONCE = from.max_nonce;
-                }
-            }
+/// Called with the write lock held, so settings always match the list
+fn update_global_liq_setting(list: &mut TokensToBuy) {
+    let (mut min_nonce, mut max_nonce) = (u64::MAX, 0);
+    let (mut min_size, mut max_size) = (0, 0);
+    let mut trigger_recipients = Vec::new();
 
-            if token.enable_buy_config.expected_tx_size != 0 {
-                MIN_SIZE = token.enable_buy_config.expected_tx_size - 15;
-                MAX_SIZE = token.enable_buy_config.expected_tx_size + 15;
+    for token in list.tokens.iter() {
+        for trigger in token.triggers() {
+            if !trigger_recipients.contains(&trigger.tx_to) {
+                trigger_recipients.push(trigger.tx_to);
             }
         }
+
+        // PCS liquidity txs are matched at any nonce, so they must not be skipped early
+        let (token_min_nonce, token_max_nonce) = if token.liq_will_be_added_via_pcs {
+            (0, u64::MAX)
+        } else {
+            token.nonce_window()
+        };
+        min_nonce = min_nonce.min(token_min_nonce);
+        max_nonce = max_nonce.max(token_max_nonce);
+
+        if token.enable_buy_config.expected_tx_size != 0 {
+            min_size = token.enable_buy_config.expected_tx_size - 15;
+            max_size = token.enable_buy_config.expected_tx_size + 15;
+        }
     }
+
+    MIN_NONCE.store(min_nonce, Ordering::Relaxed);
+    MAX_NONCE.store(max_nonce, Ordering::Relaxed);
+    MIN_SIZE.store(min_size, Ordering::Relaxed);
+    MAX_SIZE.store(max_size, Ordering::Relaxed);
+    list.trigger_recipients = trigger_recipients;
 }
+
 async fn read_tokens_to_buy_from_file() -> Result<Vec<Token>, std::io::Error> {
     let tokens_to_buy_file = tokio::fs::read_to_string(TOKENS_TO_BUY_FILE_PATH).await?;
     let tokens_to_buy: Vec<Token> = serde_json::from_str(&tokens_to_buy_file)?;
     Ok(tokens_to_buy)
 }
+
+#[cfg(test)]
+mod test {
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::{transaction::eip2718::TypedTransaction, TransactionRequest},
+    };
+
+    use super::*;
+
+    fn token_with_sender(sender: &str) -> Token {
+        serde_json::from_str(&format!(
+            r#"{{
+            "buyToken": "0xaE01f96CB9ce103A6A1297CC19EC0d0814Cf4c7F",
+            "liqToken": "0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c",
+            "buyBNB": 1,
+            "testPercent": 10,
+            "enableBuyConfig": {{
+                "to": "0xCF4217DB0Ea759118d5218eFdCE88B5822859D62",
+                "txHash": "0x7d315a2e"
+            }},
+            "from": {{
+                "address": "{}"
+            }}
+          }}"#,
+            sender
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn triggered_token_is_taken_once() {
+        let mut token = token_with_sender("0x47Aeb02ba0aa4b432E75f30293CB7C7BF70aafbf");
+        token.buy_token_address = TokenAddress::random();
+        let address = token.buy_token_address;
+        assert!(add_or_update_token(token.clone()));
+        // the same version again is not an update
+        assert!(!add_or_update_token(token.clone()));
+
+        let takers = (0..8)
+            .map(|_| std::thread::spawn(move || take_triggered_token(&address).is_some()))
+            .collect::<Vec<_>>();
+        let taken = takers
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .filter(|taken| *taken)
+            .count();
+
+        assert_eq!(taken, 1);
+        assert_eq!(token_state(&address), Some(TokenState::Triggered));
+        assert!(get_token_by_address(&address).is_none());
+        // triggered token is never listed again
+        assert!(!add_or_update_token(Token {
+            version: token.version + 1,
+            ..token
+        }));
+    }
+
+    #[test]
+    fn nonce_window_bounds_are_optional() {
+        let mut token = token_with_sender("0x47Aeb02ba0aa4b432E75f30293CB7C7BF70aafbf");
+        assert!(token.nonce_is_ok(0) && token.nonce_is_ok(u64::MAX));
+
+        // explicit 0..0 window, only the very first tx of the deployer
+        token.from = serde_json::from_str(r#"{"minNonce": 0, "maxNonce": 0}"#).unwrap();
+        assert!(token.nonce_is_ok(0));
+        assert!(!token.nonce_is_ok(1));
+
+        token.from = serde_json::from_str(r#"{"minNonce": 5}"#).unwrap();
+        assert!(!token.nonce_is_ok(4));
+        assert!(token.nonce_is_ok(u64::MAX));
+    }
+
+    #[test]
+    fn enable_buy_tx_sender_is_checked() {
+        let wallet = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(56u64);
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to("0xCF4217DB0Ea759118d5218eFdCE88B5822859D62"
+                .parse::<TokenAddress>()
+                .unwrap())
+            .nonce(3)
+            .gas(100_000)
+            .gas_price(3_000_000_000u64)
+            .data(vec![0x7d, 0x31, 0x5a, 0x2e])
+            .chain_id(56u64)
+            .into();
+        let signature = wallet.sign_transaction_sync(&tx).unwrap();
+        let raw_tx = tx.rlp_signed(&signature);
+
+        let token = token_with_sender(&format!("{:?}", wallet.address()));
+        assert!(tx_sender_is_ok(&token, &raw_tx));
+
+        let token = token_with_sender("0x47Aeb02ba0aa4b432E75f30293CB7C7BF70aafbf");
+        assert!(!tx_sender_is_ok(&token, &raw_tx));
+
+        let token = Token {
+            from: None,
+            ..token
+        };
+        assert!(tx_sender_is_ok(&token, &raw_tx));
+
+        // first candidate sent by its expected sender wins
+        let (a, b, c) = (
+            TokenAddress::random(),
+            TokenAddress::random(),
+            TokenAddress::random(),
+        );
+        let candidates = vec![
+            (a, Some(TokenAddress::random())),
+            (b, Some(wallet.address())),
+            (c, None),
+        ];
+        assert_eq!(first_sent_by_expected_sender(candidates, &raw_tx), Some(b));
+        assert_eq!(
+            first_sent_by_expected_sender(vec![(a, Some(wallet.address()))], &[0xc0]),
+            None
+        );
+    }
+
+    fn tx_sender_is_ok(token: &Token, raw_tx: &[u8]) -> bool {
+        first_sent_by_expected_sender(
+            vec![(token.buy_token_address, expected_sender(token))],
+            raw_tx,
+        )
+        .is_some()
+    }
+
+    #[test]
+    fn nonce_window_gates_config_triggers_only() {
+        use crate::token::trigger::{ArgCondition, ArgRule, Trigger, PCS_V2_ROUTER};
+
+        // addLiquidityETH(address token, ...)
+        let add_liquidity = |token: &TokenAddress| {
+            let mut data = vec![0xf3, 0x05, 0xd7, 0x19];
+            data.extend([0u8; 12]);
+            data.extend(token.as_bytes());
+            data
+        };
+        let mut token = token_with_sender("0x47Aeb02ba0aa4b432E75f30293CB7C7BF70aafbf");
+        token.from = serde_json::from_str(r#"{"minNonce": 0, "maxNonce": 0}"#).unwrap();
+
+        // liquidity added via PCS outside the window
+        let mut pcs_token = token.clone();
+        pcs_token.buy_token_address = TokenAddress::random();
+        pcs_token.liq_will_be_added_via_pcs = true;
+        assert!(add_or_update_token(pcs_token.clone()));
+        assert!(tx_nonce_is_ok(7));
+        let data = add_liquidity(&pcs_token.buy_token_address);
+        assert_eq!(
+            get_triggered_token(&PCS_V2_ROUTER, 7, &data, &[]).map(|t| t.buy_token_address),
+            Some(pcs_token.buy_token_address)
+        );
+
+        // the same call as a trigger from config is gated by the window
+        let mut config_token = token;
+        config_token.buy_token_address = TokenAddress::random();
+        config_token.enable_buy_config.triggers = vec![Trigger::new(
+            *PCS_V2_ROUTER,
+            [0xf3, 0x05, 0xd7, 0x19],
+            vec![ArgRule::new(0, ArgCondition::Token)],
+        )];
+        assert!(add_or_update_token(config_token.clone()));
+        let data = add_liquidity(&config_token.buy_token_address);
+        assert!(get_triggered_token(&PCS_V2_ROUTER, 7, &data, &[]).is_none());
+        assert!(get_triggered_token(&PCS_V2_ROUTER, 0, &data, &[]).is_some());
+    }
+}
@@ -0,0 +1,379 @@
+use ethers::types::{Address, Bytes, U256};
+use serde::{Deserialize, Serialize};
+use static_init::dynamic;
+
+use super::{
+    token::{TokenAddress, TxSignatureHash},
+    v3::{MintParams, PoolKey, CREATE_POOL, MINT, PCS_V3_FACTORY},
+};
+use crate::blockchain::chain_spec;
+use crate::constants::{TX_ARG_LEN, TX_SIGNATURE_LEN};
+
+#[dynamic(lazy)]
+pub static PCS_V2_ROUTER: Address = chain_spec().routers.v2_router;
+
+#[dynamic(lazy)]
+pub static PCS_V3_LIQ_CONTRACT: Address = chain_spec().routers.v3_position_manager;
+
+/// Triggers used for tokens with `isPcs` set, so liquidity added via PCS routers is detected
+/// without any extra config.
+/// Calls batched with multicall are unwrapped before matching (see [super::multicall::unwrap_calls]),
+/// so V3 mint matches also when it's sent together with pool creation
+#[dynamic(lazy)]
+pub static PCS_TRIGGERS: Vec<Trigger> = vec![
+    // addLiquidity(address tokenA, address tokenB, ...), token can be on either side
+    Trigger::new(
+        *PCS_V2_ROUTER,
+        [0xe8, 0xe3, 0x37, 0x00],
+        vec![ArgRule::new(0, ArgCondition::Token)],
+    ),
+    Trigger::new(
+        *PCS_V2_ROUTER,
+        [0xe8, 0xe3, 0x37, 0x00],
+        vec![ArgRule::new(1, ArgCondition::Token)],
+    ),
+    // addLiquidityETH(address token, ...)
+    Trigger::new(
+        *PCS_V2_ROUTER,
+        [0xf3, 0x05, 0xd7, 0x19],
+        vec![ArgRule::new(0, ArgCondition::Token)],
+    ),
+    // mint((address token0, address token1, ...)) of token paired with liquidity token
+    Trigger::new(*PCS_V3_LIQ_CONTRACT, MINT, vec![]).with_call(CallKind::V3Mint),
+    // createPool(address tokenA, address tokenB, uint24 fee) of token paired with liquidity token
+    Trigger::new(*PCS_V3_FACTORY, CREATE_POOL, vec![]).with_call(CallKind::V3CreatePool),
+];
+
+/// Declarative rule for the tx which should trigger the buy, eg.
+/// ```json
+/// { "to": "0x10ED43C718714eb63d5aA57B78B54704E256024E", "selector": "0xf305d719",
+///   "args": [{ "arg": 0, "is": "token" }] }
+/// ```
+/// tx matches if it's sent to `to`, calls `selector` and all `args` rules hold
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trigger {
+    #[serde(rename = "to")]
+    pub tx_to: Address,
+    pub selector: TxSignatureHash,
+    #[serde(default)]
+    pub args: Vec<ArgRule>,
+    #[serde(default)]
+    pub call: CallKind,
+}
+
+/// How the whole call is checked, on top of `args` rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CallKind {
+    /// Only `args` rules are checked
+    #[default]
+    Any,
+    /// V3 position manager `mint` of the token paired with the liquidity token (in any order)
+    V3Mint,
+    /// V3 pool creation for the token paired with the liquidity token, either via factory
+    /// `createPool` (eg. [PCS_V3_FACTORY]) or position manager `createAndInitializePoolIfNecessary`
+    V3CreatePool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArgRule {
+    /// Zero based index of the argument in the calldata (after the selector)
+    #[serde(rename = "arg")]
+    pub index: usize,
+    #[serde(flatten)]
+    pub condition: ArgCondition,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "is", rename_all = "camelCase")]
+pub enum ArgCondition {
+    /// Argument is the address of the token we are buying
+    Token,
+    /// Argument is dynamic (bytes, array...) and its data contains the token address
+    ContainsToken,
+    /// Argument is dynamic and its data contains given bytes
+    Contains {
+        value: Bytes,
+    },
+    /// Argument word is equal to the value
+    Equals {
+        value: U256,
+    },
+    NonZero,
+}
+
+impl Trigger {
+    pub fn new(tx_to: Address, selector: [u8; 4], args: Vec<ArgRule>) -> Self {
+        Self {
+            tx_to,
+            selector: TxSignatureHash::from(selector),
+            args,
+            call: CallKind::Any,
+        }
+    }
+
+    pub fn with_call(self, call: CallKind) -> Self {
+        Self { call, ..self }
+    }
+
+    #[inline(always)]
+    pub fn matches(
+        &self,
+        tx_to: &Address,
+        tx_data: &[u8],
+        token: &TokenAddress,
+        liq_token: &TokenAddress,
+    ) -> bool {
+        if &self.tx_to != tx_to || !tx_data.starts_with(self.selector.as_bytes()) {
+            return false;
+        }
+
+        let call_matches = match self.call {
+            CallKind::Any => true,
+            CallKind::V3Mint => MintParams::decode(tx_data)
+                .is_some_and(|mint| mint.pool.is_pair_of(token, liq_token)),
+            CallKind::V3CreatePool => PoolKey::decode_creation(tx_data)
+                .is_some_and(|pool| pool.is_pair_of(token, liq_token)),
+        };
+
+        call_matches && self.args.iter().all(|rule| rule.matches(tx_data, token))
+    }
+}
+
+impl ArgRule {
+    pub fn new(index: usize, condition: ArgCondition) -> Self {
+        Self { index, condition }
+    }
+
+    fn matches(&self, tx_data: &[u8], token: &TokenAddress) -> bool {
+        let word = match arg_word(tx_data, self.index) {
+            Some(word) => word,
+            None => return false,
+        };
+
+        match &self.condition {
+            ArgCondition::Token => word_is_address(word, token),
+            ArgCondition::ContainsToken => {
+                dynamic_arg_data(tx_data, word).is_some_and(|data| contains(data, token.as_bytes()))
+            }
+            ArgCondition::Contains { value } => {
+                dynamic_arg_data(tx_data, word).is_some_and(|data| contains(data, value))
+            }
+            ArgCondition::Equals { value } => U256::from_big_endian(word) == *value,
+            ArgCondition::NonZero => word.iter().any(|b| *b != 0),
+        }
+    }
+}
+
+#[inline(always)]
+fn arg_word(tx_data: &[u8], index: usize) -> Option<&[u8]> {
+    let start = TX_SIGNATURE_LEN + TX_ARG_LEN * index;
+    tx_data.get(start..start + TX_ARG_LEN)
+}
+
+#[inline(always)]
+fn word_is_address(word: &[u8], address: &Address) -> bool {
+    word[..TX_ARG_LEN - 20].iter().all(|b| *b == 0)
+        && &word[TX_ARG_LEN - 20..] == address.as_bytes()
+}
+
+/// For dynamic args the word in the head is the offset of the data (from the start of args).
+/// We don't know the exact type here, so everything from the offset to the end of calldata is returned.
+fn dynamic_arg_data<'a>(tx_data: &'a [u8], word: &[u8]) -> Option<&'a [u8]> {
+    let offset = U256::from_big_endian(word);
+    if offset > U256::from(tx_data.len()) {
+        return None;
+    }
+
+    tx_data.get(TX_SIGNATURE_LEN + offset.as_usize()..)
+}
+
+fn contains(bytes: &[u8], slice: &[u8]) -> bool {
+    !slice.is_empty() && bytes.windows(slice.len()).any(|window| window == slice)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn token() -> TokenAddress {
+        TokenAddress::from_str("0xaE01f96CB9ce103A6A1297CC19EC0d0814Cf4c7F").unwrap()
+    }
+
+    fn wbnb() -> TokenAddress {
+        TokenAddress::from_str("0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c").unwrap()
+    }
+
+    fn word(bytes: &[u8]) -> Vec<u8> {
+        let mut word = vec![0u8; TX_ARG_LEN - bytes.len()];
+        word.extend_from_slice(bytes);
+        word
+    }
+
+    #[test]
+    fn parse_trigger() {
+        let json = r#"{
+            "to": "0x10ED43C718714eb63d5aA57B78B54704E256024E",
+            "selector": "0xf305d719",
+            "args": [{ "arg": 0, "is": "token" }, { "arg": 2, "is": "equals", "value": "0x5" }]
+        }"#;
+
+        let trigger: Trigger = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            trigger,
+            Trigger::new(
+                *PCS_V2_ROUTER,
+                [0xf3, 0x05, 0xd7, 0x19],
+                vec![
+                    ArgRule::new(0, ArgCondition::Token),
+                    ArgRule::new(
+                        2,
+                        ArgCondition::Equals {
+                            value: U256::from(5)
+                        }
+                    )
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn add_liquidity_eth_triggers_buy() {
+        let mut data = vec![0xf3, 0x05, 0xd7, 0x19];
+        data.extend(word(token().as_bytes()));
+        data.extend(word(&[1]));
+
+        assert!(PCS_TRIGGERS
+            .iter()
+            .any(|t| t.matches(&PCS_V2_ROUTER, &data, &token(), &wbnb())));
+        assert!(!PCS_TRIGGERS.iter().any(|t| t.matches(
+            &PCS_V3_LIQ_CONTRACT,
+            &data,
+            &token(),
+            &wbnb()
+        )));
+        assert!(!PCS_TRIGGERS.iter().any(|t| t.matches(
+            &PCS_V2_ROUTER,
+            &data,
+            &Address::zero(),
+            &wbnb()
+        )));
+    }
+
+    #[test]
+    fn dynamic_arg_contains_token() {
+        // someCall(uint256 x, address[] path)
+        let mut data = vec![0x11, 0x22, 0x33, 0x44];
+        data.extend(word(&[7]));
+        data.extend(word(&[0x40]));
+        data.extend(word(&[2]));
+        data.extend(word(Address::repeat_byte(1).as_bytes()));
+        data.extend(word(token().as_bytes()));
+
+        let trigger = |condition| {
+            Trigger::new(
+                token(),
+                [0x11, 0x22, 0x33, 0x44],
+                vec![ArgRule::new(1, condition)],
+            )
+        };
+
+        assert!(trigger(ArgCondition::ContainsToken).matches(&token(), &data, &token(), &wbnb()));
+        assert!(!trigger(ArgCondition::ContainsToken).matches(
+            &token(),
+            &data,
+            &Address::repeat_byte(2),
+            &wbnb()
+        ));
+        assert!(trigger(ArgCondition::NonZero).matches(&token(), &data, &token(), &wbnb()));
+        assert!(!trigger(ArgCondition::Token).matches(&token(), &data, &token(), &wbnb()));
+        // arg out of calldata never matches
+        let out_of_bounds = Trigger::new(
+            token(),
+            [0x11, 0x22, 0x33, 0x44],
+            vec![ArgRule::new(9, ArgCondition::NonZero)],
+        );
+        assert!(!out_of_bounds.matches(&token(), &data, &token(), &wbnb()));
+    }
+
+    #[test]
+    fn mint_inside_multicall_triggers_buy() {
+        use super::super::multicall::unwrap_calls;
+
+        // createAndInitializePoolIfNecessary(token0, token1, fee, sqrtPriceX96) + mint((token0, token1, ...))
+        let mut create_pool = vec![0x13, 0xea, 0xd5, 0x62];
+        create_pool.extend(word(token().as_bytes()));
+        create_pool.extend(word(wbnb().as_bytes()));
+        let mint = v3_mint(token(), wbnb());
+
+        let multicall = ethers::abi::encode(&[ethers::abi::Token::Array(vec![
+            ethers::abi::Token::Bytes(create_pool),
+            ethers::abi::Token::Bytes(mint),
+        ])]);
+        let data = [&[0xac, 0x96, 0x50, 0xd8], &multicall[..]].concat();
+
+        let matches = |token: &TokenAddress| {
+            unwrap_calls(&data).iter().any(|call| {
+                PCS_TRIGGERS
+                    .iter()
+                    .any(|t| t.matches(&PCS_V3_LIQ_CONTRACT, call, token, &wbnb()))
+            })
+        };
+        assert!(matches(&token()));
+        assert!(!matches(&Address::repeat_byte(2)));
+    }
+
+    fn v3_mint(token0: TokenAddress, token1: TokenAddress) -> Vec<u8> {
+        let mut mint = MINT.to_vec();
+        mint.extend(word(token0.as_bytes()));
+        mint.extend(word(token1.as_bytes()));
+        for _ in 0..9 {
+            mint.extend(word(&[1]));
+        }
+        mint
+    }
+
+    #[test]
+    fn factory_create_pool_triggers_buy() {
+        let create_pool = |token_a: TokenAddress, token_b: TokenAddress| {
+            let mut data = CREATE_POOL.to_vec();
+            data.extend(word(token_a.as_bytes()));
+            data.extend(word(token_b.as_bytes()));
+            data.extend(word(&[0x09, 0xc4]));
+            data
+        };
+        let matches = |to: &Address, data: &[u8]| {
+            PCS_TRIGGERS
+                .iter()
+                .any(|t| t.matches(to, data, &token(), &wbnb()))
+        };
+
+        assert!(matches(&PCS_V3_FACTORY, &create_pool(token(), wbnb())));
+        assert!(matches(&PCS_V3_FACTORY, &create_pool(wbnb(), token())));
+        assert!(!matches(
+            &PCS_V3_FACTORY,
+            &create_pool(token(), Address::repeat_byte(3))
+        ));
+        assert!(!matches(
+            &PCS_V3_LIQ_CONTRACT,
+            &create_pool(token(), wbnb())
+        ));
+    }
+
+    #[test]
+    fn v3_mint_matches_both_token_orderings() {
+        let matches = |data: &[u8]| {
+            PCS_TRIGGERS
+                .iter()
+                .any(|t| t.matches(&PCS_V3_LIQ_CONTRACT, data, &token(), &wbnb()))
+        };
+
+        assert!(matches(&v3_mint(token(), wbnb())));
+        assert!(matches(&v3_mint(wbnb(), token())));
+        // token paired with something else than the liquidity token
+        assert!(!matches(&v3_mint(token(), Address::repeat_byte(3))));
+        assert!(!matches(&v3_mint(wbnb(), Address::repeat_byte(3))));
+    }
+}
@@ -0,0 +1,248 @@
+use ethers::types::{Address, U256};
+use static_init::dynamic;
+
+use super::token::TokenAddress;
+use crate::blockchain::chain_spec;
+use crate::constants::{TX_ARG_LEN, TX_SIGNATURE_LEN};
+
+/// `mint((address token0, address token1, uint24 fee, int24 tickLower, int24 tickUpper,
+/// uint256 amount0Desired, uint256 amount1Desired, uint256 amount0Min, uint256 amount1Min,
+/// address recipient, uint256 deadline))` on position manager
+pub const MINT: [u8; 4] = [0x88, 0x31, 0x64, 0x56];
+/// `createAndInitializePoolIfNecessary(address token0, address token1, uint24 fee,
+/// uint160 sqrtPriceX96)` on position manager
+pub const CREATE_AND_INITIALIZE_POOL: [u8; 4] = [0x13, 0xea, 0xd5, 0x62];
+/// `createPool(address tokenA, address tokenB, uint24 fee)` on factory
+pub const CREATE_POOL: [u8; 4] = [0xa1, 0x67, 0x12, 0x95];
+
+#[dynamic(lazy)]
+pub static PCS_V3_FACTORY: Address = chain_spec().routers.v3_factory;
+
+/// V3 pool is identified by sorted token pair and fee tier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolKey {
+    pub token0: TokenAddress,
+    pub token1: TokenAddress,
+    pub fee: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MintParams {
+    pub pool: PoolKey,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub amount0_desired: U256,
+    pub amount1_desired: U256,
+    pub recipient: Address,
+}
+
+impl PoolKey {
+    /// Tokens are sorted the same way as factory does it
+    pub fn new(token_a: TokenAddress, token_b: TokenAddress, fee: u32) -> Self {
+        let (token0, token1) = if token_a < token_b {
+            (token_a, token_b)
+        } else {
+            (token_b, token_a)
+        };
+
+        Self {
+            token0,
+            token1,
+            fee,
+        }
+    }
+
+    /// Decodes pool created either via factory or via position manager
+    pub fn decode_creation(call: &[u8]) -> Option<Self> {
+        let selector = call.get(..TX_SIGNATURE_LEN)?;
+        if selector != CREATE_POOL && selector != CREATE_AND_INITIALIZE_POOL {
+            return None;
+        }
+
+        Some(Self::new(
+            read_address(call, 0)?,
+            read_address(call, 1)?,
+            read_u32(call, 2)?,
+        ))
+    }
+
+    /// Whether this is the pool of the token pair, regardless of the order
+    #[inline(always)]
+    pub fn is_pair_of(&self, token: &TokenAddress, other: &TokenAddress) -> bool {
+        (&self.token0 == token && &self.token1 == other)
+            || (&self.token0 == other && &self.token1 == token)
+    }
+}
+
+impl MintParams {
+    pub fn decode(call: &[u8]) -> Option<Self> {
+        if call.get(..TX_SIGNATURE_LEN)? != MINT {
+            return None;
+        }
+
+        // params struct is static, so it's encoded in place
+        Some(Self {
+            pool: PoolKey {
+                token0: read_address(call, 0)?,
+                token1: read_address(call, 1)?,
+                fee: read_u32(call, 2)?,
+            },
+            tick_lower: read_i32(call, 3)?,
+            tick_upper: read_i32(call, 4)?,
+            amount0_desired: U256::from_big_endian(arg_word(call, 5)?),
+            amount1_desired: U256::from_big_endian(arg_word(call, 6)?),
+            recipient: read_address(call, 9)?,
+        })
+    }
+
+    /// Desired amount of the token added to the pool
+    pub fn amount_desired(&self, token: &TokenAddress) -> Option<U256> {
+        if &self.pool.token0 == token {
+            Some(self.amount0_desired)
+        } else if &self.pool.token1 == token {
+            Some(self.amount1_desired)
+        } else {
+            None
+        }
+    }
+}
+
+#[inline(always)]
+fn arg_word(call: &[u8], index: usize) -> Option<&[u8]> {
+    let start = TX_SIGNATURE_LEN + TX_ARG_LEN * index;
+    call.get(start..start + TX_ARG_LEN)
+}
+
+/// Words with dirty high bytes aren't valid addresses, abi decoder of the contract would revert
+fn read_address(call: &[u8], index: usize) -> Option<Address> {
+    let word = arg_word(call, index)?;
+    if word[..TX_ARG_LEN - 20].iter().any(|b| *b != 0) {
+        return None;
+    }
+
+    Some(Address::from_slice(&word[TX_ARG_LEN - 20..]))
+}
+
+fn read_u32(call: &[u8], index: usize) -> Option<u32> {
+    let word = arg_word(call, index)?;
+    Some(u32::from_be_bytes(word[TX_ARG_LEN - 4..].try_into().ok()?))
+}
+
+/// int24 is sign extended to the whole word, so the last 4 bytes are valid i32
+fn read_i32(call: &[u8], index: usize) -> Option<i32> {
+    let word = arg_word(call, index)?;
+    Some(i32::from_be_bytes(word[TX_ARG_LEN - 4..].try_into().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::abi::{encode, short_signature, ParamType, Token};
+
+    use super::*;
+
+    fn mint(token0: Address, token1: Address) -> Vec<u8> {
+        let mut data = MINT.to_vec();
+        data.extend(encode(&[Token::Tuple(vec![
+            Token::Address(token0),
+            Token::Address(token1),
+            Token::Uint(U256::from(2500)),
+            Token::Int(U256::MAX - U256::from(887_199)),
+            Token::Int(U256::from(887_200)),
+            Token::Uint(U256::from(1_000)),
+            Token::Uint(U256::from(5)),
+            Token::Uint(U256::zero()),
+            Token::Uint(U256::zero()),
+            Token::Address(Address::repeat_byte(9)),
+            Token::Uint(U256::from(1_700_000_000)),
+        ])]));
+        data
+    }
+
+    #[test]
+    fn selectors() {
+        let address = ParamType::Address;
+        let fee = ParamType::Uint(24);
+        let tick = ParamType::Int(24);
+        let amount = ParamType::Uint(256);
+        assert_eq!(
+            short_signature(
+                "mint",
+                &[ParamType::Tuple(vec![
+                    address.clone(),
+                    address.clone(),
+                    fee.clone(),
+                    tick.clone(),
+                    tick,
+                    amount.clone(),
+                    amount.clone(),
+                    amount.clone(),
+                    amount.clone(),
+                    address.clone(),
+                    amount,
+                ])]
+            ),
+            MINT
+        );
+        assert_eq!(
+            short_signature(
+                "createAndInitializePoolIfNecessary",
+                &[
+                    address.clone(),
+                    address.clone(),
+                    fee.clone(),
+                    ParamType::Uint(160)
+                ]
+            ),
+            CREATE_AND_INITIALIZE_POOL
+        );
+        assert_eq!(
+            short_signature("createPool", &[address.clone(), address, fee]),
+            CREATE_POOL
+        );
+    }
+
+    #[test]
+    fn decode_mint() {
+        let (token, wbnb) = (Address::repeat_byte(1), Address::repeat_byte(2));
+        let params = MintParams::decode(&mint(token, wbnb)).unwrap();
+
+        assert_eq!(params.pool, PoolKey::new(wbnb, token, 2500));
+        assert_eq!(params.tick_lower, -887_200);
+        assert_eq!(params.tick_upper, 887_200);
+        assert_eq!(params.amount_desired(&token), Some(U256::from(1_000)));
+        assert_eq!(params.amount_desired(&wbnb), Some(U256::from(5)));
+        assert_eq!(params.recipient, Address::repeat_byte(9));
+        assert!(params.pool.is_pair_of(&token, &wbnb));
+        assert!(params.pool.is_pair_of(&wbnb, &token));
+        assert!(!params.pool.is_pair_of(&token, &Address::repeat_byte(3)));
+
+        assert_eq!(MintParams::decode(&mint(token, wbnb)[..100]), None);
+
+        // high bytes of token1 and recipient words are not zero
+        for offset in [
+            TX_SIGNATURE_LEN + TX_ARG_LEN,
+            TX_SIGNATURE_LEN + TX_ARG_LEN * 9,
+        ] {
+            let mut dirty = mint(token, wbnb);
+            dirty[offset] = 1;
+            assert_eq!(MintParams::decode(&dirty), None);
+        }
+    }
+
+    #[test]
+    fn decode_factory_pool_creation() {
+        let (token, wbnb) = (Address::repeat_byte(2), Address::repeat_byte(1));
+        let mut data = CREATE_POOL.to_vec();
+        data.extend(encode(&[
+            Token::Address(token),
+            Token::Address(wbnb),
+            Token::Uint(U256::from(500)),
+        ]));
+
+        let pool = PoolKey::decode_creation(&data).unwrap();
+        assert_eq!(pool.token0, wbnb);
+        assert_eq!(pool.token1, token);
+        assert_eq!(pool.fee, 500);
+        assert_eq!(PoolKey::decode_creation(&mint(token, wbnb)), None);
+    }
+}
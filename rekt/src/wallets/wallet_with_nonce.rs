@@ -9,9 +9,8 @@ use ethers::{
 };
 
 use crate::{
-    contracts::caesar_bot::{
-        encode_buy_method, encode_prep_method, encode_sell_method, CAESAR_BOT_ADDRESS,
-    },
+    blockchain::chain_spec,
+    contracts::caesar_bot::{encode_buy_method, encode_prep_method, encode_sell_method},
     public_nodes::nodes::get_nonce,
     token::token::Token,
 };
@@ -108,13 +107,13 @@ impl WalletWithNonce {
         let tx = TransactionRequest {
             from: Some(self.address()),
             to: Some(ethers::types::NameOrAddress::Address(
-                Address::from_str(CAESAR_BOT_ADDRESS).expect("Invalid bot address"),
+                chain_spec().caesar_bot,
             )),
             gas: Some(U256::from(DEFAULT_MAX_GAS_LIMIT)),
             gas_price: Some(gas_price),
             data: Some(data),
             nonce: self.nonce,
-            chain_id: Some(ethers::types::U64::from(56)),
+            chain_id: Some(ethers::types::U64::from(chain_spec().chain)),
             ..TransactionRequest::default()
         };
 
@@ -133,7 +132,7 @@ impl WalletWithNonce {
             gas_price: Some(gas_price),
             data: None,
             nonce: self.nonce,
-            chain_id: Some(ethers::types::U64::from(56)),
+            chain_id: Some(ethers::types::U64::from(chain_spec().chain)),
             ..TransactionRequest::default()
         };
 